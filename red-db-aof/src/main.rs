@@ -0,0 +1,294 @@
+use std::{collections::BTreeMap, path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use red_db_core::{
+    aof::{AofReader, AofRecord, RECORD_HEADER_SIZE},
+    proto::{Command, SpaceConfig},
+};
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt, BufReader},
+};
+
+/// Inspects, verifies and repairs red-db append-only files offline.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Print every record with its offset
+    Dump { path: PathBuf },
+    /// Report undecodable and truncated records
+    Verify { path: PathBuf },
+    /// Copy every record before the first bad one into a new file
+    Repair { path: PathBuf, output: PathBuf },
+    /// Count records and bytes per command type and per space
+    Stats { path: PathBuf },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let result = match Args::parse().action {
+        Action::Dump { path } => dump(path).await,
+        Action::Verify { path } => verify(path).await,
+        Action::Repair { path, output } => repair(path, output).await,
+        Action::Stats { path } => stats(path).await,
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn open(path: &PathBuf) -> io::Result<AofReader<BufReader<fs::File>>> {
+    let file = fs::File::open(path)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+
+    Ok(AofReader::new(BufReader::new(file)))
+}
+
+/// Each action returns `Ok(false)` when the file has problems, for the exit code.
+async fn dump(path: PathBuf) -> io::Result<bool> {
+    let mut reader = open(&path).await?;
+    let mut healthy = true;
+
+    println!("{:<10}  {:>8}  record", "offset", "bytes");
+
+    while let Some(record) = reader.next_record().await? {
+        let offset = format!("{:#010x}", record.offset());
+
+        match &record {
+            AofRecord::Command { len, command, .. } => {
+                println!("{offset}  {len:>8}  {}", describe(command));
+            }
+            _ => {
+                healthy = false;
+                println!("{offset}  {:>8}  {}", "-", problem(&record));
+            }
+        }
+    }
+
+    Ok(healthy)
+}
+
+async fn verify(path: PathBuf) -> io::Result<bool> {
+    let mut reader = open(&path).await?;
+    let mut good = 0;
+    let mut problems = 0;
+
+    while let Some(record) = reader.next_record().await? {
+        match record {
+            AofRecord::Command { .. } => good += 1,
+            record => {
+                problems += 1;
+                println!("{:#010x}: {}", record.offset(), problem(&record));
+            }
+        }
+    }
+
+    if problems == 0 {
+        println!("{}: {good} records, no problems found", path.display());
+    } else {
+        println!(
+            "{}: {good} good records, {problems} problem(s); `repair` keeps the records before the first one",
+            path.display()
+        );
+    }
+
+    Ok(problems == 0)
+}
+
+async fn repair(path: PathBuf, output: PathBuf) -> io::Result<bool> {
+    let mut reader = open(&path).await?;
+    let total = fs::metadata(&path).await?.len();
+
+    let mut kept = 0;
+    let mut good_end = 0;
+    let mut first_problem = None;
+
+    while let Some(record) = reader.next_record().await? {
+        match record {
+            AofRecord::Command { .. } => {
+                kept += 1;
+                good_end = record.end();
+            }
+            record => {
+                first_problem = Some(record);
+                break;
+            }
+        }
+    }
+
+    let mut source = fs::File::open(&path).await?.take(good_end);
+    let mut target = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&output)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", output.display())))?;
+
+    io::copy(&mut source, &mut target).await?;
+    target.sync_all().await?;
+
+    match first_problem {
+        Some(record) => println!(
+            "Kept {kept} records ({good_end} bytes); dropped {} bytes starting with {} at {:#010x}",
+            total - good_end,
+            problem(&record),
+            record.offset()
+        ),
+        None => println!("No problems found; copied {kept} records ({good_end} bytes)"),
+    }
+
+    Ok(true)
+}
+
+#[derive(Default)]
+struct Counter {
+    records: u64,
+    bytes: u64,
+}
+
+impl Counter {
+    fn add(&mut self, bytes: u64) {
+        self.records += 1;
+        self.bytes += bytes;
+    }
+}
+
+async fn stats(path: PathBuf) -> io::Result<bool> {
+    let mut reader = open(&path).await?;
+
+    let mut total = Counter::default();
+    let mut by_command: BTreeMap<String, Counter> = BTreeMap::new();
+    let mut by_space: BTreeMap<String, Counter> = BTreeMap::new();
+    let mut problems = Counter::default();
+
+    while let Some(record) = reader.next_record().await? {
+        let bytes = record.end() - record.offset();
+        total.add(bytes);
+
+        match &record {
+            AofRecord::Command { command, .. } => {
+                by_command
+                    .entry(command.name().to_string())
+                    .or_default()
+                    .add(bytes);
+
+                if let Some(space) = command_space(command) {
+                    by_space.entry(space.to_string()).or_default().add(bytes);
+                }
+            }
+            _ => problems.add(bytes),
+        }
+    }
+
+    println!(
+        "{}: {} records, {} bytes",
+        path.display(),
+        total.records,
+        total.bytes
+    );
+    print_counters("command", &by_command);
+    print_counters("space", &by_space);
+
+    if problems.records > 0 {
+        println!(
+            "\n{} bad record(s), {} bytes; run `verify` for details",
+            problems.records, problems.bytes
+        );
+    }
+
+    Ok(problems.records == 0)
+}
+
+fn print_counters(title: &str, counters: &BTreeMap<String, Counter>) {
+    let width = counters
+        .keys()
+        .map(String::len)
+        .chain([title.len()])
+        .max()
+        .unwrap_or(0);
+
+    println!("\n{title:<width$}  {:>10}  {:>12}", "records", "bytes");
+    for (name, counter) in counters {
+        println!(
+            "{name:<width$}  {:>10}  {:>12}",
+            counter.records, counter.bytes
+        );
+    }
+}
+
+fn command_space(command: &Command) -> Option<&str> {
+    match command {
+        Command::Get { space, .. }
+        | Command::Set { space, .. }
+        | Command::Delete { space, .. }
+        | Command::ListKeys { space }
+        | Command::DeleteSpace { space }
+        | Command::CreateSpace { space }
+        | Command::CreateSpaceWithConfig { space, .. }
+        | Command::AlterSpace { space, .. }
+        | Command::IsSpaceExists { space } => Some(space),
+        _ => None,
+    }
+}
+
+fn describe(command: &Command) -> String {
+    match command {
+        Command::Set { space, key, value } => {
+            format!("Set {space}/{key} = {}", preview(value))
+        }
+        Command::Delete { space, key } => format!("Delete {space}/{key}"),
+        Command::CreateSpace { space } => format!("CreateSpace {space}"),
+        Command::CreateSpaceWithConfig { space, config } if *config == SpaceConfig::default() => {
+            format!("CreateSpace {space}")
+        }
+        Command::CreateSpaceWithConfig { space, config } => {
+            format!("CreateSpace {space} {config:?}")
+        }
+        Command::AlterSpace { space, config } => format!("AlterSpace {space} {config:?}"),
+        Command::DeleteSpace { space } => format!("DeleteSpace {space}"),
+        Command::Auth { username, .. } => format!("Auth {username}"),
+        command => format!("{command:?}"),
+    }
+}
+
+fn preview(value: &[u8]) -> String {
+    const MAX_PREVIEW: usize = 40;
+
+    match std::str::from_utf8(value) {
+        Ok(text) if text.chars().count() <= MAX_PREVIEW => format!("{text:?}"),
+        Ok(text) => format!(
+            "{:?}... ({} bytes)",
+            text.chars().take(MAX_PREVIEW).collect::<String>(),
+            value.len()
+        ),
+        Err(_) => format!("<{} bytes of binary data>", value.len()),
+    }
+}
+
+fn problem(record: &AofRecord) -> String {
+    match record {
+        AofRecord::Command { .. } => "ok".to_string(),
+        AofRecord::Undecodable { len, error, .. } => {
+            format!("undecodable record of {len} bytes: {error}")
+        }
+        AofRecord::TruncatedHeader { available, .. } => {
+            format!("truncated length header ({available} of {RECORD_HEADER_SIZE} bytes)")
+        }
+        AofRecord::TruncatedPayload { len, available, .. } => {
+            format!("truncated record ({available} of {len} bytes)")
+        }
+    }
+}
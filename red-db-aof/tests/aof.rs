@@ -0,0 +1,154 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command as Process, Output},
+};
+
+use red_db_core::{db::Db, proto::Command};
+use tempfile::tempdir;
+
+fn aof(args: &[&Path]) -> Output {
+    Process::new(env!("CARGO_BIN_EXE_red-db-aof"))
+        .args(args)
+        .output()
+        .expect("Failed to run red-db-aof")
+}
+
+fn run(action: &str, paths: &[&Path]) -> (bool, String) {
+    let mut args = vec![Path::new(action)];
+    args.extend_from_slice(paths);
+
+    let output = aof(&args);
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+async fn write_aof(path: &Path) {
+    let db = Db::new(path.to_path_buf()).await;
+    db.execute(Command::CreateSpace {
+        space: "users".to_string(),
+    })
+    .await;
+    db.execute(Command::Set {
+        space: "users".to_string(),
+        key: "user:1".to_string(),
+        value: b"Alice".to_vec(),
+    })
+    .await;
+    db.execute(Command::Set {
+        space: "users".to_string(),
+        key: "user:2".to_string(),
+        value: vec![0xff; 64],
+    })
+    .await;
+    db.execute(Command::Delete {
+        space: "users".to_string(),
+        key: "user:1".to_string(),
+    })
+    .await;
+    db.shutdown().await.unwrap();
+}
+
+fn corrupt(path: &Path) {
+    let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+    // An undecodable record followed by a record cut off mid-payload.
+    file.write_all(&4u32.to_le_bytes()).unwrap();
+    file.write_all(&[0xff; 4]).unwrap();
+    file.write_all(&100u32.to_le_bytes()).unwrap();
+    file.write_all(b"abc").unwrap();
+}
+
+#[tokio::test]
+async fn test_dump_and_stats() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("aof.rdb");
+    write_aof(&path).await;
+
+    let (ok, dump) = run("dump", &[&path]);
+    assert!(ok);
+    assert!(dump.contains("0x00000000"));
+    assert!(dump.contains("CreateSpace users"));
+    assert!(dump.contains(r#"Set users/user:1 = "Alice""#));
+    assert!(dump.contains("Set users/user:2 = <64 bytes of binary data>"));
+    assert!(dump.contains("Delete users/user:1"));
+
+    let (ok, stats) = run("stats", &[&path]);
+    assert!(ok);
+    assert!(stats.contains(": 4 records"));
+    assert!(
+        stats
+            .lines()
+            .any(|line| line.starts_with("set ") && line.split_whitespace().nth(1) == Some("2"))
+    );
+    assert!(
+        stats
+            .lines()
+            .any(|line| line.starts_with("users") && line.split_whitespace().nth(1) == Some("4"))
+    );
+}
+
+#[tokio::test]
+async fn test_verify_and_repair() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("aof.rdb");
+    write_aof(&path).await;
+    let good_len = fs::metadata(&path).unwrap().len();
+
+    let (ok, report) = run("verify", &[&path]);
+    assert!(ok);
+    assert!(report.contains("4 records, no problems found"));
+
+    corrupt(&path);
+
+    let (ok, report) = run("verify", &[&path]);
+    assert!(!ok);
+    assert!(report.contains(&format!("{good_len:#010x}: undecodable record of 4 bytes")));
+    assert!(report.contains("truncated record (3 of 100 bytes)"));
+    assert!(report.contains("4 good records, 2 problem(s)"));
+
+    let repaired = dir.path().join("repaired.rdb");
+    let (ok, report) = run("repair", &[&path, &repaired]);
+    assert!(ok);
+    assert!(report.contains(&format!("Kept 4 records ({good_len} bytes)")));
+    assert_eq!(fs::metadata(&repaired).unwrap().len(), good_len);
+
+    let (ok, _) = run("verify", &[&repaired]);
+    assert!(ok);
+
+    // The repaired file must restore into a working database.
+    let db = Db::new(repaired.clone()).await;
+    let response = db
+        .execute(Command::Get {
+            space: "users".to_string(),
+            key: "user:2".to_string(),
+        })
+        .await;
+    assert!(matches!(
+        response,
+        red_db_core::proto::Response::Value(Some(value)) if value == vec![0xff; 64]
+    ));
+    db.shutdown().await.unwrap();
+
+    // Repair never overwrites an existing file.
+    let (ok, _) = run("repair", &[&path, &repaired]);
+    assert!(!ok);
+}
+
+#[tokio::test]
+async fn test_verify_garbage_length() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let path = dir.path().join("aof.rdb");
+    write_aof(&path).await;
+
+    // A header claiming a 4 GiB record must not be allocated up front.
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&u32::MAX.to_le_bytes()).unwrap();
+    file.write_all(b"abc").unwrap();
+
+    let (ok, report) = run("verify", &[&path]);
+    assert!(!ok);
+    assert!(report.contains(&format!("truncated record (3 of {} bytes)", u32::MAX)));
+}
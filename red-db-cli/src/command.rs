@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use red_db_core::proto::{Command, ImportMode, ImportOptions, SpaceConfig, info::InfoSection};
+
+use crate::transfer::FileFormat;
+
+/// A parsed CLI command. `Ping` is separate because the client measures its latency;
+/// `Export` and `Import` because they read and write local files.
+pub enum CliCommand {
+    Server(Command),
+    Ping,
+    Export {
+        space: Option<String>,
+        output: Option<PathBuf>,
+        format: FileFormat,
+    },
+    Import {
+        path: PathBuf,
+        format: FileFormat,
+        options: ImportOptions,
+    },
+    Help,
+    Quit,
+}
+
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("spaces", "List all spaces"),
+    (
+        "create <space> [option=value...]",
+        "Create a space, optionally with limits",
+    ),
+    (
+        "alter <space> [option=value...]",
+        "Replace the limits of a space",
+    ),
+    ("drop <space>", "Delete a space and all its keys"),
+    ("exists <space>", "Check whether a space exists"),
+    ("describe <space>", "Show the size and limits of a space"),
+    ("keys <space>", "List the keys in a space"),
+    ("get <space> <key>", "Print the value of a key"),
+    ("set <space> <key> <value>", "Set a key to a UTF-8 value"),
+    ("del <space> <key>", "Delete a key"),
+    (
+        "export [space] [--output <file>] [--format jsonl|csv]",
+        "Export a space, or all spaces, as JSON Lines or CSV",
+    ),
+    (
+        "import <file> [--format jsonl|csv] [--skip-existing] [--dry-run]",
+        "Load entries exported with 'export'",
+    ),
+    (
+        "info [server|clients|stats|persistence|spaces]",
+        "Show server statistics",
+    ),
+    (
+        "backup <path>",
+        "Write a backup archive to <path> in the server's backup directory",
+    ),
+    ("ping", "Check the connection and print the round-trip time"),
+    ("help", "Show this help"),
+    ("quit", "Leave the shell"),
+];
+
+/// Commands whose first argument is a space name.
+pub const SPACE_COMMANDS: &[&str] = &[
+    "create", "alter", "drop", "exists", "describe", "keys", "get", "set", "del", "export",
+];
+
+/// Commands whose second argument is a key.
+pub const KEY_COMMANDS: &[&str] = &["get", "set", "del"];
+
+pub fn parse(args: &[String]) -> Result<CliCommand, String> {
+    let Some((name, args)) = args.split_first() else {
+        return Err("no command given".to_string());
+    };
+
+    let command = match (name.to_lowercase().as_str(), args) {
+        ("spaces", []) => CliCommand::Server(Command::ListSpaces),
+        ("create", [space]) => CliCommand::Server(Command::CreateSpace {
+            space: space.clone(),
+        }),
+        ("create", [space, options @ ..]) => CliCommand::Server(Command::CreateSpaceWithConfig {
+            space: space.clone(),
+            config: parse_space_config(options).ok_or_else(|| usage("create"))?,
+        }),
+        ("alter", [space, options @ ..]) => CliCommand::Server(Command::AlterSpace {
+            space: space.clone(),
+            config: parse_space_config(options).ok_or_else(|| usage("alter"))?,
+        }),
+        ("drop", [space]) => CliCommand::Server(Command::DeleteSpace {
+            space: space.clone(),
+        }),
+        ("exists", [space]) => CliCommand::Server(Command::IsSpaceExists {
+            space: space.clone(),
+        }),
+        ("describe", [space]) => CliCommand::Server(Command::SpaceInfo {
+            space: space.clone(),
+        }),
+        ("info", []) => CliCommand::Server(Command::Info { section: None }),
+        ("info", [section]) => CliCommand::Server(Command::Info {
+            section: Some(parse_info_section(section).ok_or_else(|| usage("info"))?),
+        }),
+        ("keys", [space]) => CliCommand::Server(Command::ListKeys {
+            space: space.clone(),
+        }),
+        ("get", [space, key]) => CliCommand::Server(Command::Get {
+            space: space.clone(),
+            key: key.clone(),
+        }),
+        ("set", [space, key, value]) => CliCommand::Server(Command::Set {
+            space: space.clone(),
+            key: key.clone(),
+            value: value.as_bytes().to_vec(),
+        }),
+        ("del", [space, key]) => CliCommand::Server(Command::Delete {
+            space: space.clone(),
+            key: key.clone(),
+        }),
+        ("export", args) => parse_export(args).ok_or_else(|| usage("export"))?,
+        ("import", args) => parse_import(args).ok_or_else(|| usage("import"))?,
+        ("backup", [dest_path]) => CliCommand::Server(Command::Backup {
+            dest_path: dest_path.clone(),
+        }),
+        ("ping", []) => CliCommand::Ping,
+        ("help", _) => CliCommand::Help,
+        ("quit" | "exit", []) => CliCommand::Quit,
+        (name, _) => return Err(usage(name)),
+    };
+
+    Ok(command)
+}
+
+fn usage(name: &str) -> String {
+    match COMMANDS
+        .iter()
+        .find(|(usage, _)| usage.split_whitespace().next() == Some(name))
+    {
+        Some((usage, _)) => format!("usage: {usage}"),
+        None => format!("unknown command '{name}', try 'help'"),
+    }
+}
+
+fn parse_export(args: &[String]) -> Option<CliCommand> {
+    let mut space = None;
+    let mut output = None;
+    let mut format = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(PathBuf::from(args.next()?)),
+            "--format" => format = Some(FileFormat::parse(args.next()?).ok()?),
+            flag if flag.starts_with("--") => return None,
+            _ if space.is_none() => space = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+
+    let format = format
+        .or_else(|| output.as_deref().map(FileFormat::from_path))
+        .unwrap_or(FileFormat::JsonLines);
+
+    Some(CliCommand::Export {
+        space,
+        output,
+        format,
+    })
+}
+
+fn parse_import(args: &[String]) -> Option<CliCommand> {
+    let mut path = None;
+    let mut format = None;
+    let mut options = ImportOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Some(FileFormat::parse(args.next()?).ok()?),
+            "--skip-existing" => options.mode = ImportMode::SkipExisting,
+            "--dry-run" => options.dry_run = true,
+            flag if flag.starts_with("--") => return None,
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return None,
+        }
+    }
+
+    let path = path?;
+    let format = format.unwrap_or_else(|| FileFormat::from_path(&path));
+
+    Some(CliCommand::Import {
+        path,
+        format,
+        options,
+    })
+}
+
+/// Space options are given as `max-value-size=<bytes>`, `max-key-length=<bytes>`,
+/// `max-keys=<n>`, `max-bytes=<bytes>`, `default-ttl-ms=<ms>` and `read-only=<bool>`.
+fn parse_space_config(args: &[String]) -> Option<SpaceConfig> {
+    let mut config = SpaceConfig::default();
+
+    for arg in args {
+        let (name, value) = arg.split_once('=')?;
+        match name {
+            "max-value-size" => config.max_value_size = Some(value.parse().ok()?),
+            "max-key-length" => config.max_key_length = Some(value.parse().ok()?),
+            "max-keys" => config.max_keys = Some(value.parse().ok()?),
+            "max-bytes" => config.max_bytes = Some(value.parse().ok()?),
+            "default-ttl-ms" => config.default_ttl_ms = Some(value.parse().ok()?),
+            "read-only" => config.read_only = value.parse().ok()?,
+            _ => return None,
+        }
+    }
+
+    Some(config)
+}
+
+fn parse_info_section(name: &str) -> Option<InfoSection> {
+    match name.to_lowercase().as_str() {
+        "server" => Some(InfoSection::Server),
+        "clients" => Some(InfoSection::Clients),
+        "stats" => Some(InfoSection::Stats),
+        "persistence" => Some(InfoSection::Persistence),
+        "spaces" => Some(InfoSection::Spaces),
+        _ => None,
+    }
+}
+
+pub fn help() -> String {
+    let width = COMMANDS
+        .iter()
+        .map(|(usage, _)| usage.len())
+        .max()
+        .unwrap_or(0);
+
+    COMMANDS
+        .iter()
+        .map(|(usage, description)| format!("  {usage:width$}  {description}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
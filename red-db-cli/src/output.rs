@@ -0,0 +1,241 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use red_db_core::proto::{
+    Response, SpaceConfig,
+    info::{Info, SpaceInfo},
+};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Values are written byte for byte, without a trailing newline.
+    Raw,
+    /// Values are printed as lowercase hex.
+    Hex,
+    /// Values are printed as text; invalid UTF-8 is replaced.
+    Utf8,
+    /// Every result is a JSON document. Values that hold JSON are embedded as-is.
+    Json,
+}
+
+/// Prints a successful response to stdout. Error responses are returned as `Err` so the
+/// caller can report them and set the exit code.
+pub fn print(response: Response, format: Format) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+
+    let result = match response {
+        Response::Error(e) => return Err(e.to_string()),
+        Response::Value(Some(value)) if format == Format::Raw => stdout.write_all(&value),
+        response if format == Format::Json => {
+            writeln!(stdout, "{}", to_json(response))
+        }
+        Response::Ok => writeln!(stdout, "OK"),
+        Response::Value(None) => writeln!(stdout, "(nil)"),
+        Response::Value(Some(value)) if format == Format::Hex => {
+            writeln!(stdout, "{}", hex(&value))
+        }
+        Response::Value(Some(value)) => {
+            writeln!(stdout, "{}", String::from_utf8_lossy(&value))
+        }
+        Response::Bool(value) => writeln!(stdout, "{value}"),
+        Response::Keys(items) | Response::Spaces(items) if items.is_empty() => {
+            writeln!(stdout, "(empty)")
+        }
+        Response::Keys(items) | Response::Spaces(items) => {
+            items.iter().try_for_each(|item| writeln!(stdout, "{item}"))
+        }
+        Response::Pong(_) => writeln!(stdout, "PONG"),
+        Response::ExportPage(page) => writeln!(stdout, "{} entries", page.entries.len()),
+        Response::Imported(summary) => writeln!(
+            stdout,
+            "{} written, {} skipped",
+            summary.written, summary.skipped
+        ),
+        Response::Backup(metadata) => writeln!(
+            stdout,
+            "Backup written: {} spaces, {} keys",
+            metadata.spaces.len(),
+            metadata.keys
+        ),
+        Response::Info(info) => write!(stdout, "{}", info_text(&info)),
+        Response::SpaceInfo(info) => {
+            let usage = [
+                ("keys", info.usage.keys.to_string()),
+                ("bytes", info.usage.bytes.to_string()),
+            ];
+            usage
+                .into_iter()
+                .chain(config_fields(&info.config))
+                .try_for_each(|(name, value)| writeln!(stdout, "{name}: {value}"))
+        }
+    };
+
+    result
+        .and_then(|_| stdout.flush())
+        .map_err(|e| format!("failed to write output: {e}"))
+}
+
+fn to_json(response: Response) -> Value {
+    match response {
+        Response::Ok => Value::from("OK"),
+        Response::Value(None) => Value::Null,
+        Response::Value(Some(value)) => {
+            serde_json::from_slice(&value).unwrap_or_else(|_| match String::from_utf8(value) {
+                Ok(text) => Value::from(text),
+                Err(e) => Value::from(e.into_bytes()),
+            })
+        }
+        Response::Keys(items) | Response::Spaces(items) => Value::from(items),
+        Response::Bool(value) => Value::from(value),
+        Response::Pong(_) => Value::from("PONG"),
+        Response::ExportPage(page) => Value::from(page.entries.len()),
+        Response::Imported(summary) => serde_json::json!({
+            "written": summary.written,
+            "skipped": summary.skipped,
+            "spaces_created": summary.spaces_created,
+        }),
+        Response::Backup(metadata) => serde_json::json!({
+            "server_version": metadata.server_version,
+            "created_at": metadata.created_at,
+            "spaces": metadata.spaces,
+            "keys": metadata.keys,
+        }),
+        Response::Info(info) => info_json(&info),
+        Response::SpaceInfo(info) => space_json(&info),
+        Response::Error(e) => serde_json::json!({ "error": e.to_string() }),
+    }
+}
+
+/// `# Section` headers followed by `name: value` lines, like Redis `INFO`.
+fn info_text(info: &Info) -> String {
+    let mut text = String::new();
+    let mut section = |title: &str, fields: Vec<(String, String)>| {
+        text.push_str(&format!("# {title}\n"));
+        for (name, value) in fields {
+            text.push_str(&format!("{name}: {value}\n"));
+        }
+    };
+
+    if let Some(server) = &info.server {
+        section(
+            "Server",
+            vec![
+                ("version".to_string(), server.version.clone()),
+                ("uptime_secs".to_string(), server.uptime_secs.to_string()),
+            ],
+        );
+    }
+    if let Some(clients) = &info.clients {
+        section(
+            "Clients",
+            vec![("connected".to_string(), clients.connected.to_string())],
+        );
+    }
+    if let Some(stats) = &info.stats {
+        let mut fields = vec![(
+            "total_commands".to_string(),
+            stats.total_commands.to_string(),
+        )];
+        fields.extend(
+            stats
+                .commands
+                .iter()
+                .map(|(name, count)| (name.clone(), count.to_string())),
+        );
+        section("Stats", fields);
+    }
+    if let Some(persistence) = &info.persistence {
+        let mut fields = vec![
+            ("engine".to_string(), persistence.engine.clone()),
+            ("aof_size".to_string(), persistence.aof_size.to_string()),
+            (
+                "last_write_error".to_string(),
+                persistence
+                    .last_write_error
+                    .clone()
+                    .unwrap_or_else(|| "(none)".to_string()),
+            ),
+        ];
+        if let Some(used_memory) = persistence.used_memory {
+            fields.push(("used_memory".to_string(), used_memory.to_string()));
+        }
+        section("Persistence", fields);
+    }
+    if let Some(spaces) = &info.spaces {
+        section(
+            "Spaces",
+            spaces
+                .iter()
+                .map(|space| {
+                    let usage = format!("keys={} bytes={}", space.usage.keys, space.usage.bytes);
+                    (space.name.clone(), usage)
+                })
+                .collect(),
+        );
+    }
+
+    text
+}
+
+/// The limits a space sets, leaving out those that are unset.
+fn config_fields(config: &SpaceConfig) -> Vec<(&'static str, String)> {
+    [
+        ("max_value_size", config.max_value_size),
+        ("max_key_length", config.max_key_length),
+        ("max_keys", config.max_keys),
+        ("max_bytes", config.max_bytes),
+        ("default_ttl_ms", config.default_ttl_ms),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value?.to_string())))
+    .chain(config.read_only.then(|| ("read_only", "true".to_string())))
+    .collect()
+}
+
+fn info_json(info: &Info) -> Value {
+    serde_json::json!({
+        "server": info.server.as_ref().map(|server| serde_json::json!({
+            "version": server.version,
+            "uptime_secs": server.uptime_secs,
+        })),
+        "clients": info.clients.as_ref().map(|clients| serde_json::json!({
+            "connected": clients.connected,
+        })),
+        "stats": info.stats.as_ref().map(|stats| serde_json::json!({
+            "total_commands": stats.total_commands,
+            "commands": stats
+                .commands
+                .iter()
+                .map(|(name, count)| (name.clone(), Value::from(*count)))
+                .collect::<serde_json::Map<_, _>>(),
+        })),
+        "persistence": info.persistence.as_ref().map(|persistence| serde_json::json!({
+            "engine": persistence.engine,
+            "aof_size": persistence.aof_size,
+            "last_write_error": persistence.last_write_error,
+            "used_memory": persistence.used_memory,
+        })),
+        "spaces": info.spaces.as_ref().map(|spaces| spaces.iter().map(space_json).collect::<Vec<_>>()),
+    })
+}
+
+fn space_json(info: &SpaceInfo) -> Value {
+    serde_json::json!({
+        "name": info.name,
+        "keys": info.usage.keys,
+        "bytes": info.usage.bytes,
+        "config": {
+            "max_value_size": info.config.max_value_size,
+            "max_key_length": info.config.max_key_length,
+            "max_keys": info.config.max_keys,
+            "max_bytes": info.config.max_bytes,
+            "default_ttl_ms": info.config.default_ttl_ms,
+            "read_only": info.config.read_only,
+        },
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
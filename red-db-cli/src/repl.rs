@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use red_db_client::blocking::Client;
+use red_db_core::proto::{Command, Response};
+use rustyline::{
+    Context, Editor, Helper,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+};
+
+use crate::{
+    command::{COMMANDS, KEY_COMMANDS, SPACE_COMMANDS},
+    output::Format,
+    run,
+};
+
+struct CliHelper {
+    client: Client,
+}
+
+impl CliHelper {
+    fn list(&self, command: Command) -> Vec<String> {
+        match self.client.execute(command) {
+            Ok(Response::Keys(items) | Response::Spaces(items)) => items,
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Completer for CliHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let prefix = &line[start..];
+        let words: Vec<&str> = line[..start].split_whitespace().collect();
+
+        let candidates = match words.as_slice() {
+            [] => COMMANDS
+                .iter()
+                .filter_map(|(usage, _)| usage.split_whitespace().next())
+                .map(str::to_string)
+                .collect(),
+            [command] if SPACE_COMMANDS.contains(command) => self.list(Command::ListSpaces),
+            [command, space] if KEY_COMMANDS.contains(command) => self.list(Command::ListKeys {
+                space: space.to_string(),
+            }),
+            _ => Vec::new(),
+        };
+
+        Ok((
+            start,
+            candidates
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(prefix))
+                .map(|candidate| Pair {
+                    display: candidate.clone(),
+                    replacement: candidate,
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl Hinter for CliHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CliHelper {}
+
+impl Validator for CliHelper {}
+
+impl Helper for CliHelper {}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".red_db_cli_history"))
+}
+
+pub fn run_repl(client: &Client, format: Format, prompt: &str) -> Result<(), ReadlineError> {
+    let mut editor: Editor<CliHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(CliHelper {
+        client: client.clone(),
+    }));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    println!("Connected to {prompt}. Type 'help' for a list of commands.");
+
+    loop {
+        let line = match editor.readline(&format!("{prompt}> ")) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str())?;
+
+        let Some(args) = shlex::split(&line) else {
+            eprintln!("(error) unbalanced quotes");
+            continue;
+        };
+
+        match run(client, &args, format) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("(error) {e}"),
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+
+    Ok(())
+}
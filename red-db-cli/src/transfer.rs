@@ -0,0 +1,220 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use red_db_client::blocking::Client;
+use red_db_core::proto::{Entry, ImportOptions};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    JsonLines,
+    Csv,
+}
+
+impl FileFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(FileFormat::JsonLines),
+            "csv" => Ok(FileFormat::Csv),
+            _ => Err(format!("unknown format '{name}', expected jsonl or csv")),
+        }
+    }
+
+    /// `.csv` files are CSV, everything else is JSON Lines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => FileFormat::Csv,
+            _ => FileFormat::JsonLines,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+/// One line of JSON Lines or one CSV row. Values that aren't valid UTF-8 are base64.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    space: String,
+    key: String,
+    #[serde(default)]
+    encoding: Encoding,
+    value: String,
+}
+
+impl From<Entry> for Record {
+    fn from(entry: Entry) -> Self {
+        let (encoding, value) = match String::from_utf8(entry.value) {
+            Ok(value) => (Encoding::Utf8, value),
+            Err(e) => (Encoding::Base64, STANDARD.encode(e.as_bytes())),
+        };
+
+        Record {
+            space: entry.space,
+            key: entry.key,
+            encoding,
+            value,
+        }
+    }
+}
+
+impl TryFrom<Record> for Entry {
+    type Error = String;
+
+    fn try_from(record: Record) -> Result<Self, Self::Error> {
+        let value = match record.encoding {
+            Encoding::Utf8 => record.value.into_bytes(),
+            Encoding::Base64 => STANDARD
+                .decode(&record.value)
+                .map_err(|e| format!("invalid base64 value for key '{}': {e}", record.key))?,
+        };
+
+        Ok(Entry {
+            space: record.space,
+            key: record.key,
+            value,
+        })
+    }
+}
+
+/// Writes `space`, or every space, to `output` or stdout, a page at a time.
+pub fn export(
+    client: &Client,
+    space: Option<&str>,
+    output: Option<&Path>,
+    format: FileFormat,
+) -> Result<(), String> {
+    let sink: Box<dyn Write> = match output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).map_err(|e| {
+                format!("failed to create {}: {e}", path.display())
+            })?))
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut writer = EntryWriter::new(format, sink);
+    let mut count = 0;
+    let mut after = None;
+    loop {
+        let page = client
+            .export_page(space, after)
+            .map_err(|e| e.to_string())?;
+        count += page.entries.len();
+        writer.write(page.entries)?;
+
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    writer.finish()?;
+
+    if let Some(path) = output {
+        eprintln!("Exported {count} entries to {}", path.display());
+    }
+    Ok(())
+}
+
+pub fn import(
+    client: &Client,
+    path: &Path,
+    format: FileFormat,
+    options: ImportOptions,
+) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    let entries = read_entries(BufReader::new(file), format)?;
+    let summary = client.import(entries, options).map_err(|e| e.to_string())?;
+
+    let verb = if options.dry_run {
+        "Would write"
+    } else {
+        "Wrote"
+    };
+    println!(
+        "{verb} {} entries, skipped {}",
+        summary.written, summary.skipped
+    );
+
+    if !summary.spaces_created.is_empty() {
+        let verb = if options.dry_run {
+            "Would create"
+        } else {
+            "Created"
+        };
+        println!("{verb} spaces: {}", summary.spaces_created.join(", "));
+    }
+
+    Ok(())
+}
+
+enum EntryWriter<W: Write> {
+    JsonLines(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> EntryWriter<W> {
+    fn new(format: FileFormat, writer: W) -> Self {
+        match format {
+            FileFormat::JsonLines => EntryWriter::JsonLines(writer),
+            FileFormat::Csv => EntryWriter::Csv(Box::new(csv::Writer::from_writer(writer))),
+        }
+    }
+
+    fn write(&mut self, entries: Vec<Entry>) -> Result<(), String> {
+        let mut records = entries.into_iter().map(Record::from);
+
+        let result = match self {
+            EntryWriter::JsonLines(writer) => records
+                .try_for_each(|record| {
+                    serde_json::to_writer(&mut *writer, &record)?;
+                    writer.write_all(b"\n").map_err(serde_json::Error::io)
+                })
+                .map_err(|e| e.to_string()),
+            EntryWriter::Csv(writer) => records
+                .try_for_each(|record| writer.serialize(record))
+                .map_err(|e| e.to_string()),
+        };
+
+        result.map_err(|e| format!("failed to write export: {e}"))
+    }
+
+    fn finish(self) -> Result<(), String> {
+        let result = match self {
+            EntryWriter::JsonLines(mut writer) => writer.flush(),
+            EntryWriter::Csv(mut writer) => writer.flush(),
+        };
+
+        result.map_err(|e| format!("failed to write export: {e}"))
+    }
+}
+
+fn read_entries<R: Read>(reader: BufReader<R>, format: FileFormat) -> Result<Vec<Entry>, String> {
+    match format {
+        FileFormat::JsonLines => reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(index, line)| {
+                let line = line.map_err(|e| e.to_string())?;
+                serde_json::from_str::<Record>(&line)
+                    .map_err(|e| e.to_string())
+                    .and_then(Entry::try_from)
+                    .map_err(|e| format!("line {}: {e}", index + 1))
+            })
+            .collect(),
+        FileFormat::Csv => csv::Reader::from_reader(reader)
+            .deserialize::<Record>()
+            .map(|record| record.map_err(|e| e.to_string()).and_then(Entry::try_from))
+            .collect(),
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+use tempfile::tempdir;
+
+fn cli(db: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_red-db-cli"))
+        .arg("--file")
+        .arg(db)
+        .args(args)
+        .output()
+        .expect("Failed to run red-db-cli")
+}
+
+fn stdout(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "red-db-cli failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_one_shot_commands() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let db = dir.path().join("cli.rdb");
+
+    assert_eq!(stdout(cli(&db, &["create", "users"])), "OK\n");
+    assert_eq!(
+        stdout(cli(&db, &["set", "users", "user:1", "Alice"])),
+        "OK\n"
+    );
+    assert_eq!(
+        stdout(cli(&db, &["set", "users", "user:2", r#"{"name":"Bob"}"#])),
+        "OK\n"
+    );
+
+    assert_eq!(stdout(cli(&db, &["get", "users", "user:1"])), "Alice\n");
+    assert_eq!(
+        stdout(cli(&db, &["-o", "raw", "get", "users", "user:1"])),
+        "Alice"
+    );
+    assert_eq!(
+        stdout(cli(&db, &["-o", "hex", "get", "users", "user:1"])),
+        "416c696365\n"
+    );
+    assert_eq!(
+        stdout(cli(&db, &["-o", "json", "get", "users", "user:2"])),
+        "{\"name\":\"Bob\"}\n"
+    );
+    assert_eq!(
+        stdout(cli(&db, &["-o", "json", "get", "users", "user:3"])),
+        "null\n"
+    );
+    assert_eq!(stdout(cli(&db, &["get", "users", "user:3"])), "(nil)\n");
+
+    let mut keys: Vec<String> = stdout(cli(&db, &["keys", "users"]))
+        .lines()
+        .map(str::to_string)
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec!["user:1", "user:2"]);
+
+    assert_eq!(stdout(cli(&db, &["-o", "json", "spaces"])), "[\"users\"]\n");
+    assert!(stdout(cli(&db, &["ping"])).starts_with("PONG"));
+
+    assert_eq!(
+        stdout(cli(&db, &["describe", "users"])),
+        "keys: 2\nbytes: 31\n"
+    );
+    assert_eq!(
+        stdout(cli(
+            &db,
+            &["create", "limited", "max-keys=1", "read-only=true"]
+        )),
+        "OK\n"
+    );
+    assert_eq!(
+        stdout(cli(&db, &["describe", "limited"])),
+        "keys: 0\nbytes: 0\nmax_keys: 1\nread_only: true\n"
+    );
+    assert!(stdout(cli(&db, &["info", "stats"])).starts_with("# Stats\n"));
+}
+
+#[test]
+fn test_errors() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let db = dir.path().join("cli.rdb");
+
+    let output = cli(&db, &["get", "missing", "key"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Space 'missing' not found"));
+
+    let output = cli(&db, &["get", "missing"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: get <space> <key>"));
+}
+
+#[test]
+fn test_export_import() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let source = dir.path().join("source.rdb");
+    let jsonl = dir.path().join("seed.jsonl");
+    let csv = dir.path().join("seed.csv");
+
+    std::fs::write(
+        &jsonl,
+        concat!(
+            r#"{"space":"users","key":"user:1","value":"Alice"}"#,
+            "\n",
+            r#"{"space":"blobs","key":"blob:1","encoding":"base64","value":"/wA="}"#,
+            "\n",
+        ),
+    )
+    .unwrap();
+
+    assert_eq!(
+        stdout(cli(
+            &source,
+            &["import", jsonl.to_str().unwrap(), "--dry-run"]
+        )),
+        "Would write 2 entries, skipped 0\nWould create spaces: users, blobs\n"
+    );
+    assert_eq!(stdout(cli(&source, &["spaces"])), "(empty)\n");
+
+    stdout(cli(&source, &["import", jsonl.to_str().unwrap()]));
+    assert_eq!(
+        stdout(cli(&source, &["-o", "hex", "get", "blobs", "blob:1"])),
+        "ff00\n"
+    );
+
+    assert_eq!(
+        stdout(cli(&source, &["export", "users"])),
+        "{\"space\":\"users\",\"key\":\"user:1\",\"encoding\":\"utf8\",\"value\":\"Alice\"}\n"
+    );
+
+    stdout(cli(&source, &["export", "--output", csv.to_str().unwrap()]));
+    assert_eq!(
+        std::fs::read_to_string(&csv).unwrap(),
+        "space,key,encoding,value\nblobs,blob:1,base64,/wA=\nusers,user:1,utf8,Alice\n"
+    );
+
+    // Import the CSV into a second database, keeping a key that already exists.
+    let target = dir.path().join("target.rdb");
+    stdout(cli(&target, &["set", "users", "user:1", "Alicia"]));
+    assert_eq!(
+        stdout(cli(
+            &target,
+            &["import", csv.to_str().unwrap(), "--skip-existing"]
+        )),
+        "Wrote 1 entries, skipped 1\nCreated spaces: blobs\n"
+    );
+    assert_eq!(
+        stdout(cli(&target, &["get", "users", "user:1"])),
+        "Alicia\n"
+    );
+    assert_eq!(
+        stdout(cli(&target, &["-o", "hex", "get", "blobs", "blob:1"])),
+        "ff00\n"
+    );
+
+    let output = cli(
+        &target,
+        &["import", "--format", "xml", csv.to_str().unwrap()],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: import <file>"));
+}
+
+#[test]
+fn test_backup() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let db = dir.path().join("cli.rdb");
+    let archive = dir.path().join("cli.rdbak");
+
+    stdout(cli(&db, &["set", "users", "user:1", "Alice"]));
+    assert_eq!(
+        stdout(cli(&db, &["backup", archive.to_str().unwrap()])),
+        "Backup written: 1 spaces, 1 keys\n"
+    );
+    assert!(archive.exists());
+}
@@ -0,0 +1,218 @@
+//! A synchronous client for code that doesn't run inside an async runtime.
+//!
+//! Each `Client` drives the regular async client on a small private runtime, so it speaks
+//! the same protocol and supports the same transports, including embedded mode. Its methods
+//! must not be called from within an async context.
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use red_db_core::{
+    backup::BackupMetadata,
+    proto::{
+        Command, Entry, ExportCursor, ExportPage, ImportOptions, ImportSummary, Response,
+        SpaceConfig,
+        info::{Info, InfoSection, SpaceInfo},
+    },
+};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::runtime::Runtime;
+
+use crate::{
+    ClientBuilder,
+    codec::{Codec, JsonCodec},
+    error::ClientResult,
+};
+
+struct Inner {
+    client: crate::Client,
+    runtime: Runtime,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Embedded databases flush their AOF here; the runtime is about to stop its writer.
+        let _ = self.runtime.block_on(self.client.shutdown());
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+impl Client {
+    pub(crate) fn build(builder: &ClientBuilder) -> ClientResult<Self> {
+        // A worker thread keeps background tasks such as the AOF writer running between calls.
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("red-db-client")
+            .enable_all()
+            .build()?;
+
+        let client = runtime.block_on(builder.build())?;
+
+        Ok(Self {
+            inner: Arc::new(Inner { client, runtime }),
+        })
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.inner.runtime.block_on(future)
+    }
+
+    pub fn execute(&self, command: Command) -> ClientResult<Response> {
+        self.block_on(self.inner.client.execute(command))
+    }
+
+    pub fn ping(&self) -> ClientResult<Duration> {
+        self.block_on(self.inner.client.ping())
+    }
+
+    pub fn status(&self) -> deadpool::managed::Status {
+        self.inner.client.status()
+    }
+
+    pub fn shutdown(&self) -> ClientResult<()> {
+        self.block_on(self.inner.client.shutdown())
+    }
+
+    pub fn is_space_exists(&self, space_name: String) -> ClientResult<bool> {
+        self.block_on(self.inner.client.is_space_exists(space_name))
+    }
+
+    pub fn space(&self, space_name: String) -> ClientResult<SpaceClient> {
+        self.space_with_codec(space_name, JsonCodec)
+    }
+
+    pub fn space_with_codec<C: Codec>(
+        &self,
+        space_name: String,
+        codec: C,
+    ) -> ClientResult<SpaceClient<C>> {
+        let space = self.block_on(self.inner.client.space_with_codec(space_name, codec))?;
+
+        Ok(SpaceClient {
+            inner: self.inner.clone(),
+            space,
+        })
+    }
+
+    pub fn create_space(&self, space_name: String) -> ClientResult<()> {
+        self.block_on(self.inner.client.create_space(space_name))
+    }
+
+    pub fn create_space_with_config(
+        &self,
+        space_name: String,
+        config: SpaceConfig,
+    ) -> ClientResult<()> {
+        self.block_on(
+            self.inner
+                .client
+                .create_space_with_config(space_name, config),
+        )
+    }
+
+    pub fn alter_space(&self, space_name: String, config: SpaceConfig) -> ClientResult<()> {
+        self.block_on(self.inner.client.alter_space(space_name, config))
+    }
+
+    pub fn info(&self, section: Option<InfoSection>) -> ClientResult<Info> {
+        self.block_on(self.inner.client.info(section))
+    }
+
+    pub fn space_info(&self, space_name: String) -> ClientResult<SpaceInfo> {
+        self.block_on(self.inner.client.space_info(space_name))
+    }
+
+    pub fn export(&self, space: Option<&str>) -> ClientResult<Vec<Entry>> {
+        self.block_on(self.inner.client.export(space))
+    }
+
+    pub fn export_page(
+        &self,
+        space: Option<&str>,
+        after: Option<ExportCursor>,
+    ) -> ClientResult<ExportPage> {
+        self.block_on(self.inner.client.export_page(space, after))
+    }
+
+    pub fn import(
+        &self,
+        entries: Vec<Entry>,
+        options: ImportOptions,
+    ) -> ClientResult<ImportSummary> {
+        self.block_on(self.inner.client.import(entries, options))
+    }
+
+    pub fn backup(&self, dest_path: impl Into<String>) -> ClientResult<BackupMetadata> {
+        self.block_on(self.inner.client.backup(dest_path))
+    }
+
+    pub fn save_to(&self, path: impl Into<PathBuf>) -> ClientResult<()> {
+        self.block_on(self.inner.client.save_to(path))
+    }
+
+    pub fn delete_space(&self, space_name: String) -> ClientResult<()> {
+        self.block_on(self.inner.client.delete_space(space_name))
+    }
+}
+
+#[derive(Clone)]
+pub struct SpaceClient<C = JsonCodec> {
+    inner: Arc<Inner>,
+    space: crate::SpaceClient<C>,
+}
+
+impl<C: Codec> SpaceClient<C> {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.inner.runtime.block_on(future)
+    }
+
+    pub fn with_codec<D: Codec>(self, codec: D) -> SpaceClient<D> {
+        SpaceClient {
+            inner: self.inner,
+            space: self.space.with_codec(codec),
+        }
+    }
+
+    pub fn set_value<T: Serialize>(&self, key: &str, value: &T) -> ClientResult<()> {
+        self.block_on(self.space.set_value(key, value))
+    }
+
+    pub fn get_value<T: DeserializeOwned>(&self, key: &str) -> ClientResult<Option<T>> {
+        self.block_on(self.space.get_value(key))
+    }
+
+    pub fn set_json<T: Serialize>(&self, key: &str, value: &T) -> ClientResult<()> {
+        self.block_on(self.space.set_json(key, value))
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, key: &str) -> ClientResult<Option<T>> {
+        self.block_on(self.space.get_json(key))
+    }
+
+    pub fn set(&self, key: &str, value: Vec<u8>) -> ClientResult<()> {
+        self.block_on(self.space.set(key, value))
+    }
+
+    pub fn set_string(&self, key: &str, value: &str) -> ClientResult<()> {
+        self.block_on(self.space.set_string(key, value))
+    }
+
+    pub fn get(&self, key: &str) -> ClientResult<Option<Vec<u8>>> {
+        self.block_on(self.space.get(key))
+    }
+
+    pub fn get_string(&self, key: &str) -> ClientResult<Option<String>> {
+        self.block_on(self.space.get_string(key))
+    }
+
+    pub fn delete(&self, key: &str) -> ClientResult<()> {
+        self.block_on(self.space.delete(key))
+    }
+
+    pub fn list_keys(&self) -> ClientResult<Vec<String>> {
+        self.block_on(self.space.list_keys())
+    }
+}
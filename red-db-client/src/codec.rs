@@ -0,0 +1,78 @@
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::{ClientError, ClientResult};
+
+/// Turns typed values into the bytes stored under a key and back.
+pub trait Codec: Clone + Send + Sync + 'static {
+    fn encode<T: Serialize>(&self, value: &T) -> ClientResult<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> ClientResult<T>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> ClientResult<Vec<u8>> {
+        serde_json::to_vec(value).map_err(codec_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> ClientResult<T> {
+        serde_json::from_slice(bytes).map_err(codec_error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> ClientResult<Vec<u8>> {
+        bincode::serde::encode_to_vec(value, bincode::config::standard()).map_err(codec_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> ClientResult<T> {
+        let (value, read) = bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+            .map_err(codec_error)?;
+
+        if read != bytes.len() {
+            return Err(ClientError::Codec(format!(
+                "{} trailing bytes after value",
+                bytes.len() - read
+            )));
+        }
+
+        Ok(value)
+    }
+}
+
+/// MessagePack with struct fields encoded by name, so structs can gain fields later.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+impl Codec for MessagePackCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> ClientResult<Vec<u8>> {
+        rmp_serde::to_vec_named(value).map_err(codec_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> ClientResult<T> {
+        rmp_serde::from_slice(bytes).map_err(codec_error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn encode<T: Serialize>(&self, value: &T) -> ClientResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(codec_error)?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> ClientResult<T> {
+        ciborium::from_reader(bytes).map_err(codec_error)
+    }
+}
+
+fn codec_error<E: std::fmt::Display>(err: E) -> ClientError {
+    ClientError::Codec(err.to_string())
+}
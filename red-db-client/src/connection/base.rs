@@ -4,5 +4,5 @@ use crate::error::ClientResult;
 
 pub(crate) trait BasicConnection {
     async fn execute(&mut self, command: Command) -> ClientResult<Response>;
-    async fn is_healthy(&self) -> bool;
+    async fn is_healthy(&mut self) -> bool;
 }
@@ -22,7 +22,7 @@ impl BasicConnection for FileConnection {
         Ok(self.db.execute(command).await)
     }
 
-    async fn is_healthy(&self) -> bool {
+    async fn is_healthy(&mut self) -> bool {
         true
     }
 }
@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::sync::Arc;
 
 use red_db_core::{
     db::Db,
@@ -6,16 +6,35 @@ use red_db_core::{
 };
 
 use crate::{
-    connection::{base::BasicConnection, file::FileConnection, tcp::TcpConnection},
+    connection::{
+        base::BasicConnection,
+        file::FileConnection,
+        tcp::{TcpConfig, TcpConnection},
+    },
     error::ClientResult,
 };
 
+#[cfg(unix)]
+use crate::connection::unix::{UnixConfig, UnixConnection};
+
 pub mod base;
 pub mod file;
+pub mod multiplexed;
+pub(crate) mod stream;
 pub mod tcp;
+#[cfg(unix)]
+pub mod unix;
+
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
 
 enum ConnectionImpl {
     Tcp(TcpConnection),
+    #[cfg(unix)]
+    Unix(UnixConnection),
     File(FileConnection),
 }
 
@@ -24,9 +43,16 @@ pub struct Connection {
 }
 
 impl Connection {
-    pub async fn remote_connect(url: SocketAddr) -> ClientResult<Self> {
+    pub async fn remote_connect(config: &TcpConfig) -> ClientResult<Self> {
+        Ok(Connection {
+            connection_impl: ConnectionImpl::Tcp(TcpConnection::connect(config).await?),
+        })
+    }
+
+    #[cfg(unix)]
+    pub async fn unix_connect(config: &UnixConfig) -> ClientResult<Self> {
         Ok(Connection {
-            connection_impl: ConnectionImpl::Tcp(TcpConnection::connect(url).await?),
+            connection_impl: ConnectionImpl::Unix(UnixConnection::connect(config).await?),
         })
     }
 
@@ -39,13 +65,17 @@ impl Connection {
     pub async fn execute(&mut self, command: Command) -> ClientResult<Response> {
         match &mut self.connection_impl {
             ConnectionImpl::Tcp(tcp_connection) => tcp_connection.execute(command).await,
+            #[cfg(unix)]
+            ConnectionImpl::Unix(unix_connection) => unix_connection.execute(command).await,
             ConnectionImpl::File(file_connection) => file_connection.execute(command).await,
         }
     }
 
-    pub async fn is_healthy(&self) -> bool {
-        match &self.connection_impl {
+    pub async fn is_healthy(&mut self) -> bool {
+        match &mut self.connection_impl {
             ConnectionImpl::Tcp(tcp_connection) => tcp_connection.is_healthy().await,
+            #[cfg(unix)]
+            ConnectionImpl::Unix(unix_connection) => unix_connection.is_healthy().await,
             ConnectionImpl::File(file_connection) => file_connection.is_healthy().await,
         }
     }
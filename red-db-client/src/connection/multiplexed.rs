@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use red_db_core::proto::{
+    Command, Reply, Request, Response,
+    frame::{MAX_RESPONSE_SIZE, encode_frame, read_frame},
+};
+use tokio::{
+    io::{AsyncWriteExt, ReadHalf, WriteHalf},
+    sync::{mpsc, oneshot},
+};
+use tracing::debug;
+
+use crate::{
+    connection::stream::{Stream, StreamConnection, check_protocol},
+    error::{ClientError, ClientResult},
+};
+
+#[derive(Default)]
+struct Pending {
+    closed: bool,
+    waiters: HashMap<u64, oneshot::Sender<Response>>,
+}
+
+impl Pending {
+    fn close(&mut self) {
+        // Dropping the waiters wakes every caller still waiting with an error.
+        self.closed = true;
+        self.waiters.clear();
+    }
+}
+
+/// Forgets a request's waiter when its caller stops waiting for the reply.
+struct WaiterGuard<'a> {
+    pending: &'a Mutex<Pending>,
+    id: u64,
+}
+
+impl Drop for WaiterGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().waiters.remove(&self.id);
+    }
+}
+
+/// A single server connection shared by many tasks. Requests are pipelined on one
+/// socket and replies are matched back to their callers by request id.
+///
+/// Frames are written by a dedicated task, so a caller that stops waiting, e.g. on a
+/// timeout, can't leave half a frame on the socket.
+#[derive(Clone)]
+pub struct MultiplexedConnection {
+    frames: mpsc::Sender<Vec<u8>>,
+    pending: Arc<Mutex<Pending>>,
+    next_id: Arc<AtomicU64>,
+    protocol_version: u32,
+}
+
+impl MultiplexedConnection {
+    pub(crate) fn new(connection: StreamConnection) -> Self {
+        let (stream, next_id, protocol_version) = connection.into_parts();
+        let (reader, writer) = tokio::io::split(stream);
+        let (frames, frame_receiver) = mpsc::channel(256);
+
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        tokio::spawn(dispatch_replies(reader, pending.clone()));
+        tokio::spawn(write_requests(writer, frame_receiver, pending.clone()));
+
+        Self {
+            frames,
+            pending,
+            next_id: Arc::new(AtomicU64::new(next_id)),
+            protocol_version,
+        }
+    }
+
+    pub async fn execute(&self, command: Command) -> ClientResult<Response> {
+        check_protocol(&command, self.protocol_version)?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let frame = encode_frame(&Request { id, command })?;
+
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(connection_closed());
+            }
+            pending.waiters.insert(id, sender);
+        }
+
+        let _guard = WaiterGuard {
+            pending: &self.pending,
+            id,
+        };
+
+        self.frames
+            .send(frame)
+            .await
+            .map_err(|_| connection_closed())?;
+
+        receiver.await.map_err(|_| connection_closed())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.pending.lock().unwrap().closed
+    }
+}
+
+async fn dispatch_replies(mut reader: ReadHalf<Stream>, pending: Arc<Mutex<Pending>>) {
+    loop {
+        match read_frame::<Reply, _>(&mut reader, MAX_RESPONSE_SIZE).await {
+            Ok(Some(Reply { id, response })) => {
+                let waiter = pending.lock().unwrap().waiters.remove(&id);
+
+                match waiter {
+                    Some(waiter) => {
+                        let _ = waiter.send(response);
+                    }
+                    None => debug!("Dropping reply to unknown request {}", id),
+                }
+            }
+            Ok(None) => {
+                debug!("Server closed multiplexed connection");
+                break;
+            }
+            Err(e) => {
+                debug!("Multiplexed connection failed: {}", e);
+                break;
+            }
+        }
+    }
+
+    pending.lock().unwrap().close();
+}
+
+/// Writes queued frames until every handle to the connection is dropped, flushing
+/// whenever the queue runs empty.
+async fn write_requests(
+    mut writer: WriteHalf<Stream>,
+    mut frames: mpsc::Receiver<Vec<u8>>,
+    pending: Arc<Mutex<Pending>>,
+) {
+    while let Some(frame) = frames.recv().await {
+        let mut written = writer.write_all(&frame).await;
+        if written.is_ok() && frames.is_empty() {
+            written = writer.flush().await;
+        }
+
+        if let Err(e) = written {
+            debug!("Multiplexed connection failed: {}", e);
+            pending.lock().unwrap().close();
+            return;
+        }
+    }
+
+    let _ = writer.shutdown().await;
+}
+
+fn connection_closed() -> ClientError {
+    ClientError::Io(io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "Multiplexed connection closed",
+    ))
+}
@@ -0,0 +1,240 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use red_db_core::proto::{
+    Command, Reply, Request, Response,
+    frame::{MAX_RESPONSE_SIZE, read_frame, write_frame},
+    handshake::{self, FEATURE_PIPELINING, Hello, HelloReply, PROTOCOL_MAGIC, PROTOCOL_VERSION},
+};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::client::TlsStream;
+use tracing::debug;
+
+use crate::{
+    connection::Credentials,
+    error::{ClientError, ClientResult},
+};
+
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// The native protocol spoken over any byte stream: handshake, authentication and
+/// request/reply framing. TCP and Unix socket connections both wrap this.
+#[derive(Debug)]
+pub(crate) struct StreamConnection {
+    stream: Stream,
+    next_id: u64,
+    protocol_version: u32,
+}
+
+impl StreamConnection {
+    pub(crate) async fn open(
+        stream: Stream,
+        client_name: &str,
+        credentials: Option<&Credentials>,
+    ) -> ClientResult<Self> {
+        let mut connection = StreamConnection {
+            stream,
+            next_id: 0,
+            protocol_version: PROTOCOL_VERSION,
+        };
+
+        connection.handshake(client_name).await?;
+
+        if let Some(credentials) = credentials {
+            connection.authenticate(credentials).await?;
+        }
+
+        Ok(connection)
+    }
+
+    async fn handshake(&mut self, client_name: &str) -> ClientResult<()> {
+        self.stream.write_all(&PROTOCOL_MAGIC).await?;
+        write_frame(
+            &mut self.stream,
+            &Hello {
+                protocol_version: PROTOCOL_VERSION,
+                client_name: client_name.to_string(),
+                features: vec![FEATURE_PIPELINING.to_string()],
+            },
+        )
+        .await?;
+
+        let reply: HelloReply = read_frame(&mut self.stream, MAX_RESPONSE_SIZE)
+            .await?
+            .ok_or_else(|| ClientError::Handshake("server closed the connection".to_string()))?;
+
+        match reply {
+            HelloReply::Accepted {
+                protocol_version,
+                server_version,
+                capabilities,
+            } => {
+                self.protocol_version =
+                    handshake::negotiate(protocol_version).map_err(ClientError::Handshake)?;
+                debug!(
+                    "Connected to red-db {} (protocol {}, capabilities {:?})",
+                    server_version, protocol_version, capabilities
+                );
+                Ok(())
+            }
+            HelloReply::Rejected {
+                reason,
+                protocol_version,
+            } => Err(ClientError::Handshake(format!(
+                "server (protocol {protocol_version}) rejected the connection: {reason}"
+            ))),
+        }
+    }
+
+    async fn authenticate(&mut self, credentials: &Credentials) -> ClientResult<()> {
+        let command = Command::Auth {
+            username: credentials.username.clone(),
+            password: credentials.password.clone(),
+        };
+
+        match self.execute(command).await? {
+            Response::Ok => Ok(()),
+            Response::Error(e) => Err(ClientError::Server(e)),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// The stream, the id of the next request and the negotiated protocol version.
+    pub(crate) fn into_parts(self) -> (Stream, u64, u32) {
+        (self.stream, self.next_id, self.protocol_version)
+    }
+
+    async fn send_request(&mut self, command: Command) -> ClientResult<u64> {
+        check_protocol(&command, self.protocol_version)?;
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        write_frame(&mut self.stream, &Request { id, command }).await?;
+        debug!("Sent request {}", id);
+
+        Ok(id)
+    }
+
+    async fn receive_reply(&mut self, id: u64) -> ClientResult<Response> {
+        let reply: Reply = read_frame(&mut self.stream, MAX_RESPONSE_SIZE)
+            .await?
+            .ok_or_else(|| {
+                ClientError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Server closed the connection",
+                ))
+            })?;
+
+        if reply.id != id {
+            return Err(ClientError::Protocol(format!(
+                "Expected reply to request {id}, got {}",
+                reply.id
+            )));
+        }
+
+        Ok(reply.response)
+    }
+
+    pub(crate) async fn execute(&mut self, command: Command) -> ClientResult<Response> {
+        let id = self.send_request(command).await?;
+        self.receive_reply(id).await
+    }
+
+    pub(crate) async fn is_healthy(&mut self) -> bool {
+        let payload = self.next_id.to_le_bytes().to_vec();
+        let ping = Command::Ping {
+            payload: payload.clone(),
+        };
+
+        // Servers from before `Ping` answer any command, so a cheap one shows the
+        // connection works.
+        if ping.protocol_version() > self.protocol_version {
+            let probe = Command::IsSpaceExists {
+                space: String::new(),
+            };
+            return self.execute(probe).await.is_ok();
+        }
+
+        matches!(
+            self.execute(ping).await,
+            Ok(Response::Pong(pong)) if pong == payload
+        )
+    }
+}
+
+/// Fails before sending a command the server's protocol version doesn't have.
+pub(crate) fn check_protocol(command: &Command, protocol_version: u32) -> ClientResult<()> {
+    if command.protocol_version() > protocol_version {
+        return Err(ClientError::Unsupported(format!(
+            "'{}' needs protocol version {}, the server speaks {}",
+            command.name(),
+            command.protocol_version(),
+            protocol_version
+        )));
+    }
+
+    Ok(())
+}
@@ -1,86 +1,70 @@
 use std::net::SocketAddr;
 
 use red_db_core::proto::{Command, Response};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-};
-use tracing::debug;
+use tokio::net::TcpStream;
 
 use crate::{
-    connection::base::BasicConnection,
+    connection::{
+        Credentials,
+        base::BasicConnection,
+        stream::{Stream, StreamConnection},
+    },
     error::{ClientError, ClientResult},
+    tls::TlsParams,
 };
 
+#[derive(Clone)]
+pub struct TcpConfig {
+    pub addr: SocketAddr,
+    pub tls: Option<TlsParams>,
+    pub credentials: Option<Credentials>,
+    pub client_name: String,
+}
+
 #[derive(Debug)]
 pub struct TcpConnection {
-    stream: TcpStream,
+    inner: StreamConnection,
 }
 
 impl TcpConnection {
-    pub async fn connect(to: SocketAddr) -> ClientResult<Self> {
-        let stream = TcpStream::connect(to).await.map_err(ClientError::Io)?;
-
-        stream.set_nodelay(true).expect("Failed to set nodelay");
-
-        Ok(TcpConnection { stream })
-    }
-
-    async fn send_command(&mut self, command: &Command) -> ClientResult<()> {
-        let data = bincode::encode_to_vec(command, bincode::config::standard())
-            .map_err(|e| ClientError::Protocol(format!("Encode error: {e}")))?;
-
-        let len_bytes = (data.len() as u32).to_le_bytes();
-
-        self.stream
-            .write_all(&len_bytes)
+    pub async fn connect(config: &TcpConfig) -> ClientResult<Self> {
+        let stream = TcpStream::connect(config.addr)
             .await
             .map_err(ClientError::Io)?;
-        debug!("Sent command length: {}", data.len());
-        self.stream
-            .write_all(&data)
-            .await
-            .map_err(ClientError::Io)?;
-        debug!("Sent command");
 
-        Ok(())
-    }
-
-    async fn receive_response(&mut self) -> ClientResult<Response> {
-        let mut len_bytes = [0u8; 4];
-        self.stream
-            .read_exact(&mut len_bytes)
-            .await
-            .map_err(ClientError::Io)?;
+        stream.set_nodelay(true).expect("Failed to set nodelay");
 
-        let len = u32::from_le_bytes(len_bytes) as usize;
+        let stream = match &config.tls {
+            Some(tls) => {
+                let stream = tls
+                    .connector
+                    .connect(tls.server_name.clone(), stream)
+                    .await
+                    .map_err(|e| ClientError::Tls(format!("Handshake failed: {e}")))?;
 
-        if len > 16 * 1024 * 1024 {
-            return Err(ClientError::Protocol("Response too large".to_string()));
-        }
+                Stream::Tls(Box::new(stream))
+            }
+            None => Stream::Plain(stream),
+        };
 
-        let mut response_buf = vec![0u8; len];
-        self.stream
-            .read_exact(&mut response_buf)
-            .await
-            .map_err(ClientError::Io)?;
+        let inner =
+            StreamConnection::open(stream, &config.client_name, config.credentials.as_ref())
+                .await?;
 
-        let (response, _) = bincode::decode_from_slice(&response_buf, bincode::config::standard())
-            .map_err(|e| ClientError::Protocol(format!("Decode error: {e}")))?;
+        Ok(TcpConnection { inner })
+    }
 
-        Ok(response)
+    pub(crate) fn into_inner(self) -> StreamConnection {
+        self.inner
     }
 }
 
 impl BasicConnection for TcpConnection {
     async fn execute(&mut self, command: Command) -> ClientResult<Response> {
-        self.send_command(&command).await?;
-        self.receive_response().await
+        self.inner.execute(command).await
     }
 
-    // TODO: Improve health check.
-    async fn is_healthy(&self) -> bool {
-        let mut buf = [0u8; 0];
-        matches!(self.stream.try_read(&mut buf), Ok(0) | Err(_))
+    async fn is_healthy(&mut self) -> bool {
+        self.inner.is_healthy().await
     }
 }
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use red_db_core::proto::{Command, Response};
+use tokio::net::UnixStream;
+
+use crate::{
+    connection::{
+        Credentials,
+        base::BasicConnection,
+        stream::{Stream, StreamConnection},
+    },
+    error::{ClientError, ClientResult},
+};
+
+#[derive(Clone)]
+pub struct UnixConfig {
+    pub path: PathBuf,
+    pub credentials: Option<Credentials>,
+    pub client_name: String,
+}
+
+#[derive(Debug)]
+pub struct UnixConnection {
+    inner: StreamConnection,
+}
+
+impl UnixConnection {
+    pub async fn connect(config: &UnixConfig) -> ClientResult<Self> {
+        let stream = UnixStream::connect(&config.path)
+            .await
+            .map_err(ClientError::Io)?;
+
+        let inner = StreamConnection::open(
+            Stream::Unix(stream),
+            &config.client_name,
+            config.credentials.as_ref(),
+        )
+        .await?;
+
+        Ok(UnixConnection { inner })
+    }
+
+    pub(crate) fn into_inner(self) -> StreamConnection {
+        self.inner
+    }
+}
+
+impl BasicConnection for UnixConnection {
+    async fn execute(&mut self, command: Command) -> ClientResult<Response> {
+        self.inner.execute(command).await
+    }
+
+    async fn is_healthy(&mut self) -> bool {
+        self.inner.is_healthy().await
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+use percent_encoding::percent_decode_str;
+use red_db_core::storage::EngineKind;
+use url::{Host, Url};
+
+use crate::{
+    ClientBuilder, TlsConfig,
+    connection::Credentials,
+    error::{ClientError, ClientResult},
+};
+
+const DEFAULT_PORT: u16 = 25500;
+
+/// Parses `reddb://`, `reddbs://` (TLS), `reddb+unix://` and `file://` URLs into a builder.
+pub(crate) fn parse(input: &str) -> ClientResult<ClientBuilder> {
+    let url = Url::parse(input).map_err(|e| invalid(format!("{e}")))?;
+    let mut builder = ClientBuilder::new();
+
+    match url.scheme() {
+        "reddb" | "reddbs" => {
+            if !matches!(url.path(), "" | "/") {
+                return Err(invalid(format!("unexpected path `{}`", url.path())));
+            }
+            builder.server_addr = Some(resolve(&url)?);
+        }
+        "reddb+unix" => {
+            if url.path().is_empty() {
+                return Err(invalid("missing socket path"));
+            }
+            builder = with_unix_socket(builder, PathBuf::from(decode(url.path())?))?;
+        }
+        "file" => {
+            let path = url
+                .to_file_path()
+                .map_err(|_| invalid("file URLs must contain an absolute local path"))?;
+            builder.aof_path = Some(path);
+        }
+        scheme => {
+            return Err(invalid(format!(
+                "unsupported scheme `{scheme}`, expected reddb, reddbs, reddb+unix or file"
+            )));
+        }
+    }
+
+    if !url.username().is_empty() {
+        if url.scheme() == "file" {
+            return Err(invalid("file URLs can't contain credentials"));
+        }
+
+        builder.credentials = Some(Credentials {
+            username: decode(url.username())?,
+            password: decode(url.password().unwrap_or_default())?,
+        });
+    }
+
+    let mut ca_cert = None;
+    let mut server_name = None;
+    let mut client_cert = None;
+    let mut client_key = None;
+
+    for (name, value) in url.query_pairs() {
+        let value = value.into_owned();
+
+        match name.as_ref() {
+            "pool" => {
+                builder.max_pool_size = value
+                    .parse()
+                    .ok()
+                    .filter(|size| *size > 0)
+                    .ok_or_else(|| invalid(format!("invalid pool size `{value}`")))?;
+            }
+            "timeout" => builder.request_timeout = Some(parse_duration(&value)?),
+            "connect_timeout" => builder.connect_timeout = Some(parse_duration(&value)?),
+            "pool_timeout" => builder.pool_timeout = Some(parse_duration(&value)?),
+            "retries" => {
+                builder.retry_policy.max_retries = value
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid retry count `{value}`")))?;
+            }
+            "client_name" => builder.client_name = value,
+            "engine" => {
+                builder.storage_engine = match value.as_str() {
+                    "memory" => EngineKind::Memory,
+                    "lsm" => EngineKind::Lsm,
+                    _ => return Err(invalid(format!("unknown storage engine `{value}`"))),
+                };
+            }
+            "ca_cert" => ca_cert = Some(PathBuf::from(value)),
+            "server_name" => server_name = Some(value),
+            "client_cert" => client_cert = Some(PathBuf::from(value)),
+            "client_key" => client_key = Some(PathBuf::from(value)),
+            name => return Err(invalid(format!("unknown parameter `{name}`"))),
+        }
+    }
+
+    let client_cert = match (client_cert, client_key) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => {
+            return Err(invalid(
+                "`client_cert` and `client_key` must be set together",
+            ));
+        }
+    };
+
+    if url.scheme() == "reddbs" {
+        let ca_cert =
+            ca_cert.ok_or_else(|| invalid("reddbs URLs require a `ca_cert` parameter"))?;
+        let server_name = match server_name {
+            Some(server_name) => server_name,
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut tls = TlsConfig::new(server_name, ca_cert);
+        if let Some((cert, key)) = client_cert {
+            tls = tls.with_client_cert(cert, key);
+        }
+        builder.tls = Some(tls);
+    } else if ca_cert.is_some() || server_name.is_some() || client_cert.is_some() {
+        return Err(invalid("TLS parameters require the reddbs scheme"));
+    }
+
+    Ok(builder)
+}
+
+fn resolve(url: &Url) -> ClientResult<SocketAddr> {
+    let port = url.port().unwrap_or(DEFAULT_PORT);
+
+    match url.host() {
+        Some(Host::Ipv4(ip)) => Ok(SocketAddr::from((ip, port))),
+        Some(Host::Ipv6(ip)) => Ok(SocketAddr::from((ip, port))),
+        Some(Host::Domain(host)) if !host.is_empty() => (host, port)
+            .to_socket_addrs()
+            .map_err(|e| invalid(format!("can't resolve `{host}`: {e}")))?
+            .next()
+            .ok_or_else(|| invalid(format!("`{host}` has no addresses"))),
+        _ => Err(invalid("missing host")),
+    }
+}
+
+#[cfg(unix)]
+fn with_unix_socket(builder: ClientBuilder, path: PathBuf) -> ClientResult<ClientBuilder> {
+    Ok(builder.with_unix_socket(path))
+}
+
+#[cfg(not(unix))]
+fn with_unix_socket(_: ClientBuilder, _: PathBuf) -> ClientResult<ClientBuilder> {
+    Err(invalid("Unix sockets are not supported on this platform"))
+}
+
+/// Accepts `500ms`, `2s`, `1m` or a bare number of seconds.
+fn parse_duration(value: &str) -> ClientResult<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| invalid(format!("invalid duration `{value}`")))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| invalid(format!("duration `{value}` is too long"))),
+        _ => Err(invalid(format!("invalid duration `{value}`"))),
+    }
+}
+
+fn decode(value: &str) -> ClientResult<String> {
+    percent_decode_str(value)
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(|_| invalid(format!("`{value}` is not valid UTF-8")))
+}
+
+fn invalid<T: Into<String>>(message: T) -> ClientError {
+    ClientError::InvalidUrl(message.into())
+}
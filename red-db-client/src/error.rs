@@ -1,4 +1,4 @@
-use red_db_core::error::ServerError;
+use red_db_core::{error::ServerError, proto::frame::FrameError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,6 +15,43 @@ pub enum ClientError {
     Pool(String),
     #[error("No configuration")]
     NoConfig,
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("Handshake failed: {0}")]
+    Handshake(String),
+    #[error("Invalid connection URL: {0}")]
+    InvalidUrl(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Codec error: {0}")]
+    Codec(String),
+    #[error("Not supported by the server: {0}")]
+    Unsupported(String),
+}
+
+impl ClientError {
+    /// Whether a read-only request that failed with this error may succeed if sent again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ClientError::Io(_) | ClientError::Timeout)
+    }
+
+    pub(crate) fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            ClientError::Io(_) | ClientError::Protocol(_) | ClientError::Timeout
+        )
+    }
+}
+
+impl From<FrameError> for ClientError {
+    fn from(err: FrameError) -> Self {
+        match err {
+            FrameError::Io(e) => ClientError::Io(e),
+            e => ClientError::Protocol(e.to_string()),
+        }
+    }
 }
 
 pub type ClientResult<T> = std::result::Result<T, ClientError>;
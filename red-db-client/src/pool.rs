@@ -1,17 +1,28 @@
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use deadpool::managed::{Manager, Object, Pool, RecycleError, RecycleResult};
 use red_db_core::db::Db;
 
-use crate::{connection::Connection, error::ClientError};
+use crate::{
+    connection::{Connection, tcp::TcpConfig},
+    error::ClientError,
+};
+
+#[cfg(unix)]
+use crate::connection::unix::UnixConfig;
+
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
 
 enum ConnectionUrl {
-    Tcp(SocketAddr),
+    Tcp(TcpConfig),
+    #[cfg(unix)]
+    Unix(UnixConfig),
     File(Arc<Db>),
 }
 
 pub struct ConnectionManager {
     connection_url: ConnectionUrl,
+    health_check_interval: Duration,
 }
 
 impl Manager for ConnectionManager {
@@ -20,7 +31,9 @@ impl Manager for ConnectionManager {
 
     async fn create(&self) -> Result<Connection, Self::Error> {
         match &self.connection_url {
-            ConnectionUrl::Tcp(addr) => Connection::remote_connect(*addr).await,
+            ConnectionUrl::Tcp(config) => Connection::remote_connect(config).await,
+            #[cfg(unix)]
+            ConnectionUrl::Unix(config) => Connection::unix_connect(config).await,
             ConnectionUrl::File(db) => Ok(Connection::use_db(Arc::clone(db)).await),
         }
     }
@@ -28,9 +41,11 @@ impl Manager for ConnectionManager {
     async fn recycle(
         &self,
         conn: &mut Connection,
-        _: &deadpool::managed::Metrics,
+        metrics: &deadpool::managed::Metrics,
     ) -> RecycleResult<Self::Error> {
-        if conn.is_healthy().await {
+        // Recently checked connections are handed out without a round trip. Any that broke
+        // since are dropped when their next request fails.
+        if metrics.last_used() < self.health_check_interval || conn.is_healthy().await {
             Ok(())
         } else {
             Err(RecycleError::Backend(ClientError::Protocol(
@@ -43,19 +58,49 @@ impl Manager for ConnectionManager {
 }
 
 impl ConnectionManager {
-    pub fn with_server_addr(server_addr: SocketAddr) -> Self {
-        Self {
-            connection_url: ConnectionUrl::Tcp(server_addr),
+    pub fn with_tcp_config(config: TcpConfig) -> Self {
+        Self::new(ConnectionUrl::Tcp(config))
+    }
+
+    #[cfg(unix)]
+    pub fn with_unix_config(config: UnixConfig) -> Self {
+        Self::new(ConnectionUrl::Unix(config))
+    }
+
+    pub async fn shutdown(&self) -> Result<(), ClientError> {
+        match &self.connection_url {
+            ConnectionUrl::Tcp(_) => Ok(()),
+            #[cfg(unix)]
+            ConnectionUrl::Unix(_) => Ok(()),
+            ConnectionUrl::File(db) => db.shutdown().await.map_err(ClientError::Server),
         }
     }
 
-    pub async fn with_file_path(file_path: PathBuf) -> Self {
-        let db = Arc::new(Db::new(file_path).await);
+    pub async fn save_to(&self, path: PathBuf) -> Result<(), ClientError> {
+        match &self.connection_url {
+            ConnectionUrl::File(db) => db.save_to(path).await.map_err(ClientError::Server),
+            _ => Err(ClientError::InvalidConfig(
+                "save_to is only available in embedded mode".to_string(),
+            )),
+        }
+    }
 
+    pub fn with_db(db: Db) -> Self {
+        Self::new(ConnectionUrl::File(Arc::new(db)))
+    }
+
+    fn new(connection_url: ConnectionUrl) -> Self {
         Self {
-            connection_url: ConnectionUrl::File(db),
+            connection_url,
+            health_check_interval: DEFAULT_HEALTH_CHECK_INTERVAL,
         }
     }
+
+    /// Pooled connections idle for longer than `interval` are pinged before reuse.
+    pub fn with_health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
 }
 
 pub type ConnectionPool = Pool<ConnectionManager>;
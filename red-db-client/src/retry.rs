@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use red_db_core::proto::Command;
+
+/// How plain reads are retried after connection errors and timeouts.
+/// Writes are never retried, since the server may have applied them already, and
+/// neither are reads with side effects or large results, such as `Backup` and `Export`.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+/// Whether `command` can be sent again after a failed attempt.
+pub(crate) fn is_retryable(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Get { .. } | Command::ListKeys { .. } | Command::IsSpaceExists { .. }
+    )
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::{path::PathBuf, sync::Arc};
+
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        ClientConfig, RootCertStore,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    },
+};
+
+use crate::error::{ClientError, ClientResult};
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    server_name: String,
+    ca_cert_path: PathBuf,
+    client_cert: Option<(PathBuf, PathBuf)>,
+}
+
+impl TlsConfig {
+    pub fn new<T: Into<String>>(server_name: T, ca_cert_path: PathBuf) -> Self {
+        Self {
+            server_name: server_name.into(),
+            ca_cert_path,
+            client_cert: None,
+        }
+    }
+
+    pub fn with_client_cert(mut self, cert_path: PathBuf, key_path: PathBuf) -> Self {
+        self.client_cert = Some((cert_path, key_path));
+        self
+    }
+
+    pub(crate) fn build(&self) -> ClientResult<TlsParams> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca_cert_path)? {
+            roots.add(cert).map_err(|e| {
+                ClientError::Tls(format!(
+                    "Invalid CA certificate {:?}: {e}",
+                    self.ca_cert_path
+                ))
+            })?;
+        }
+
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| ClientError::Tls(e.to_string()))?
+            .with_root_certificates(roots);
+
+        let config = match &self.client_cert {
+            Some((cert_path, key_path)) => {
+                let certs = load_certs(cert_path)?;
+                let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| {
+                    ClientError::Tls(format!("Failed to load private key {key_path:?}: {e}"))
+                })?;
+
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(|e| ClientError::Tls(e.to_string()))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let server_name = ServerName::try_from(self.server_name.clone())
+            .map_err(|e| ClientError::Tls(format!("Invalid server name: {e}")))?;
+
+        Ok(TlsParams {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct TlsParams {
+    pub(crate) connector: TlsConnector,
+    pub(crate) server_name: ServerName<'static>,
+}
+
+fn load_certs(path: &PathBuf) -> ClientResult<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ClientError::Tls(format!("Failed to load certificates {path:?}: {e}")))?;
+
+    if certs.is_empty() {
+        return Err(ClientError::Tls(format!(
+            "No certificates found in {path:?}"
+        )));
+    }
+
+    Ok(certs)
+}
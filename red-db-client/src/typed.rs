@@ -0,0 +1,131 @@
+use std::marker::PhantomData;
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    SpaceClient,
+    codec::{Codec, JsonCodec},
+    error::{ClientError, ClientResult},
+};
+
+/// Converts a key type to and from the string keys stored on the server.
+pub trait KeyEncoder: Sized {
+    fn encode_key(&self) -> String;
+    fn decode_key(key: &str) -> ClientResult<Self>;
+}
+
+impl KeyEncoder for String {
+    fn encode_key(&self) -> String {
+        self.clone()
+    }
+
+    fn decode_key(key: &str) -> ClientResult<Self> {
+        Ok(key.to_string())
+    }
+}
+
+macro_rules! impl_key_encoder {
+    ($($ty:ty),*) => {
+        $(
+            impl KeyEncoder for $ty {
+                fn encode_key(&self) -> String {
+                    self.to_string()
+                }
+
+                fn decode_key(key: &str) -> ClientResult<Self> {
+                    key.parse().map_err(|e| {
+                        ClientError::Codec(format!(
+                            "Invalid {} key {:?}: {}",
+                            stringify!($ty),
+                            key,
+                            e
+                        ))
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_key_encoder!(
+    u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, usize, isize
+);
+
+/// A space whose keys are `K` and whose values are `V`, encoded with `C`.
+pub struct TypedSpace<K, V, C = JsonCodec> {
+    space: SpaceClient<C>,
+    _types: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V, C: Codec> Clone for TypedSpace<K, V, C> {
+    fn clone(&self) -> Self {
+        Self {
+            space: self.space.clone(),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<K, V, C> TypedSpace<K, V, C>
+where
+    K: KeyEncoder,
+    V: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    pub(crate) fn new(space: SpaceClient<C>) -> Self {
+        Self {
+            space,
+            _types: PhantomData,
+        }
+    }
+
+    pub async fn get(&self, key: &K) -> ClientResult<Option<V>> {
+        self.space.get_value(&key.encode_key()).await
+    }
+
+    pub async fn set(&self, key: &K, value: &V) -> ClientResult<()> {
+        self.space.set_value(&key.encode_key(), value).await
+    }
+
+    pub async fn delete(&self, key: &K) -> ClientResult<()> {
+        self.space.delete(&key.encode_key()).await
+    }
+
+    pub async fn keys(&self) -> ClientResult<Vec<K>> {
+        self.space
+            .list_keys()
+            .await?
+            .iter()
+            .map(|key| K::decode_key(key))
+            .collect()
+    }
+
+    /// Every entry in the space, in order of the encoded keys. The entries are fetched a
+    /// page at a time with `Client::export_page`, all from one snapshot, so writes made
+    /// while scanning don't show up.
+    pub async fn scan(&self) -> ClientResult<Vec<(K, V)>> {
+        let mut entries = Vec::new();
+        let mut after = None;
+
+        loop {
+            let page = self
+                .space
+                .client
+                .export_page(Some(&self.space.space_name), after)
+                .await?;
+            for entry in page.entries {
+                let value = self.space.codec.decode(&entry.value)?;
+                entries.push((K::decode_key(&entry.key)?, value));
+            }
+
+            match page.next {
+                Some(next) => after = Some(next),
+                None => return Ok(entries),
+            }
+        }
+    }
+
+    pub fn untyped(&self) -> &SpaceClient<C> {
+        &self.space
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::proto::Command;
+
+/// Size of the little-endian `u32` length that precedes every AOF record.
+pub const RECORD_HEADER_SIZE: u64 = 4;
+
+#[derive(Debug)]
+pub enum AofRecord {
+    Command {
+        offset: u64,
+        len: usize,
+        command: Command,
+    },
+    /// A complete record whose payload doesn't decode as a `Command`.
+    Undecodable {
+        offset: u64,
+        len: usize,
+        error: String,
+    },
+    /// The file ends inside a length header.
+    TruncatedHeader { offset: u64, available: usize },
+    /// The file ends before the payload announced by the header.
+    TruncatedPayload {
+        offset: u64,
+        len: usize,
+        available: usize,
+    },
+}
+
+impl AofRecord {
+    pub fn offset(&self) -> u64 {
+        match self {
+            AofRecord::Command { offset, .. }
+            | AofRecord::Undecodable { offset, .. }
+            | AofRecord::TruncatedHeader { offset, .. }
+            | AofRecord::TruncatedPayload { offset, .. } => *offset,
+        }
+    }
+
+    /// Offset just past this record, where the next one starts.
+    pub fn end(&self) -> u64 {
+        match self {
+            AofRecord::Command { offset, len, .. } | AofRecord::Undecodable { offset, len, .. } => {
+                offset + RECORD_HEADER_SIZE + *len as u64
+            }
+            AofRecord::TruncatedHeader { offset, available } => offset + *available as u64,
+            AofRecord::TruncatedPayload {
+                offset, available, ..
+            } => offset + RECORD_HEADER_SIZE + *available as u64,
+        }
+    }
+}
+
+/// Reads AOF records one at a time. A truncated record is always the last one returned.
+pub struct AofReader<R> {
+    reader: R,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AofReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    pub async fn next_record(&mut self) -> io::Result<Option<AofRecord>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let offset = self.offset;
+
+        let mut len_bytes = [0u8; RECORD_HEADER_SIZE as usize];
+        let available = read_full(&mut self.reader, &mut len_bytes).await?;
+        if available == 0 {
+            self.done = true;
+            return Ok(None);
+        }
+        if available < len_bytes.len() {
+            self.done = true;
+            return Ok(Some(AofRecord::TruncatedHeader { offset, available }));
+        }
+
+        // The header may be corrupt, so the payload only grows with the bytes actually read.
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        let mut payload = Vec::new();
+        let available = (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut payload)
+            .await?;
+        if available < len {
+            self.done = true;
+            return Ok(Some(AofRecord::TruncatedPayload {
+                offset,
+                len,
+                available,
+            }));
+        }
+
+        self.offset += RECORD_HEADER_SIZE + len as u64;
+
+        let record = match bincode::decode_from_slice(&payload, bincode::config::standard()) {
+            Ok((command, _)) => AofRecord::Command {
+                offset,
+                len,
+                command,
+            },
+            Err(e) => AofRecord::Undecodable {
+                offset,
+                len,
+                error: e.to_string(),
+            },
+        };
+
+        Ok(Some(record))
+    }
+}
+
+/// Like `read_exact`, but returns how many bytes were read instead of failing at EOF.
+async fn read_full<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+
+    Ok(filled)
+}
@@ -0,0 +1,429 @@
+use std::{
+    fs,
+    io::{self, BufWriter, SeekFrom, Write},
+    mem,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bincode::{Decode, Encode};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt};
+
+use crate::{
+    aof::{AofReader, AofRecord},
+    error::ServerError,
+    proto::{Command, frame::encode_frame},
+    storage::{
+        EngineKind, Snapshot, StorageEngine,
+        lsm::{LsmEngine, LsmOptions},
+    },
+};
+
+/// Backup archives start with these bytes, followed by a `u32` format version.
+pub const BACKUP_MAGIC: &[u8; 8] = b"RDBACKUP";
+/// Version 2 records the config of each space; version 1 archives restore with defaults.
+pub const BACKUP_FORMAT_VERSION: u32 = 2;
+const MIN_BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Size of the CRC32 of all preceding bytes that ends every archive.
+const CHECKSUM_SIZE: usize = 4;
+/// Bytes of records `restore` writes to an LSM engine at a time.
+const RESTORE_BATCH_SIZE: u64 = 1024 * 1024;
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct BackupMetadata {
+    pub server_version: String,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub spaces: Vec<String>,
+    pub keys: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not a red-db backup archive")]
+    NotABackup,
+    #[error("Unsupported backup format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Checksum mismatch, the archive is damaged")]
+    ChecksumMismatch,
+    #[error("Corrupt archive: {0}")]
+    Corrupt(String),
+    #[error("Failed to write the restored data: {0}")]
+    Storage(#[from] ServerError),
+}
+
+/// Passes writes through to `inner` and keeps a CRC32 of everything written.
+struct ChecksumWriter<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes `snapshot` to `dest_path` as an archive: magic, format version, length-prefixed
+/// metadata, the store as AOF records and a CRC32 trailer. The records are streamed to the
+/// file; since the metadata comes first, the keys are counted in a pass of their own.
+pub(crate) fn write_archive(
+    dest_path: &Path,
+    snapshot: &impl Snapshot,
+) -> Result<BackupMetadata, BackupError> {
+    let mut spaces = snapshot.spaces();
+    spaces.sort();
+
+    let mut keys = 0;
+    for space in &spaces {
+        for entry in snapshot.scan(space).map_err(corrupt)? {
+            entry.map_err(corrupt)?;
+            keys += 1;
+        }
+    }
+
+    let metadata = BackupMetadata {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default(),
+        spaces,
+        keys,
+    };
+
+    write_atomically(dest_path, |file| {
+        let mut writer = ChecksumWriter {
+            inner: file,
+            hasher: crc32fast::Hasher::new(),
+        };
+        writer.write_all(BACKUP_MAGIC)?;
+        writer.write_all(&BACKUP_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&encode_frame(&metadata).map_err(corrupt)?)?;
+
+        let written = write_records(snapshot, &metadata.spaces, &mut writer)?;
+        if written != metadata.keys {
+            return Err(BackupError::Corrupt(format!(
+                "counted {} keys, wrote {written}",
+                metadata.keys
+            )));
+        }
+
+        let checksum = writer.hasher.finalize();
+        writer.inner.write_all(&checksum.to_le_bytes())?;
+        Ok(())
+    })?;
+
+    Ok(metadata)
+}
+
+/// Writes `snapshot` to `dest_path` as a plain AOF that `Db::new` can open.
+pub(crate) fn write_aof(dest_path: &Path, snapshot: &impl Snapshot) -> Result<(), BackupError> {
+    let mut spaces = snapshot.spaces();
+    spaces.sort();
+
+    write_atomically(dest_path, |file| {
+        write_records(snapshot, &spaces, file)?;
+        Ok(())
+    })
+}
+
+/// Writes, for every space, a `CreateSpace` record, a `Set` for each of its keys and an
+/// `AlterSpace` that restores its config. Returns the number of keys.
+fn write_records(
+    snapshot: &impl Snapshot,
+    spaces: &[String],
+    writer: &mut impl Write,
+) -> Result<u64, BackupError> {
+    let mut keys = 0;
+
+    // Spaces are created with the default config, which admits any entry a space can
+    // hold, and get their own config once their entries are written, since a config may
+    // have been tightened after the entries it would reject.
+    for space in spaces {
+        writer.write_all(&encode_record(&Command::CreateSpace {
+            space: space.clone(),
+        })?)?;
+
+        for entry in snapshot.scan(space).map_err(corrupt)? {
+            let (key, value) = entry.map_err(corrupt)?;
+            writer.write_all(&encode_record(&Command::Set {
+                space: space.clone(),
+                key,
+                value,
+            })?)?;
+            keys += 1;
+        }
+
+        let config = snapshot.space_config(space).map_err(corrupt)?;
+        writer.write_all(&encode_record(&Command::AlterSpace {
+            space: space.clone(),
+            config,
+        })?)?;
+    }
+
+    Ok(keys)
+}
+
+/// Writes to a temporary file first, so `dest_path` never holds partial contents.
+fn write_atomically(
+    dest_path: &Path,
+    write: impl FnOnce(&mut BufWriter<fs::File>) -> Result<(), BackupError>,
+) -> Result<(), BackupError> {
+    let tmp_path = temporary_path(dest_path);
+    let result = (|| {
+        let mut file = BufWriter::new(fs::File::create(&tmp_path)?);
+        write(&mut file)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, dest_path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Resolves `dest_path`, as sent by a client, to a file in `backup_dir`. The path must be
+/// relative, without `..`, and its directory must not lead out of `backup_dir` through a
+/// symlink.
+pub fn resolve_path(backup_dir: &Path, dest_path: &str) -> Result<PathBuf, ServerError> {
+    let invalid = |reason: &str| ServerError::InvalidBackupPath(format!("'{dest_path}' {reason}"));
+
+    let relative = Path::new(dest_path);
+    let Some(file_name) = relative.file_name() else {
+        return Err(invalid("does not name a file"));
+    };
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(invalid(
+            "must be relative to the backup directory and must not contain `..`",
+        ));
+    }
+
+    let backup_dir = backup_dir.canonicalize().map_err(|e| {
+        ServerError::BackupFailed(format!("backup directory {}: {e}", backup_dir.display()))
+    })?;
+    let parent = backup_dir
+        .join(relative)
+        .parent()
+        .unwrap_or(backup_dir.as_path())
+        .canonicalize()
+        .map_err(|_| invalid("is not in an existing directory"))?;
+    if !parent.starts_with(&backup_dir) {
+        return Err(invalid("leaves the backup directory"));
+    }
+
+    let path = parent.join(file_name);
+    if path
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        return Err(invalid("is a symlink"));
+    }
+
+    Ok(path)
+}
+
+/// Checks the magic, version and checksum of the archive at `archive_path`, and that its
+/// records are intact and hold as many keys as its metadata says. The archive is streamed
+/// rather than loaded. Returns the metadata and the byte range of the AOF records that make
+/// up the store.
+pub async fn read_archive(
+    archive_path: &Path,
+) -> Result<(BackupMetadata, Range<u64>), BackupError> {
+    let mut file = tokio::fs::File::open(archive_path).await?;
+    let len = file.metadata().await?.len();
+    let header_size = (BACKUP_MAGIC.len() + 4) as u64;
+    if len < header_size + CHECKSUM_SIZE as u64 {
+        return Err(BackupError::NotABackup);
+    }
+    let content_len = len - CHECKSUM_SIZE as u64;
+
+    let mut header = [0; BACKUP_MAGIC.len() + 4];
+    file.read_exact(&mut header).await?;
+    if !header.starts_with(BACKUP_MAGIC) {
+        return Err(BackupError::NotABackup);
+    }
+
+    let version = u32::from_le_bytes(header[BACKUP_MAGIC.len()..].try_into().unwrap());
+    if !(MIN_BACKUP_FORMAT_VERSION..=BACKUP_FORMAT_VERSION).contains(&version) {
+        return Err(BackupError::UnsupportedVersion(version));
+    }
+
+    file.rewind().await?;
+    let mut content = tokio::io::BufReader::new(&mut file).take(content_len);
+    let mut hasher = crc32fast::Hasher::new();
+    loop {
+        let buf = content.fill_buf().await?;
+        if buf.is_empty() {
+            break;
+        }
+        hasher.update(buf);
+        let consumed = buf.len();
+        content.consume(consumed);
+    }
+    let mut checksum = [0; CHECKSUM_SIZE];
+    file.seek(SeekFrom::Start(content_len)).await?;
+    file.read_exact(&mut checksum).await?;
+    if hasher.finalize().to_le_bytes() != checksum {
+        return Err(BackupError::ChecksumMismatch);
+    }
+
+    if content_len < header_size + 4 {
+        return Err(BackupError::Corrupt("missing metadata".to_string()));
+    }
+    file.seek(SeekFrom::Start(header_size)).await?;
+    let metadata_len = u64::from(file.read_u32_le().await?);
+    let body_start = header_size + 4 + metadata_len;
+    if body_start > content_len {
+        return Err(BackupError::Corrupt("truncated metadata".to_string()));
+    }
+    let mut metadata_bytes = vec![0; metadata_len as usize];
+    file.read_exact(&mut metadata_bytes).await?;
+
+    let (metadata, _): (BackupMetadata, _) =
+        bincode::decode_from_slice(&metadata_bytes, bincode::config::standard())
+            .map_err(|e| BackupError::Corrupt(format!("invalid metadata: {e}")))?;
+
+    let body = body_start..content_len;
+    let mut reader = AofReader::new(tokio::io::BufReader::new(file).take(body.end - body.start));
+    let mut keys = 0;
+    while let Some(record) = reader.next_record().await? {
+        match record {
+            AofRecord::Command {
+                command: Command::Set { .. },
+                ..
+            } => keys += 1,
+            AofRecord::Command { .. } => {}
+            record => return Err(bad_record(&record)),
+        }
+    }
+
+    if keys != metadata.keys {
+        return Err(BackupError::Corrupt(format!(
+            "expected {} keys, found {keys}",
+            metadata.keys
+        )));
+    }
+
+    Ok((metadata, body))
+}
+
+/// Verifies the archive at `archive_path` and makes its contents the data at `path` of an
+/// `engine` database: the AOF itself, or a fresh LSM directory whose tables are built by
+/// writing the records to it. Existing data is kept next to it with a `.pre-restore`
+/// suffix.
+pub async fn restore(
+    archive_path: &Path,
+    path: &Path,
+    engine: EngineKind,
+) -> Result<BackupMetadata, BackupError> {
+    let (metadata, body) = read_archive(archive_path).await?;
+
+    let mut archive = tokio::fs::File::open(archive_path).await?;
+    archive.seek(SeekFrom::Start(body.start)).await?;
+    let records = tokio::io::BufReader::new(archive).take(body.end - body.start);
+
+    let tmp_path = temporary_path(path);
+    let result = match engine {
+        EngineKind::Memory => copy_records(records, &tmp_path).await,
+        EngineKind::Lsm => load_records(records, &tmp_path).await,
+    };
+    if let Err(e) = result {
+        let _ = match engine {
+            EngineKind::Memory => tokio::fs::remove_file(&tmp_path).await,
+            EngineKind::Lsm => tokio::fs::remove_dir_all(&tmp_path).await,
+        };
+        return Err(e);
+    }
+
+    if tokio::fs::try_exists(path).await? {
+        let mut previous = path.as_os_str().to_owned();
+        previous.push(".pre-restore");
+        tokio::fs::rename(path, &previous).await?;
+    }
+    tokio::fs::rename(&tmp_path, path).await?;
+
+    Ok(metadata)
+}
+
+/// Copies `records` to a new AOF at `aof_path`.
+async fn copy_records(
+    mut records: impl AsyncRead + Unpin,
+    aof_path: &Path,
+) -> Result<(), BackupError> {
+    let mut file = tokio::fs::File::create(aof_path).await?;
+    tokio::io::copy(&mut records, &mut file).await?;
+    file.sync_all().await?;
+    Ok(())
+}
+
+/// Writes `records` to an LSM engine in a new directory at `dir`, a batch at a time, so
+/// that they are flushed to tables like live writes and never held in memory at once.
+async fn load_records(records: impl AsyncRead + Unpin, dir: &Path) -> Result<(), BackupError> {
+    // A restore that was interrupted may have left its directory behind.
+    if tokio::fs::try_exists(dir).await? {
+        tokio::fs::remove_dir_all(dir).await?;
+    }
+
+    let engine = LsmEngine::open(dir.to_path_buf(), LsmOptions::default()).await?;
+    let result = async {
+        let mut reader = AofReader::new(records);
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        while let Some(record) = reader.next_record().await? {
+            let size = record.end() - record.offset();
+            let AofRecord::Command { command, .. } = record else {
+                return Err(bad_record(&record));
+            };
+            batch.push(command);
+            batch_size += size;
+
+            if batch_size >= RESTORE_BATCH_SIZE {
+                engine.write(mem::take(&mut batch)).await?;
+                batch_size = 0;
+            }
+        }
+        if !batch.is_empty() {
+            engine.write(batch).await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    engine.shutdown().await?;
+    result
+}
+
+fn encode_record(command: &Command) -> Result<Vec<u8>, BackupError> {
+    encode_frame(command).map_err(corrupt)
+}
+
+fn bad_record(record: &AofRecord) -> BackupError {
+    BackupError::Corrupt(format!("bad record at offset {}", record.offset()))
+}
+
+fn corrupt(e: impl ToString) -> BackupError {
+    BackupError::Corrupt(e.to_string())
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    PathBuf::from(tmp_path)
+}
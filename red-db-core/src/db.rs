@@ -1,268 +1,546 @@
-use std::{path::PathBuf, sync::Arc};
-
-use arc_swap::ArcSwap;
-use rpds::HashTrieMapSync;
-use tokio::{
-    fs,
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::mpsc,
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
+
 use tracing::{debug, error};
 
 use crate::{
+    backup::{self, BackupMetadata},
     error::ServerError,
-    proto::{Command, Response},
-    utils::HashedKey,
+    proto::{
+        Command, Entry, ExportCursor, ExportPage, ImportMode, ImportOptions, ImportSummary,
+        Response, SpaceConfig,
+        info::{ClientsInfo, Info, InfoSection, ServerInfo, SpaceInfo, StatsInfo},
+    },
+    storage::{
+        Engine, EngineKind, Snapshot, StorageEngine,
+        lsm::{LsmEngine, LsmOptions},
+        memory::{MemoryEngine, MemoryLimit},
+    },
 };
 
-type SpaceData = HashTrieMapSync<HashedKey, Vec<u8>>;
-type Store = HashTrieMapSync<String, SpaceData>;
+/// Approximate size of the entries in one page of `Command::Export`. A page holds at
+/// least one entry, so it always fits in a response frame.
+pub const EXPORT_PAGE_SIZE: usize = 1024 * 1024;
+
+/// How long the snapshot of an unfinished export is kept after its last page was read.
+pub const EXPORT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A database on storage engine `E`. `Db::new`, `Db::in_memory` and `Db::open` pick one
+/// of the built-in engines at runtime; other engines are plugged in with `Db::with_engine`.
+pub struct Db<E: StorageEngine = Engine> {
+    engine: Arc<E>,
+    stats: Arc<Stats>,
+    exports: Arc<Mutex<Exports<E::Snapshot>>>,
+}
+
+impl<E: StorageEngine> Clone for Db<E> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone(),
+            stats: self.stats.clone(),
+            exports: self.exports.clone(),
+        }
+    }
+}
+
+/// Snapshots of the exports that have pages left, by export id, with the time their
+/// last page was read.
+struct Exports<S> {
+    next_id: u64,
+    snapshots: HashMap<u64, (Arc<S>, Instant)>,
+}
+
+/// Counters reported by `Command::Info`.
+struct Stats {
+    started_at: Instant,
+    connected_clients: AtomicU64,
+    /// Executed commands, indexed by `Command::index`.
+    commands: [AtomicU64; Command::NAMES.len()],
+}
+
+/// Counts a client as connected until it is dropped. See `Db::track_client`.
+pub struct ClientGuard(Arc<Stats>);
 
-#[derive(Clone)]
-pub struct Db {
-    data: Arc<ArcSwap<Store>>,
-    aof_sender: mpsc::Sender<Command>,
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl Db {
+    /// Opens an in-memory database persisted to the append-only file at `aof_path`.
     pub async fn new(aof_path: PathBuf) -> Self {
-        let (aof_sender, aof_receiver) = mpsc::channel(1024);
+        Self::with_engine(Engine::Memory(MemoryEngine::open(aof_path).await))
+    }
 
-        let initial_store = Self::restore_from_aof(&aof_path).await.unwrap_or_else(|e| {
-            error!("Failed to restore from AOF: {}, starting fresh", e);
-            Store::new_sync()
-        });
+    /// Opens a database that is kept in memory only. It behaves like one opened with `new`
+    /// but writes nothing to disk; call `save_to` to persist it.
+    pub fn in_memory() -> Self {
+        Self::with_engine(Engine::Memory(MemoryEngine::ephemeral()))
+    }
 
-        tokio::spawn(aof_writer_task(aof_receiver, aof_path));
+    /// Opens a database on the `kind` engine. `path` is the file or directory it keeps its
+    /// data in.
+    pub async fn open(kind: EngineKind, path: PathBuf) -> Result<Self, ServerError> {
+        let engine = match kind {
+            EngineKind::Memory => Engine::Memory(MemoryEngine::open(path).await),
+            EngineKind::Lsm => Engine::Lsm(LsmEngine::open(path, LsmOptions::default()).await?),
+        };
 
-        Self {
-            data: Arc::new(ArcSwap::from(Arc::new(initial_store))),
-            aof_sender,
-        }
+        Ok(Self::with_engine(engine))
     }
 
-    async fn restore_from_aof(aof_path: &PathBuf) -> Result<Store, ServerError> {
-        if !aof_path.exists() {
-            return Ok(Store::new_sync());
-        }
-
-        let mut file = fs::File::open(aof_path).await.map_err(|e| {
-            error!("Failed to open AOF file: {}", e);
-            ServerError::AofReadFailed
-        })?;
-
-        let mut store = Store::new_sync();
-
-        loop {
-            let mut len_bytes = [0u8; 4];
-            match file.read_exact(&mut len_bytes).await {
-                Ok(_) => {}
-                Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
-                Err(e) => {
-                    error!("Failed to read command from AOF: {}", e);
-                    return Err(ServerError::AofReadFailed);
-                }
+    /// Limits the memory taken by the store, or lifts the limit with `None`. Only the memory
+    /// engine supports limits.
+    pub fn set_memory_limit(&self, limit: Option<MemoryLimit>) -> Result<(), ServerError> {
+        match &*self.engine {
+            Engine::Memory(engine) => {
+                engine.set_memory_limit(limit);
+                Ok(())
             }
+            Engine::Lsm(_) => Err(ServerError::StorageFailed(
+                "memory limits are only supported by the memory engine".to_string(),
+            )),
+        }
+    }
+}
 
-            let len = u32::from_le_bytes(len_bytes) as usize;
-            let mut command_bytes = vec![0u8; len];
-            file.read_exact(&mut command_bytes).await.map_err(|e| {
-                error!("Failed to read command from AOF: {}", e);
-                ServerError::AofReadFailed
-            })?;
-
-            if let Ok((command, _)) =
-                bincode::decode_from_slice(&command_bytes, bincode::config::standard())
-            {
-                store = Self::apply_command_to_store(store, &command);
-            }
+impl<E: StorageEngine> Db<E> {
+    pub fn with_engine(engine: E) -> Self {
+        Self {
+            engine: Arc::new(engine),
+            stats: Arc::new(Stats {
+                started_at: Instant::now(),
+                connected_clients: AtomicU64::new(0),
+                commands: std::array::from_fn(|_| AtomicU64::new(0)),
+            }),
+            exports: Arc::new(Mutex::new(Exports {
+                next_id: 0,
+                snapshots: HashMap::new(),
+            })),
         }
+    }
 
-        Ok(store)
+    /// Counts a server connection in `Info` for as long as the guard is held.
+    pub fn track_client(&self) -> ClientGuard {
+        self.stats.connected_clients.fetch_add(1, Ordering::Relaxed);
+        ClientGuard(self.stats.clone())
     }
 
-    fn apply_command_to_store(store: Store, command: &Command) -> Store {
-        match command {
-            Command::Set { space, key, value } => {
-                let hashed_key = HashedKey::new(key.clone());
-                let space_data = store
-                    .get(space)
-                    .cloned()
-                    .unwrap_or_else(SpaceData::new_sync);
-                let updated_space = space_data.insert(hashed_key, value.clone());
-                store.insert(space.clone(), updated_space)
-            }
-            Command::Delete { space, key } => {
-                let hashed_key = HashedKey::new(key.clone());
-                if let Some(space_data) = store.get(space) {
-                    let updated_space = space_data.remove(&hashed_key);
-                    store.insert(space.clone(), updated_space)
-                } else {
-                    store
-                }
-            }
-            Command::CreateSpace { space } => store.insert(space.clone(), SpaceData::new_sync()),
-            Command::DeleteSpace { space } => store.remove(space),
-            _ => store,
-        }
+    /// Makes every accepted write durable. Writes issued after this call fail with
+    /// `ServerError::AofWriteFailed`.
+    pub async fn shutdown(&self) -> Result<(), ServerError> {
+        self.engine.shutdown().await
     }
 
+    /// Checks what holds for every space. The limits set by a space's config are enforced
+    /// by the engine.
     fn validate_command(command: &Command) -> Result<(), ServerError> {
         match command {
-            Command::Set { key, value, .. } => {
-                if key.is_empty() {
-                    return Err(ServerError::InvalidKey("Key cannot be empty".to_string()));
-                }
-                if value.len() > 1024 * 1024 {
-                    return Err(ServerError::ValueTooLarge);
-                }
+            Command::Set { key, .. } => Self::validate_key(key),
+            Command::CreateSpace { space } => Self::validate_space_name(space),
+            Command::CreateSpaceWithConfig { space, config } => {
+                Self::validate_space_name(space)?;
+                config.validate()
             }
-            Command::CreateSpace { space } => {
-                if space.is_empty() || space.len() > 255 {
-                    return Err(ServerError::InvalidSpaceName);
-                }
-            }
-            _ => {}
+            Command::AlterSpace { config, .. } => config.validate(),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_key(key: &str) -> Result<(), ServerError> {
+        if key.is_empty() {
+            return Err(ServerError::InvalidKey("Key cannot be empty".to_string()));
+        }
+        Ok(())
+    }
+
+    fn validate_space_name(space: &str) -> Result<(), ServerError> {
+        if space.is_empty() || space.len() > 255 {
+            return Err(ServerError::InvalidSpaceName);
         }
         Ok(())
     }
 
     pub async fn execute(&self, command: Command) -> Response {
+        self.execute_filtered(command, |_| true).await
+    }
+
+    /// Like `execute`, but an `Export` of all spaces only scans the spaces for which
+    /// `readable` returns true.
+    pub async fn execute_filtered(
+        &self,
+        command: Command,
+        readable: impl Fn(&str) -> bool + Send + 'static,
+    ) -> Response {
+        self.stats.commands[command.index()].fetch_add(1, Ordering::Relaxed);
+
         match command {
             Command::Get { space, key } => {
-                let hashed_key = HashedKey::new(key.clone());
-                let db_snapshot = self.data.load();
-
-                if let Some(space_data) = db_snapshot.get(&space) {
-                    Response::Value(space_data.get(&hashed_key).cloned())
-                } else {
-                    Response::Error(ServerError::SpaceNotFound(space))
+                match self.read(move |snapshot| snapshot.get(&space, &key)).await {
+                    Ok(value) => Response::Value(value),
+                    Err(err) => Response::Error(err),
                 }
             }
             Command::ListKeys { space } => {
-                let db_snapshot = self.data.load();
-
-                if let Some(space_data) = db_snapshot.get(&space) {
-                    let keys = space_data.keys().map(|k| k.key.clone()).collect();
-                    Response::Keys(keys)
-                } else {
-                    Response::Error(ServerError::SpaceNotFound(space))
+                match self.read(move |snapshot| snapshot.keys(&space)).await {
+                    Ok(keys) => Response::Keys(keys),
+                    Err(err) => Response::Error(err),
                 }
             }
-            Command::ListSpaces => {
-                let db_snapshot = self.data.load();
-
-                let spaces = db_snapshot.keys().cloned().collect();
-                Response::Spaces(spaces)
-            }
+            Command::ListSpaces => Response::Spaces(self.engine.snapshot().spaces()),
             Command::IsSpaceExists { space } => {
-                let db_snapshot = self.data.load();
-                Response::Bool(db_snapshot.contains_key(&space))
+                Response::Bool(self.engine.snapshot().has_space(&space))
             }
+            // Embedded databases have no users; authentication is handled by the server.
+            Command::Auth { .. } => Response::Ok,
+            Command::Ping { payload } => Response::Pong(payload),
+            Command::Export { space, after } => match self.export(space, after, readable).await {
+                Ok(page) => Response::ExportPage(page),
+                Err(err) => Response::Error(err),
+            },
+            Command::Import { entries, options } => match self.import(entries, options).await {
+                Ok(summary) => Response::Imported(summary),
+                Err(err) => Response::Error(err),
+            },
+            Command::Backup { dest_path } => match self.backup(PathBuf::from(dest_path)).await {
+                Ok(metadata) => Response::Backup(metadata),
+                Err(err) => Response::Error(err),
+            },
+            Command::Info { section } => Response::Info(self.info(section)),
+            Command::SpaceInfo { space } => match self.space_info(space) {
+                Ok(info) => Response::SpaceInfo(info),
+                Err(err) => Response::Error(err),
+            },
             _ => self.handle_write(command).await,
         }
     }
 
-    async fn handle_write(&self, command: Command) -> Response {
-        if let Err(err) = Self::validate_command(&command) {
-            return Response::Error(err);
+    /// Statistics about the database, or only `section` of them. Space statistics are
+    /// taken from a single snapshot.
+    pub fn info(&self, section: Option<InfoSection>) -> Info {
+        let wants = |wanted| section.is_none_or(|section| section == wanted);
+        let mut info = Info::default();
+
+        if wants(InfoSection::Server) {
+            info.server = Some(ServerInfo {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                uptime_secs: self.stats.started_at.elapsed().as_secs(),
+            });
         }
 
-        if self.aof_sender.send(command.clone()).await.is_err() {
-            return Response::Error(ServerError::AofWriteFailed);
+        if wants(InfoSection::Clients) {
+            info.clients = Some(ClientsInfo {
+                connected: self.stats.connected_clients.load(Ordering::Relaxed),
+            });
         }
 
-        debug!("Received command: {:#?}", command);
+        if wants(InfoSection::Stats) {
+            let commands: Vec<(String, u64)> = Command::NAMES
+                .iter()
+                .zip(&self.stats.commands)
+                .map(|(name, count)| (name.to_string(), count.load(Ordering::Relaxed)))
+                .filter(|(_, count)| *count > 0)
+                .collect();
+
+            info.stats = Some(StatsInfo {
+                total_commands: commands.iter().map(|(_, count)| count).sum(),
+                commands,
+            });
+        }
 
-        loop {
-            let current_data_ptr = self.data.load();
-            let mut new_data = (**current_data_ptr).clone();
+        if wants(InfoSection::Persistence) {
+            info.persistence = Some(self.engine.persistence_info());
+        }
 
-            let result = match &command {
-                Command::Set { space, key, value } => {
-                    let hashed_key = HashedKey::new(key.clone());
+        if wants(InfoSection::Spaces) {
+            let snapshot = self.engine.snapshot();
+            let mut spaces = snapshot.spaces();
+            spaces.sort();
+
+            info.spaces = Some(
+                spaces
+                    .into_iter()
+                    .filter_map(|space| space_info(&snapshot, space).ok())
+                    .collect(),
+            );
+        }
 
-                    let space_data = new_data
-                        .get(space)
-                        .cloned()
-                        .unwrap_or_else(SpaceData::new_sync);
+        info
+    }
 
-                    let updated_space_data = space_data.insert(hashed_key, value.clone());
+    pub fn space_info(&self, space: String) -> Result<SpaceInfo, ServerError> {
+        space_info(&self.engine.snapshot(), space)
+    }
 
-                    new_data = new_data.insert(space.clone(), updated_space_data);
-                    Ok(())
-                }
-                Command::Delete { space, key } => {
-                    let hashed_key = HashedKey::new(key.clone());
-                    match new_data.get(space) {
-                        Some(space_data) => {
-                            let updated_space_data = space_data.remove(&hashed_key);
-                            new_data = new_data.insert(space.clone(), updated_space_data);
-                            Ok(())
-                        }
-                        None => return Response::Error(ServerError::SpaceNotFound(space.clone())),
+    /// The page of an export that starts after `after`. Without a `space`, only spaces
+    /// for which `readable` returns true are scanned.
+    ///
+    /// The first page pins a snapshot that the following pages are read from, so the
+    /// export sees none of the writes made while it runs. The snapshot is dropped after
+    /// the last page, or `EXPORT_TIMEOUT` after the latest one.
+    pub async fn export(
+        &self,
+        space: Option<String>,
+        after: Option<ExportCursor>,
+        readable: impl Fn(&str) -> bool + Send + 'static,
+    ) -> Result<ExportPage, ServerError> {
+        let (export_id, snapshot) = {
+            let mut exports = self.exports.lock().unwrap();
+            exports
+                .snapshots
+                .retain(|_, (_, read_at)| read_at.elapsed() < EXPORT_TIMEOUT);
+
+            match &after {
+                Some(after) => match exports.snapshots.get_mut(&after.export_id) {
+                    Some((snapshot, read_at)) => {
+                        *read_at = Instant::now();
+                        (after.export_id, snapshot.clone())
                     }
+                    None => return Err(ServerError::ExportExpired(after.export_id)),
+                },
+                None => {
+                    exports.next_id += 1;
+                    (exports.next_id, Arc::new(self.engine.snapshot()))
                 }
-                Command::DeleteSpace { space } => {
-                    if !new_data.contains_key(space) {
-                        return Response::Error(ServerError::SpaceNotFound(space.clone()));
+            }
+        };
+
+        let page = self
+            .read_from(snapshot.clone(), move |snapshot| {
+                export_page(snapshot, export_id, space, after, readable)
+            })
+            .await?;
+
+        let mut exports = self.exports.lock().unwrap();
+        if page.next.is_some() {
+            exports
+                .snapshots
+                .insert(export_id, (snapshot, Instant::now()));
+        } else {
+            exports.snapshots.remove(&export_id);
+        }
+
+        Ok(page)
+    }
+
+    /// Runs `read` on the current snapshot, on the blocking thread pool if the engine
+    /// reads from disk.
+    async fn read<T: Send + 'static>(
+        &self,
+        read: impl FnOnce(&E::Snapshot) -> Result<T, ServerError> + Send + 'static,
+    ) -> Result<T, ServerError> {
+        self.read_from(self.engine.snapshot(), read).await
+    }
+
+    /// Like `read`, on a snapshot taken earlier.
+    async fn read_from<S, T>(
+        &self,
+        snapshot: S,
+        read: impl FnOnce(&E::Snapshot) -> Result<T, ServerError> + Send + 'static,
+    ) -> Result<T, ServerError>
+    where
+        S: Borrow<E::Snapshot> + Send + 'static,
+        T: Send + 'static,
+    {
+        if !self.engine.reads_block() {
+            return read(snapshot.borrow());
+        }
+
+        tokio::task::spawn_blocking(move || read(snapshot.borrow()))
+            .await
+            .unwrap_or_else(|e| Err(ServerError::StorageFailed(e.to_string())))
+    }
+
+    /// Writes the current snapshot of the store to `dest_path`. Writes made while the
+    /// archive is being written are not included and are not blocked.
+    pub async fn backup(&self, dest_path: PathBuf) -> Result<BackupMetadata, ServerError> {
+        let snapshot = self.engine.snapshot();
+
+        let result = tokio::task::spawn_blocking(move || {
+            backup::write_archive(&dest_path, &snapshot).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        result.map_err(|e| {
+            error!("Backup failed: {}", e);
+            ServerError::BackupFailed(e)
+        })
+    }
+
+    /// Writes the current snapshot of the store to `path` as an AOF, which `Db::new` can
+    /// open. The file is replaced atomically.
+    pub async fn save_to(&self, path: PathBuf) -> Result<(), ServerError> {
+        let snapshot = self.engine.snapshot();
+
+        let result = tokio::task::spawn_blocking(move || {
+            backup::write_aof(&path, &snapshot).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        result.map_err(|e| {
+            error!("Failed to save the database: {}", e);
+            ServerError::StorageFailed(e)
+        })
+    }
+
+    /// Applies `entries` as one batch: it is validated up front and written at once.
+    /// Skipped keys are decided against the snapshot at the start of the import.
+    pub async fn import(
+        &self,
+        entries: Vec<Entry>,
+        options: ImportOptions,
+    ) -> Result<ImportSummary, ServerError> {
+        let (mut summary, writes) = self
+            .read(move |snapshot| {
+                let mut summary = ImportSummary::default();
+                let mut imported = HashSet::new();
+                let mut writes = Vec::new();
+
+                for Entry { space, key, value } in entries {
+                    if !snapshot.has_space(&space) && !summary.spaces_created.contains(&space) {
+                        Self::validate_space_name(&space)?;
+                        summary.spaces_created.push(space.clone());
                     }
-                    new_data = new_data.remove(space);
-                    Ok(())
-                }
-                Command::CreateSpace { space } => {
-                    if new_data.contains_key(space) {
-                        return Response::Error(ServerError::SpaceAlreadyExists(space.clone()));
+
+                    if options.mode == ImportMode::SkipExisting
+                        && (imported.contains(&(space.clone(), key.clone()))
+                            || matches!(snapshot.get(&space, &key), Ok(Some(_))))
+                    {
+                        summary.skipped += 1;
+                        continue;
                     }
-                    new_data = new_data.insert(space.clone(), SpaceData::new_sync());
-                    Ok(())
+
+                    Self::validate_key(&key)?;
+                    // Checked here too so that dry runs report entries the engine would
+                    // reject.
+                    snapshot
+                        .space_config(&space)
+                        .unwrap_or_default()
+                        .check_entry(&space, &key, &value)?;
+                    imported.insert((space.clone(), key.clone()));
+                    writes.push(Entry { space, key, value });
                 }
-                _ => unreachable!(),
-            };
 
-            if let Err(err) = result {
-                return Response::Error(err);
-            }
+                Ok((summary, writes))
+            })
+            .await?;
 
-            if Arc::ptr_eq(
-                &current_data_ptr,
-                &self
-                    .data
-                    .compare_and_swap(&current_data_ptr, Arc::new(new_data)),
-            ) {
-                break;
-            }
+        summary.written = writes.len() as u64;
+
+        if options.dry_run || writes.is_empty() {
+            return Ok(summary);
         }
 
-        debug!("Applied command: {:#?}", command);
+        self.engine.put_batch(writes).await?;
+        debug!("Imported {} entries", summary.written);
 
-        Response::Ok
+        Ok(summary)
     }
-}
 
-async fn aof_writer_task(mut receiver: mpsc::Receiver<Command>, aof_path: PathBuf) {
-    let mut file = match fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(aof_path)
-        .await
-    {
-        Ok(file) => file,
-        Err(e) => {
-            error!("Failed to open AOF file: {}", e);
-            return;
+    async fn handle_write(&self, command: Command) -> Response {
+        if let Err(err) = Self::validate_command(&command) {
+            return Response::Error(err);
         }
+
+        debug!("Received command: {:#?}", command);
+
+        let result = match command {
+            Command::Set { space, key, value } => self.engine.put(&space, &key, value).await,
+            Command::Delete { space, key } => self.engine.delete(&space, &key).await,
+            Command::CreateSpace { space } => {
+                self.engine
+                    .create_space(&space, SpaceConfig::default())
+                    .await
+            }
+            Command::CreateSpaceWithConfig { space, config } => {
+                self.engine.create_space(&space, config).await
+            }
+            Command::AlterSpace { space, config } => self.engine.alter_space(&space, config).await,
+            Command::DeleteSpace { space } => self.engine.delete_space(&space).await,
+            _ => unreachable!(),
+        };
+
+        match result {
+            Ok(()) => Response::Ok,
+            Err(err) => Response::Error(err),
+        }
+    }
+}
+
+/// Reads the page of export `export_id` that starts after `after` from `snapshot`.
+fn export_page(
+    snapshot: &impl Snapshot,
+    export_id: u64,
+    space: Option<String>,
+    after: Option<ExportCursor>,
+    readable: impl Fn(&str) -> bool,
+) -> Result<ExportPage, ServerError> {
+    let mut spaces = match space {
+        Some(space) => vec![space],
+        None => snapshot
+            .spaces()
+            .into_iter()
+            .filter(|space| readable(space))
+            .collect(),
     };
+    spaces.sort();
+    if let Some(after) = &after {
+        spaces.retain(|space| *space >= after.space);
+    }
 
-    while let Some(command) = receiver.recv().await {
-        if let Ok(serialized) = bincode::encode_to_vec(&command, bincode::config::standard()) {
-            let len = serialized.len() as u32;
-            if file.write_all(&len.to_le_bytes()).await.is_err()
-                || file.write_all(&serialized).await.is_err()
-                || file.flush().await.is_err()
-            {
-                error!("Failed to write command to AOF");
+    let mut entries = Vec::new();
+    let mut size = 0;
+    for space in spaces {
+        let after_key = after
+            .as_ref()
+            .filter(|after| after.space == space)
+            .map(|after| after.key.as_str());
+
+        for entry in snapshot.scan_after(&space, after_key)? {
+            let (key, value) = entry?;
+            if size >= EXPORT_PAGE_SIZE {
+                let last: &Entry = entries.last().expect("a full page has entries");
+                let next = ExportCursor {
+                    export_id,
+                    space: last.space.clone(),
+                    key: last.key.clone(),
+                };
+                return Ok(ExportPage {
+                    entries,
+                    next: Some(next),
+                });
             }
+
+            size += space.len() + key.len() + value.len() + 16;
+            entries.push(Entry {
+                space: space.clone(),
+                key,
+                value,
+            });
         }
     }
+
+    Ok(ExportPage {
+        entries,
+        next: None,
+    })
+}
+
+fn space_info(snapshot: &impl Snapshot, space: String) -> Result<SpaceInfo, ServerError> {
+    Ok(SpaceInfo {
+        usage: snapshot.space_usage(&space)?,
+        config: snapshot.space_config(&space)?,
+        name: space,
+    })
 }
@@ -19,4 +19,71 @@ pub enum ServerError {
     InvalidSpaceName,
     #[error("Value too large")]
     ValueTooLarge,
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+    #[error("Backup failed: {0}")]
+    BackupFailed(String),
+    #[error("Storage error: {0}")]
+    StorageFailed(String),
+    #[error("Out of memory: the write would exceed max_memory")]
+    OutOfMemory,
+    #[error("Space '{0}' is read-only")]
+    SpaceReadOnly(String),
+    #[error("Key is longer than {0} bytes")]
+    KeyTooLong(u64),
+    #[error("Space '{0}' holds its maximum number of keys")]
+    KeyLimitReached(String),
+    #[error("Space '{0}' would exceed its max_bytes")]
+    SpaceQuotaExceeded(String),
+    #[error("Invalid space config: {0}")]
+    InvalidSpaceConfig(String),
+    #[error("Invalid backup path: {0}")]
+    InvalidBackupPath(String),
+    #[error("Export {0} has expired, start it again")]
+    ExportExpired(u64),
+}
+
+impl ServerError {
+    /// Oldest protocol version that can carry this error.
+    pub fn protocol_version(&self) -> u32 {
+        match self {
+            ServerError::SpaceNotFound(_)
+            | ServerError::KeyNotFound(..)
+            | ServerError::SpaceAlreadyExists(_)
+            | ServerError::AofWriteFailed
+            | ServerError::AofReadFailed
+            | ServerError::InvalidKey(_)
+            | ServerError::InvalidSpaceName
+            | ServerError::ValueTooLarge
+            | ServerError::PermissionDenied(_)
+            | ServerError::AuthenticationFailed => 1,
+            ServerError::BackupFailed(_) => 4,
+            ServerError::StorageFailed(_) => 5,
+            ServerError::OutOfMemory => 6,
+            ServerError::SpaceReadOnly(_)
+            | ServerError::KeyTooLong(_)
+            | ServerError::KeyLimitReached(_)
+            | ServerError::SpaceQuotaExceeded(_)
+            | ServerError::InvalidSpaceConfig(_) => 7,
+            ServerError::InvalidBackupPath(_) => 9,
+            ServerError::ExportExpired(_) => 10,
+        }
+    }
+
+    /// The closest error a peer speaking `protocol_version` can decode.
+    pub fn for_protocol(self, protocol_version: u32) -> ServerError {
+        if self.protocol_version() <= protocol_version {
+            return self;
+        }
+
+        match self {
+            ServerError::BackupFailed(_)
+            | ServerError::StorageFailed(_)
+            | ServerError::OutOfMemory => ServerError::AofWriteFailed,
+            // Requests the server refuses, like writes a space's config forbids.
+            error => ServerError::PermissionDenied(error.to_string()),
+        }
+    }
 }
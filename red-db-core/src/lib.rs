@@ -1,6 +1,9 @@
+pub mod aof;
+pub mod backup;
 pub mod db;
 pub mod error;
 pub mod proto;
+pub mod storage;
 #[cfg(test)]
 mod tests;
 mod utils;
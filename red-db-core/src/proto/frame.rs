@@ -0,0 +1,76 @@
+use bincode::{Decode, Encode};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest command frame a server accepts.
+pub const MAX_REQUEST_SIZE: usize = 1024 * 1024 + 4096;
+/// Largest response frame a client accepts.
+pub const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("Connection error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Frame of {0} bytes is too large")]
+    TooLarge(usize),
+    #[error("Encode error: {0}")]
+    Encode(String),
+    #[error("Decode error: {0}")]
+    Decode(String),
+}
+
+/// Encodes `value` as a little-endian `u32` length followed by its bincode payload.
+pub fn encode_frame<T: Encode>(value: &T) -> Result<Vec<u8>, FrameError> {
+    let data = bincode::encode_to_vec(value, bincode::config::standard())
+        .map_err(|e| FrameError::Encode(e.to_string()))?;
+
+    let mut frame = Vec::with_capacity(data.len() + 4);
+    frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&data);
+
+    Ok(frame)
+}
+
+pub fn decode_payload<T: Decode<()>>(payload: &[u8]) -> Result<T, FrameError> {
+    bincode::decode_from_slice(payload, bincode::config::standard())
+        .map(|(value, _)| value)
+        .map_err(|e| FrameError::Decode(e.to_string()))
+}
+
+/// Reads one frame. Returns `Ok(None)` if the peer closed the stream between frames.
+pub async fn read_frame<T, R>(reader: &mut R, max_len: usize) -> Result<Option<T>, FrameError>
+where
+    T: Decode<()>,
+    R: AsyncRead + Unpin,
+{
+    let mut len_buf = [0u8; 4];
+    match reader.read_exact(&mut len_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(FrameError::Io(e)),
+    }
+
+    let len = u32::from_le_bytes(len_buf) as usize;
+
+    if len > max_len {
+        return Err(FrameError::TooLarge(len));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    decode_payload(&payload).map(Some)
+}
+
+pub async fn write_frame<T, W>(writer: &mut W, value: &T) -> Result<(), FrameError>
+where
+    T: Encode,
+    W: AsyncWrite + Unpin,
+{
+    let frame = encode_frame(value)?;
+
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
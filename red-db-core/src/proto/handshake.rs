@@ -0,0 +1,58 @@
+use bincode::{Decode, Encode};
+
+/// Sent by the client before its first frame so a server can tell red-db peers
+/// from anything else that connects to the port.
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"RDB\x01";
+
+/// Version of the `Request`/`Reply` wire format. Bump it whenever `Command`,
+/// `Response` or `ServerError` change in a way older peers can't decode, and record
+/// the version new variants need in `Command::protocol_version` and
+/// `ServerError::protocol_version`.
+///
+/// 1. The format the handshake was introduced with.
+/// 2. `Ping` and `Pong`.
+/// 3. `Export` and `Import`.
+/// 4. `Backup` and `BackupFailed`.
+/// 5. `StorageFailed`.
+/// 6. `OutOfMemory`.
+/// 7. `AlterSpace`, `CreateSpaceWithConfig` and the errors of space limits.
+/// 8. `Info` and `SpaceInfo`.
+/// 9. `InvalidBackupPath`.
+/// 10. `ExportExpired`.
+pub const PROTOCOL_VERSION: u32 = 10;
+/// Oldest version this build can still speak.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+pub const FEATURE_PIPELINING: &str = "pipelining";
+pub const FEATURE_AUTH: &str = "auth";
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub client_name: String,
+    pub features: Vec<String>,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum HelloReply {
+    Accepted {
+        protocol_version: u32,
+        server_version: String,
+        capabilities: Vec<String>,
+    },
+    Rejected {
+        reason: String,
+        protocol_version: u32,
+    },
+}
+
+/// Picks the protocol version both peers understand, or explains why there is none.
+pub fn negotiate(peer_version: u32) -> Result<u32, String> {
+    if peer_version < MIN_PROTOCOL_VERSION {
+        return Err(format!(
+            "protocol version {peer_version} is no longer supported, expected at least {MIN_PROTOCOL_VERSION}"
+        ));
+    }
+
+    Ok(peer_version.min(PROTOCOL_VERSION))
+}
@@ -0,0 +1,64 @@
+use bincode::{Decode, Encode};
+
+use super::{SpaceConfig, SpaceUsage};
+
+/// A part of `Info`. `Command::Info` without a section returns all of them.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoSection {
+    Server,
+    Clients,
+    Stats,
+    Persistence,
+    Spaces,
+}
+
+/// Answer to `Command::Info`. Sections that were not asked for are `None`.
+#[derive(Encode, Decode, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+    pub server: Option<ServerInfo>,
+    pub clients: Option<ClientsInfo>,
+    pub stats: Option<StatsInfo>,
+    pub persistence: Option<PersistenceInfo>,
+    pub spaces: Option<Vec<SpaceInfo>>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub version: String,
+    /// Seconds since the database was opened.
+    pub uptime_secs: u64,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct ClientsInfo {
+    /// Open server connections over every protocol. Always 0 for an embedded database.
+    pub connected: u64,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct StatsInfo {
+    pub total_commands: u64,
+    /// Commands executed by type, named as in `Command::NAMES`. Types that never ran are
+    /// left out.
+    pub commands: Vec<(String, u64)>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct PersistenceInfo {
+    /// `memory` or `lsm`.
+    pub engine: String,
+    /// Size in bytes of the AOF, or of the LSM engine's write-ahead log. 0 when nothing is
+    /// kept on disk.
+    pub aof_size: u64,
+    pub last_write_error: Option<String>,
+    /// Approximate size of the store, for the memory engine.
+    pub used_memory: Option<u64>,
+}
+
+/// Answer to `Command::SpaceInfo`, taken from a snapshot of the store.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct SpaceInfo {
+    pub name: String,
+    pub usage: SpaceUsage,
+    pub config: SpaceConfig,
+}
@@ -1,6 +1,14 @@
+pub mod frame;
+pub mod handshake;
+pub mod info;
+
 use bincode::{Decode, Encode};
 
-use crate::error::ServerError;
+use crate::{
+    backup::BackupMetadata,
+    error::ServerError,
+    proto::info::{Info, InfoSection, SpaceInfo},
+};
 
 #[derive(Encode, Decode, Debug, Clone)]
 pub enum Command {
@@ -25,12 +33,231 @@ pub enum Command {
     DeleteSpace {
         space: String,
     },
+    /// Creates `space` with the default config.
     CreateSpace {
         space: String,
     },
     IsSpaceExists {
         space: String,
     },
+
+    Auth {
+        username: String,
+        password: String,
+    },
+
+    /// Answered with `Response::Pong` echoing `payload`.
+    Ping {
+        payload: Vec<u8>,
+    },
+
+    /// A page of the entries of `space`, or of all spaces, in order of space and key,
+    /// starting after `after`. Every page of an export is read from the snapshot its
+    /// first page was read from.
+    Export {
+        space: Option<String>,
+        after: Option<ExportCursor>,
+    },
+    /// Writes `entries` in one batch, creating missing spaces.
+    Import {
+        entries: Vec<Entry>,
+        options: ImportOptions,
+    },
+    /// Writes a snapshot of the store to `dest_path` on the server as a backup archive.
+    Backup {
+        dest_path: String,
+    },
+    /// Replaces the config of `space`. Entries already over a new limit are kept.
+    AlterSpace {
+        space: String,
+        config: SpaceConfig,
+    },
+    /// Statistics about the database, or only the given section of them.
+    Info {
+        section: Option<InfoSection>,
+    },
+    SpaceInfo {
+        space: String,
+    },
+    CreateSpaceWithConfig {
+        space: String,
+        config: SpaceConfig,
+    },
+}
+
+impl Command {
+    /// Commands that never modify the store. These are safe to run concurrently and to retry.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Get { .. }
+                | Command::ListSpaces
+                | Command::ListKeys { .. }
+                | Command::IsSpaceExists { .. }
+                | Command::Ping { .. }
+                | Command::Export { .. }
+                | Command::Backup { .. }
+                | Command::Info { .. }
+                | Command::SpaceInfo { .. }
+        )
+    }
+
+    /// Oldest protocol version that can carry this command. Clients don't send commands
+    /// newer than the version they negotiated, and servers close connections that do.
+    pub fn protocol_version(&self) -> u32 {
+        match self {
+            Command::Get { .. }
+            | Command::Set { .. }
+            | Command::Delete { .. }
+            | Command::ListSpaces
+            | Command::ListKeys { .. }
+            | Command::DeleteSpace { .. }
+            | Command::CreateSpace { .. }
+            | Command::IsSpaceExists { .. }
+            | Command::Auth { .. } => 1,
+            Command::Ping { .. } => 2,
+            Command::Export { .. } | Command::Import { .. } => 3,
+            Command::Backup { .. } => 4,
+            Command::AlterSpace { .. } | Command::CreateSpaceWithConfig { .. } => 7,
+            Command::Info { .. } | Command::SpaceInfo { .. } => 8,
+        }
+    }
+
+    /// Names of the command types, indexed by `Command::index`.
+    pub const NAMES: [&'static str; 16] = [
+        "get",
+        "set",
+        "delete",
+        "list_spaces",
+        "list_keys",
+        "delete_space",
+        "create_space",
+        "is_space_exists",
+        "auth",
+        "ping",
+        "export",
+        "import",
+        "backup",
+        "alter_space",
+        "info",
+        "space_info",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.index()]
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        match self {
+            Command::Get { .. } => 0,
+            Command::Set { .. } => 1,
+            Command::Delete { .. } => 2,
+            Command::ListSpaces => 3,
+            Command::ListKeys { .. } => 4,
+            Command::DeleteSpace { .. } => 5,
+            Command::CreateSpace { .. } | Command::CreateSpaceWithConfig { .. } => 6,
+            Command::IsSpaceExists { .. } => 7,
+            Command::Auth { .. } => 8,
+            Command::Ping { .. } => 9,
+            Command::Export { .. } => 10,
+            Command::Import { .. } => 11,
+            Command::Backup { .. } => 12,
+            Command::AlterSpace { .. } => 13,
+            Command::Info { .. } => 14,
+            Command::SpaceInfo { .. } => 15,
+        }
+    }
+}
+
+/// Largest value a space accepts when its config sets no `max_value_size`.
+pub const DEFAULT_MAX_VALUE_SIZE: u64 = 1024 * 1024;
+
+/// Limits of a space, stored with it. Sizes are in bytes of keys and values; unset limits
+/// don't apply.
+#[derive(Encode, Decode, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpaceConfig {
+    /// Defaults to `DEFAULT_MAX_VALUE_SIZE`, which is also the largest value a request
+    /// can carry.
+    pub max_value_size: Option<u64>,
+    pub max_key_length: Option<u64>,
+    pub max_keys: Option<u64>,
+    /// Limit on the total size of the keys and values in the space.
+    pub max_bytes: Option<u64>,
+    /// Stored with the space for clients to read. Keys have no time to live yet, so it
+    /// doesn't make them expire.
+    pub default_ttl_ms: Option<u64>,
+    /// Rejects sets and deletes. The space can still be altered or deleted.
+    pub read_only: bool,
+}
+
+impl SpaceConfig {
+    /// Rejects limits that could never be reached because a request can't carry them.
+    pub fn validate(&self) -> Result<(), ServerError> {
+        if self
+            .max_value_size
+            .is_some_and(|max| max > DEFAULT_MAX_VALUE_SIZE)
+        {
+            return Err(ServerError::InvalidSpaceConfig(format!(
+                "max_value_size can be at most {DEFAULT_MAX_VALUE_SIZE}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a write of `key` and `value` to `space` against the limits that don't
+    /// depend on what the space already holds.
+    pub fn check_entry(&self, space: &str, key: &str, value: &[u8]) -> Result<(), ServerError> {
+        if self.read_only {
+            return Err(ServerError::SpaceReadOnly(space.to_string()));
+        }
+        if let Some(max) = self.max_key_length
+            && key.len() as u64 > max
+        {
+            return Err(ServerError::KeyTooLong(max));
+        }
+        if value.len() as u64 > self.max_value_size.unwrap_or(DEFAULT_MAX_VALUE_SIZE) {
+            return Err(ServerError::ValueTooLarge);
+        }
+        Ok(())
+    }
+
+    /// Checks what a write does to the usage of `space`. Only growth is limited, so a
+    /// space left over a lowered limit can still shrink.
+    pub fn check_usage(
+        &self,
+        space: &str,
+        before: SpaceUsage,
+        after: SpaceUsage,
+    ) -> Result<(), ServerError> {
+        if after.keys > before.keys && self.max_keys.is_some_and(|max| after.keys > max) {
+            return Err(ServerError::KeyLimitReached(space.to_string()));
+        }
+        if after.bytes > before.bytes && self.max_bytes.is_some_and(|max| after.bytes > max) {
+            return Err(ServerError::SpaceQuotaExceeded(space.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// What a space holds, counted against its `SpaceConfig`.
+#[derive(Encode, Decode, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpaceUsage {
+    pub keys: u64,
+    /// Total size of the keys and values.
+    pub bytes: u64,
+}
+
+/// A command tagged with a client-chosen id, so replies can be matched when pipelining.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct Request {
+    pub id: u64,
+    pub command: Command,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct Reply {
+    pub id: u64,
+    pub response: Response,
 }
 
 #[derive(Encode, Decode, Debug, Clone)]
@@ -41,6 +268,67 @@ pub enum Response {
     Spaces(Vec<String>),
     Bool(bool),
     Error(ServerError),
+    Pong(Vec<u8>),
+    ExportPage(ExportPage),
+    Imported(ImportSummary),
+    Backup(BackupMetadata),
+    Info(Info),
+    SpaceInfo(SpaceInfo),
+}
+
+impl Response {
+    /// Replaces errors a peer speaking `protocol_version` can't decode. Other responses
+    /// only answer commands of their own version, so they pass through unchanged.
+    pub fn for_protocol(self, protocol_version: u32) -> Response {
+        match self {
+            Response::Error(e) => Response::Error(e.for_protocol(protocol_version)),
+            response => response,
+        }
+    }
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub space: String,
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
+/// Where the next page of an export starts: after `key` in `space`, in the snapshot the
+/// server keeps for export `export_id`.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct ExportCursor {
+    pub export_id: u64,
+    pub space: String,
+    pub key: String,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct ExportPage {
+    pub entries: Vec<Entry>,
+    /// `None` on the last page.
+    pub next: Option<ExportCursor>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    #[default]
+    Overwrite,
+    SkipExisting,
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, Default)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// Report what would be written without changing anything.
+    pub dry_run: bool,
+}
+
+#[derive(Encode, Decode, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub written: u64,
+    pub skipped: u64,
+    pub spaces_created: Vec<String>,
 }
 
 impl From<ServerError> for Response {
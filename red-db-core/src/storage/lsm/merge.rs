@@ -0,0 +1,55 @@
+use std::{io, iter::Peekable};
+
+use super::{InternalKey, Value};
+
+pub(super) type Source<'a> = Box<dyn Iterator<Item = io::Result<(InternalKey, Value)>> + 'a>;
+
+/// Merges sorted sources into one sorted stream. Sources are given newest first; when
+/// several hold the same key, the newest entry wins and the others are skipped.
+/// Tombstones are passed through. The stream ends after the first error.
+pub(super) struct MergeIter<'a> {
+    sources: Vec<Peekable<Source<'a>>>,
+    failed: bool,
+}
+
+impl<'a> MergeIter<'a> {
+    pub(super) fn new(sources: Vec<Source<'a>>) -> Self {
+        Self {
+            sources: sources.into_iter().map(Iterator::peekable).collect(),
+            failed: false,
+        }
+    }
+}
+
+impl Iterator for MergeIter<'_> {
+    type Item = io::Result<(InternalKey, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let mut newest: Option<(usize, InternalKey)> = None;
+        for index in 0..self.sources.len() {
+            match self.sources[index].peek() {
+                Some(Ok((key, _))) if newest.as_ref().is_none_or(|(_, min)| key < min) => {
+                    newest = Some((index, key.clone()));
+                }
+                Some(Err(_)) => {
+                    self.failed = true;
+                    return self.sources[index].next();
+                }
+                _ => {}
+            }
+        }
+
+        let (index, key) = newest?;
+        let entry = self.sources[index].next();
+
+        for source in &mut self.sources[index + 1..] {
+            source.next_if(|entry| matches!(entry, Ok((other, _)) if *other == key));
+        }
+
+        entry
+    }
+}
@@ -0,0 +1,1155 @@
+mod merge;
+mod sstable;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use bincode::{Decode, Encode};
+use rpds::{HashTrieMapSync, HashTrieSetSync, RedBlackTreeMapSync};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    aof::{AofReader, AofRecord},
+    error::ServerError,
+    proto::{Command, Entry, SpaceConfig, SpaceUsage, frame::encode_frame, info::PersistenceInfo},
+    storage::{
+        ScanIter, Snapshot, StorageEngine,
+        lsm::{
+            merge::{MergeIter, Source},
+            sstable::{Table, TableBuilder, table_path},
+        },
+    },
+};
+
+/// Keys are stored under the id of their space. Ids are never reused, so deleting a space
+/// only drops it from the catalog; its entries are discarded by later compactions.
+type InternalKey = (u64, String);
+/// `None` is a tombstone that hides older entries of the key.
+type Value = Option<Vec<u8>>;
+type Levels = Vec<Vec<Arc<Table>>>;
+
+const MANIFEST_FILE: &str = "MANIFEST";
+/// How long the background thread waits before retrying a failed flush or compaction.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct LsmOptions {
+    /// Approximate size of the memtable before it is flushed to a table.
+    pub memtable_size: usize,
+    pub block_size: usize,
+    /// Compactions split their output into tables of about this size.
+    pub table_size: u64,
+    /// Number of level 0 tables that triggers a compaction into level 1.
+    pub l0_compaction_trigger: usize,
+    /// Maximum size of level 1. Every further level may grow ten times larger.
+    pub level_base_size: u64,
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        Self {
+            memtable_size: 4 * 1024 * 1024,
+            block_size: 4 * 1024,
+            table_size: 2 * 1024 * 1024,
+            l0_compaction_trigger: 4,
+            level_base_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// A log-structured merge tree kept in a directory. Writes go to a write-ahead log in the
+/// AOF format and to a sorted in-memory table. Once the memtable grows past
+/// `LsmOptions::memtable_size` it is frozen and a new one is started with a new log, while
+/// a background thread flushes the frozen one to an immutable table on disk and merges
+/// tables into non-overlapping levels by leveled compaction. Writes wait if the new
+/// memtable fills up before the flush is done. The `MANIFEST` file lists the live tables
+/// and the oldest log still needed.
+///
+/// All file I/O runs on the blocking thread pool or the background thread.
+pub struct LsmEngine {
+    inner: Arc<Inner>,
+    /// `None` once the engine has been shut down.
+    background: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct Inner {
+    dir: PathBuf,
+    options: LsmOptions,
+    version: ArcSwap<Version>,
+    writer: Mutex<Writer>,
+    /// Wakes the background thread when `Writer::work_pending` or `Writer::stopping` is set.
+    work: Condvar,
+    /// Wakes writers waiting for a flush.
+    flushed: Condvar,
+    wal_size: AtomicU64,
+    last_error: ArcSwapOption<String>,
+}
+
+pub struct LsmSnapshot(Arc<Version>);
+
+#[derive(Clone)]
+struct Version {
+    spaces: HashTrieMapSync<String, SpaceMeta>,
+    next_space_id: u64,
+    memtable: Memtable,
+    /// A full memtable the background thread is writing to a table.
+    immutable: Option<Arc<Frozen>>,
+    /// Level 0 holds flushed memtables, oldest first, which may overlap. Every other
+    /// level is sorted by key and its tables don't overlap.
+    levels: Arc<Levels>,
+}
+
+#[derive(Clone, Default)]
+struct Memtable {
+    entries: RedBlackTreeMapSync<InternalKey, Value>,
+    /// Keys first written here without knowing whether a table holds an older value. The
+    /// usage of their space counts them as new until the flush of the memtable corrects it.
+    unresolved: HashTrieSetSync<InternalKey>,
+    size: usize,
+}
+
+/// A full memtable with the catalog as it was when the memtable was frozen, which is what
+/// the manifest records once the memtable is in a table.
+struct Frozen {
+    memtable: Memtable,
+    spaces: HashTrieMapSync<String, SpaceMeta>,
+    next_space_id: u64,
+    /// The log holding the writes of the memtable.
+    wal_id: u64,
+}
+
+#[derive(Encode, Decode, Clone)]
+struct SpaceMeta {
+    id: u64,
+    config: SpaceConfig,
+    usage: SpaceUsage,
+}
+
+struct Writer {
+    /// `None` once the engine has been shut down.
+    wal: Option<File>,
+    wal_id: u64,
+    work_pending: bool,
+    stopping: bool,
+}
+
+#[derive(Encode, Decode, Default, Clone)]
+struct Manifest {
+    /// Logs from this one on are replayed at startup.
+    wal_id: u64,
+    next_table_id: u64,
+    next_space_id: u64,
+    spaces: Vec<(String, SpaceMeta)>,
+    levels: Vec<Vec<u64>>,
+}
+
+impl LsmEngine {
+    pub async fn open(dir: PathBuf, options: LsmOptions) -> Result<Self, ServerError> {
+        fs::create_dir_all(&dir).map_err(storage_error)?;
+
+        let mut manifest: Manifest = match fs::read(dir.join(MANIFEST_FILE)) {
+            Ok(bytes) => {
+                bincode::decode_from_slice(&bytes, bincode::config::standard())
+                    .map_err(|e| ServerError::StorageFailed(format!("invalid manifest: {e}")))?
+                    .0
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(storage_error(e)),
+        };
+
+        let mut levels = Levels::new();
+        let mut live_tables = HashSet::new();
+        for level in &manifest.levels {
+            let mut tables = Vec::new();
+            for &id in level {
+                let table = Table::open(table_path(&dir, id), id).map_err(storage_error)?;
+                tables.push(Arc::new(table));
+                live_tables.insert(id);
+            }
+            levels.push(tables);
+        }
+        remove_stale_files(&dir, &live_tables, manifest.wal_id);
+
+        let mut version = Version {
+            spaces: manifest.spaces.iter().cloned().collect(),
+            next_space_id: manifest.next_space_id,
+            memtable: Memtable::default(),
+            immutable: None,
+            levels: Arc::new(levels),
+        };
+
+        // A crash during a flush leaves the log of the frozen memtable next to the newer one.
+        let mut wal_id = manifest.wal_id;
+        let mut replayed = replay_wal(
+            &dir,
+            wal_id,
+            &options,
+            &mut version,
+            &mut manifest.next_table_id,
+        )
+        .await?;
+        while wal_path(&dir, wal_id + 1).exists() {
+            wal_id += 1;
+            replayed |= replay_wal(
+                &dir,
+                wal_id,
+                &options,
+                &mut version,
+                &mut manifest.next_table_id,
+            )
+            .await?;
+        }
+
+        // Starting from a fresh log drops a torn record at the end of the old one. The
+        // replayed writes are flushed by the background thread.
+        if replayed {
+            version.immutable = Some(Arc::new(Frozen {
+                memtable: mem::take(&mut version.memtable),
+                spaces: version.spaces.clone(),
+                next_space_id: version.next_space_id,
+                wal_id,
+            }));
+            wal_id += 1;
+        }
+
+        let wal = File::options()
+            .create(true)
+            .append(true)
+            .open(wal_path(&dir, wal_id))
+            .map_err(storage_error)?;
+
+        let inner = Arc::new(Inner {
+            dir,
+            options,
+            version: ArcSwap::from_pointee(version),
+            writer: Mutex::new(Writer {
+                wal: Some(wal),
+                wal_id,
+                work_pending: replayed,
+                stopping: false,
+            }),
+            work: Condvar::new(),
+            flushed: Condvar::new(),
+            wal_size: AtomicU64::new(0),
+            last_error: ArcSwapOption::empty(),
+        });
+
+        let background = thread::Builder::new()
+            .name("lsm-background".to_string())
+            .spawn({
+                let inner = inner.clone();
+                move || inner.run_background(manifest)
+            })
+            .map_err(storage_error)?;
+
+        Ok(Self {
+            inner,
+            background: Mutex::new(Some(background)),
+        })
+    }
+
+    /// Runs `Inner::write` on the blocking thread pool.
+    pub(crate) async fn write(&self, commands: Vec<Command>) -> Result<(), ServerError> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || inner.write(&commands))
+            .await
+            .unwrap_or_else(|e| {
+                error!("Write task failed: {}", e);
+                Err(ServerError::AofWriteFailed)
+            })
+    }
+}
+
+impl Drop for LsmEngine {
+    fn drop(&mut self) {
+        if let Some(background) = self.background.get_mut().unwrap().take() {
+            self.inner.stop_background(background);
+        }
+    }
+}
+
+impl Inner {
+    /// Applies `commands` to a copy of the current version and logs them. If any command
+    /// fails, nothing is logged or applied.
+    fn write(&self, commands: &[Command]) -> Result<(), ServerError> {
+        let writer = self.writer.lock().unwrap();
+        // Waiting while both memtables are full keeps memory bounded when flushes fall
+        // behind.
+        let mut writer = self
+            .flushed
+            .wait_while(writer, |writer| {
+                let version = self.version.load();
+                writer.wal.is_some()
+                    && version.immutable.is_some()
+                    && version.memtable.size >= self.options.memtable_size
+            })
+            .unwrap();
+
+        let mut version = Version::clone(&self.version.load());
+        for command in commands {
+            version.apply(command)?;
+        }
+
+        let mut records = Vec::new();
+        for command in commands {
+            records.extend(encode_frame(command).map_err(|e| {
+                error!("Failed to encode command: {}", e);
+                ServerError::AofWriteFailed
+            })?);
+        }
+
+        let wal = writer.wal.as_mut().ok_or(ServerError::AofWriteFailed)?;
+        if let Err(e) = wal.write_all(&records) {
+            error!("Failed to write to the write-ahead log: {}", e);
+            self.last_error.store(Some(Arc::new(format!(
+                "Failed to write to the write-ahead log: {e}"
+            ))));
+            return Err(ServerError::AofWriteFailed);
+        }
+        self.wal_size
+            .fetch_add(records.len() as u64, Ordering::Relaxed);
+
+        // The write is already durable in the log, so a failed freeze is retried by the
+        // next write.
+        if version.memtable.size >= self.options.memtable_size
+            && version.immutable.is_none()
+            && let Err(e) = self.freeze(&mut writer, &mut version)
+        {
+            error!("Failed to start a new write-ahead log: {}", e);
+            self.last_error.store(Some(Arc::new(format!(
+                "Failed to start a new write-ahead log: {e}"
+            ))));
+        }
+
+        self.version.store(Arc::new(version));
+        Ok(())
+    }
+
+    /// Hands the memtable of `version` to the background thread and starts a new log for
+    /// the next one.
+    fn freeze(&self, writer: &mut Writer, version: &mut Version) -> io::Result<()> {
+        if let Some(wal) = &writer.wal {
+            wal.sync_all()?;
+        }
+        let wal_id = writer.wal_id + 1;
+        let wal = File::options()
+            .create(true)
+            .append(true)
+            .open(wal_path(&self.dir, wal_id))?;
+
+        version.immutable = Some(Arc::new(Frozen {
+            memtable: mem::take(&mut version.memtable),
+            spaces: version.spaces.clone(),
+            next_space_id: version.next_space_id,
+            wal_id: writer.wal_id,
+        }));
+
+        writer.wal = Some(wal);
+        writer.wal_id = wal_id;
+        writer.work_pending = true;
+        self.wal_size.store(0, Ordering::Relaxed);
+        self.work.notify_one();
+        Ok(())
+    }
+
+    /// Flushes frozen memtables and compacts until the engine stops. `manifest` is the
+    /// last one written.
+    fn run_background(&self, mut manifest: Manifest) {
+        let mut writer = self.writer.lock().unwrap();
+        loop {
+            writer = self
+                .work
+                .wait_while(writer, |writer| !writer.work_pending && !writer.stopping)
+                .unwrap();
+            if !writer.work_pending {
+                return;
+            }
+            writer.work_pending = false;
+            drop(writer);
+
+            let result = self
+                .flush(&mut manifest)
+                .and_then(|()| self.compact(&mut manifest));
+            if let Err(e) = &result {
+                error!("Failed to flush memtable: {}", e);
+                self.last_error
+                    .store(Some(Arc::new(format!("Failed to flush memtable: {e}"))));
+            }
+
+            writer = self.writer.lock().unwrap();
+            if result.is_err() {
+                // The logs still hold every write, so giving up on shutdown loses nothing.
+                if writer.stopping {
+                    return;
+                }
+                writer.work_pending = true;
+                writer = self.work.wait_timeout(writer, RETRY_DELAY).unwrap().0;
+            }
+        }
+    }
+
+    /// Tells the background thread to stop once it has no work left and waits for it.
+    fn stop_background(&self, background: JoinHandle<()>) {
+        self.writer.lock().unwrap().stopping = true;
+        self.work.notify_one();
+        if background.join().is_err() {
+            error!("LSM background thread panicked");
+        }
+    }
+
+    /// Writes the frozen memtable to a level 0 table and publishes it. The usage of the
+    /// spaces it wrote to is corrected for the unresolved keys, which are looked up in the
+    /// older tables here rather than on the write path.
+    fn flush(&self, manifest: &mut Manifest) -> io::Result<()> {
+        let version = self.version.load_full();
+        let Some(frozen) = version.immutable.clone() else {
+            return Ok(());
+        };
+        let mut levels = Levels::clone(&version.levels);
+        let mut next_table_id = manifest.next_table_id;
+        let corrections = flush_memtable(
+            &self.dir,
+            self.options.block_size,
+            &frozen.memtable,
+            &frozen.spaces,
+            &mut levels,
+            &mut next_table_id,
+        )?;
+
+        let flushed = Manifest {
+            wal_id: frozen.wal_id + 1,
+            next_table_id,
+            next_space_id: frozen.next_space_id,
+            spaces: correct_usage(&frozen.spaces, &corrections)
+                .iter()
+                .map(|(space, meta)| (space.clone(), meta.clone()))
+                .collect(),
+            levels: table_ids(&levels),
+        };
+        write_manifest(&self.dir, &flushed)?;
+
+        {
+            let _writer = self.writer.lock().unwrap();
+            let current = self.version.load_full();
+            self.version.store(Arc::new(Version {
+                spaces: correct_usage(&current.spaces, &corrections),
+                immutable: None,
+                levels: Arc::new(levels),
+                ..Version::clone(&current)
+            }));
+        }
+        self.flushed.notify_all();
+
+        for wal_id in manifest.wal_id..flushed.wal_id {
+            let _ = fs::remove_file(wal_path(&self.dir, wal_id));
+        }
+        *manifest = flushed;
+        Ok(())
+    }
+
+    /// Runs compactions until level 0 is below its trigger and every level is within its
+    /// size limit, then publishes the new levels.
+    fn compact(&self, manifest: &mut Manifest) -> io::Result<()> {
+        let version = self.version.load_full();
+        let live_spaces: HashSet<u64> = version.spaces.values().map(|meta| meta.id).collect();
+        let mut levels = Levels::clone(&version.levels);
+        let mut next_table_id = manifest.next_table_id;
+
+        let obsolete = self.compact_levels(&mut levels, &live_spaces, &mut next_table_id)?;
+        if obsolete.is_empty() {
+            return Ok(());
+        }
+
+        let compacted = Manifest {
+            next_table_id,
+            levels: table_ids(&levels),
+            ..manifest.clone()
+        };
+        write_manifest(&self.dir, &compacted)?;
+
+        {
+            let _writer = self.writer.lock().unwrap();
+            let current = self.version.load_full();
+            self.version.store(Arc::new(Version {
+                levels: Arc::new(levels),
+                ..Version::clone(&current)
+            }));
+        }
+        *manifest = compacted;
+
+        for table in obsolete {
+            table.mark_obsolete();
+        }
+        Ok(())
+    }
+
+    /// Returns the tables that were replaced.
+    fn compact_levels(
+        &self,
+        levels: &mut Levels,
+        live_spaces: &HashSet<u64>,
+        next_table_id: &mut u64,
+    ) -> io::Result<Vec<Arc<Table>>> {
+        let mut obsolete = Vec::new();
+
+        loop {
+            let (level, inputs) = if levels
+                .first()
+                .is_some_and(|l0| l0.len() >= self.options.l0_compaction_trigger)
+            {
+                // Newest first, so the merge keeps the latest entries.
+                (0, levels[0].iter().rev().cloned().collect::<Vec<_>>())
+            } else if let Some(level) = (1..levels.len())
+                .find(|&level| level_size(&levels[level]) > self.level_limit(level))
+            {
+                (level, vec![levels[level][0].clone()])
+            } else {
+                return Ok(obsolete);
+            };
+
+            let output_level = level + 1;
+            if levels.len() <= output_level {
+                levels.push(Vec::new());
+            }
+
+            let first = inputs.iter().map(|table| table.first_key()).min().unwrap();
+            let last = inputs.iter().map(|table| table.last_key()).max().unwrap();
+            let overlapping: Vec<Arc<Table>> = levels[output_level]
+                .iter()
+                .filter(|table| table.overlaps(first, last))
+                .cloned()
+                .collect();
+
+            // Tombstones only have to be kept while older entries may exist below.
+            let bottom = levels[output_level + 1..].iter().all(Vec::is_empty);
+
+            let start = (0, String::new());
+            let sources = inputs
+                .iter()
+                .chain(&overlapping)
+                .map(|table| Box::new(table.iter_from(&start)) as Source)
+                .collect();
+
+            let mut outputs = Vec::new();
+            let mut builder: Option<TableBuilder> = None;
+            for entry in MergeIter::new(sources) {
+                let (key, value) = entry?;
+                if !live_spaces.contains(&key.0) || (bottom && value.is_none()) {
+                    continue;
+                }
+
+                let current = match &mut builder {
+                    Some(builder) => builder,
+                    None => {
+                        let id = *next_table_id;
+                        *next_table_id += 1;
+                        builder.insert(TableBuilder::create(
+                            &self.dir,
+                            id,
+                            self.options.block_size,
+                        )?)
+                    }
+                };
+                current.add(key, &value)?;
+
+                if current.estimated_size() >= self.options.table_size
+                    && let Some(table) = builder.take().unwrap().finish()?
+                {
+                    outputs.push(Arc::new(table));
+                }
+            }
+            if let Some(builder) = builder
+                && let Some(table) = builder.finish()?
+            {
+                outputs.push(Arc::new(table));
+            }
+
+            debug!(
+                "Compacted {} tables from level {} into {} tables in level {}",
+                inputs.len() + overlapping.len(),
+                level,
+                outputs.len(),
+                output_level
+            );
+
+            let replaced: HashSet<u64> = inputs
+                .iter()
+                .chain(&overlapping)
+                .map(|table| table.id)
+                .collect();
+            for tables in [level, output_level] {
+                levels[tables].retain(|table| !replaced.contains(&table.id));
+            }
+            levels[output_level].extend(outputs);
+            levels[output_level].sort_by(|a, b| a.first_key().cmp(b.first_key()));
+
+            obsolete.extend(inputs);
+            obsolete.extend(overlapping);
+        }
+    }
+
+    fn level_limit(&self, level: usize) -> u64 {
+        self.options
+            .level_base_size
+            .saturating_mul(10u64.saturating_pow(level as u32 - 1))
+    }
+}
+
+impl StorageEngine for LsmEngine {
+    type Snapshot = LsmSnapshot;
+
+    fn snapshot(&self) -> LsmSnapshot {
+        LsmSnapshot(self.inner.version.load_full())
+    }
+
+    fn reads_block(&self) -> bool {
+        true
+    }
+
+    async fn put(&self, space: &str, key: &str, value: Vec<u8>) -> Result<(), ServerError> {
+        self.write(vec![Command::Set {
+            space: space.to_string(),
+            key: key.to_string(),
+            value,
+        }])
+        .await
+    }
+
+    async fn delete(&self, space: &str, key: &str) -> Result<(), ServerError> {
+        self.write(vec![Command::Delete {
+            space: space.to_string(),
+            key: key.to_string(),
+        }])
+        .await
+    }
+
+    async fn create_space(&self, space: &str, config: SpaceConfig) -> Result<(), ServerError> {
+        self.write(vec![Command::CreateSpaceWithConfig {
+            space: space.to_string(),
+            config,
+        }])
+        .await
+    }
+
+    async fn alter_space(&self, space: &str, config: SpaceConfig) -> Result<(), ServerError> {
+        self.write(vec![Command::AlterSpace {
+            space: space.to_string(),
+            config,
+        }])
+        .await
+    }
+
+    async fn delete_space(&self, space: &str) -> Result<(), ServerError> {
+        self.write(vec![Command::DeleteSpace {
+            space: space.to_string(),
+        }])
+        .await
+    }
+
+    async fn put_batch(&self, entries: Vec<Entry>) -> Result<(), ServerError> {
+        let commands: Vec<Command> = entries
+            .into_iter()
+            .map(|Entry { space, key, value }| Command::Set { space, key, value })
+            .collect();
+
+        self.write(commands).await
+    }
+
+    /// Waits for a flush in progress, so that the directory can be reopened right away.
+    async fn shutdown(&self) -> Result<(), ServerError> {
+        let Some(background) = self.background.lock().unwrap().take() else {
+            return Ok(());
+        };
+
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let wal = inner.writer.lock().unwrap().wal.take();
+            inner.flushed.notify_all();
+            inner.stop_background(background);
+
+            if let Some(wal) = wal {
+                wal.sync_all().map_err(|e| {
+                    error!("Failed to sync the write-ahead log: {}", e);
+                    ServerError::AofWriteFailed
+                })?;
+            }
+            debug!("LSM engine stopped");
+            Ok(())
+        })
+        .await
+        .unwrap_or_else(|e| {
+            error!("Shutdown task failed: {}", e);
+            Err(ServerError::AofWriteFailed)
+        })
+    }
+
+    fn persistence_info(&self) -> PersistenceInfo {
+        PersistenceInfo {
+            engine: "lsm".to_string(),
+            aof_size: self.inner.wal_size.load(Ordering::Relaxed),
+            last_write_error: self.inner.last_error.load_full().map(|e| e.to_string()),
+            used_memory: None,
+        }
+    }
+}
+
+impl Snapshot for LsmSnapshot {
+    fn spaces(&self) -> Vec<String> {
+        self.0.spaces.keys().cloned().collect()
+    }
+
+    fn has_space(&self, space: &str) -> bool {
+        self.0.spaces.contains_key(space)
+    }
+
+    fn space_config(&self, space: &str) -> Result<SpaceConfig, ServerError> {
+        Ok(self.space(space)?.config.clone())
+    }
+
+    fn space_usage(&self, space: &str) -> Result<SpaceUsage, ServerError> {
+        Ok(self.space(space)?.usage)
+    }
+
+    fn get(&self, space: &str, key: &str) -> Result<Option<Vec<u8>>, ServerError> {
+        let key = (self.space(space)?.id, key.to_string());
+        self.0.lookup(&key).map_err(storage_error)
+    }
+
+    fn scan(&self, space: &str) -> Result<ScanIter<'_>, ServerError> {
+        self.scan_after(space, None)
+    }
+
+    fn scan_after(&self, space: &str, after: Option<&str>) -> Result<ScanIter<'_>, ServerError> {
+        let id = self.space(space)?.id;
+        let first = (id, after.unwrap_or_default().to_string());
+        let last = (id + 1, String::new());
+
+        let mut sources: Vec<Source> = Vec::new();
+        for memtable in [
+            Some(&self.0.memtable),
+            self.0.immutable.as_ref().map(|frozen| &frozen.memtable),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            sources.push(Box::new(
+                memtable
+                    .entries
+                    .range(first.clone()..last.clone())
+                    .map(|(key, value)| Ok((key.clone(), value.clone()))),
+            ));
+        }
+
+        let levels = &self.0.levels;
+        if let Some(l0) = levels.first() {
+            for table in l0.iter().rev() {
+                if table.overlaps(&first, &last) {
+                    sources.push(Box::new(table.iter_from(&first)));
+                }
+            }
+        }
+        for level in levels.iter().skip(1) {
+            let tables: Vec<Arc<Table>> = level
+                .iter()
+                .filter(|table| table.overlaps(&first, &last))
+                .cloned()
+                .collect();
+            let first = first.clone();
+            sources.push(Box::new(
+                tables
+                    .into_iter()
+                    .flat_map(move |table| table.iter_from(&first)),
+            ));
+        }
+
+        let after = after.map(str::to_string);
+        Ok(Box::new(
+            MergeIter::new(sources)
+                .take_while(move |entry| !matches!(entry, Ok((key, _)) if key.0 != id))
+                .filter(
+                    move |entry| !matches!(entry, Ok((key, _)) if Some(&key.1) == after.as_ref()),
+                )
+                .filter_map(|entry| match entry {
+                    Ok(((_, key), Some(value))) => Some(Ok((key, value))),
+                    Ok((_, None)) => None,
+                    Err(e) => Some(Err(storage_error(e))),
+                }),
+        ))
+    }
+}
+
+impl LsmSnapshot {
+    fn space(&self, space: &str) -> Result<&SpaceMeta, ServerError> {
+        self.0
+            .spaces
+            .get(space)
+            .ok_or_else(|| ServerError::SpaceNotFound(space.to_string()))
+    }
+}
+
+impl Memtable {
+    fn insert(&mut self, key: InternalKey, value: Value, resolved: bool) {
+        self.size += key.1.len() + value.as_ref().map_or(0, Vec::len) + 32;
+        if !resolved {
+            self.unresolved.insert_mut(key.clone());
+        }
+        self.entries.insert_mut(key, value);
+    }
+}
+
+impl Version {
+    /// The newest value of `key`, or `None` if it is missing or deleted.
+    fn lookup(&self, key: &InternalKey) -> io::Result<Option<Vec<u8>>> {
+        if let Some(value) = self.memtable.entries.get(key) {
+            return Ok(value.clone());
+        }
+        if let Some(frozen) = &self.immutable
+            && let Some(value) = frozen.memtable.entries.get(key)
+        {
+            return Ok(value.clone());
+        }
+
+        Ok(lookup_tables(&self.levels, key)?.flatten())
+    }
+
+    /// The size of the value `key` holds before a write, and whether it is known. Tables
+    /// are only read for spaces with a key or byte limit, and only when no bloom filter
+    /// rules the key out. Otherwise a key missing from the memtables is counted as new and
+    /// left unresolved.
+    fn previous_size(
+        &self,
+        key: &InternalKey,
+        config: &SpaceConfig,
+    ) -> io::Result<(Option<usize>, bool)> {
+        let size = |value: &Value| value.as_ref().map(Vec::len);
+
+        if let Some(value) = self.memtable.entries.get(key) {
+            return Ok((size(value), true));
+        }
+        if let Some(frozen) = &self.immutable
+            && let Some(value) = frozen.memtable.entries.get(key)
+        {
+            return Ok((size(value), true));
+        }
+        if !self
+            .levels
+            .iter()
+            .flatten()
+            .any(|table| table.may_contain(key))
+        {
+            return Ok((None, true));
+        }
+        if config.max_keys.is_none() && config.max_bytes.is_none() {
+            return Ok((None, false));
+        }
+
+        Ok((
+            lookup_tables(&self.levels, key)?
+                .flatten()
+                .map(|value| value.len()),
+            true,
+        ))
+    }
+
+    /// Applies a write command, enforcing the config of its space. Used for live writes
+    /// and for log replay.
+    fn apply(&mut self, command: &Command) -> Result<(), ServerError> {
+        match command {
+            Command::Delete { space, .. }
+            | Command::DeleteSpace { space }
+            | Command::AlterSpace { space, .. }
+                if !self.spaces.contains_key(space) =>
+            {
+                return Err(ServerError::SpaceNotFound(space.clone()));
+            }
+            Command::CreateSpace { space } | Command::CreateSpaceWithConfig { space, .. }
+                if self.spaces.contains_key(space) =>
+            {
+                return Err(ServerError::SpaceAlreadyExists(space.clone()));
+            }
+            Command::Delete { space, .. } if self.spaces[space].config.read_only => {
+                return Err(ServerError::SpaceReadOnly(space.clone()));
+            }
+            Command::Set { space, key, value } => {
+                let mut meta = match self.spaces.get(space) {
+                    Some(meta) => meta.clone(),
+                    None => self.new_space(SpaceConfig::default()),
+                };
+                meta.config.check_entry(space, key, value)?;
+
+                let key = (meta.id, key.clone());
+                let before = meta.usage;
+                let (previous, resolved) = self
+                    .previous_size(&key, &meta.config)
+                    .map_err(storage_error)?;
+                if let Some(previous) = previous {
+                    meta.usage.keys -= 1;
+                    meta.usage.bytes -= (key.1.len() + previous) as u64;
+                }
+                meta.usage.keys += 1;
+                meta.usage.bytes += (key.1.len() + value.len()) as u64;
+                meta.config.check_usage(space, before, meta.usage)?;
+
+                self.spaces.insert_mut(space.clone(), meta);
+                self.memtable.insert(key, Some(value.clone()), resolved);
+            }
+            Command::Delete { space, key } => {
+                let mut meta = self.spaces[space].clone();
+                let key = (meta.id, key.clone());
+                let (previous, resolved) = self
+                    .previous_size(&key, &meta.config)
+                    .map_err(storage_error)?;
+                if let Some(previous) = previous {
+                    meta.usage.keys -= 1;
+                    meta.usage.bytes -= (key.1.len() + previous) as u64;
+                    self.spaces.insert_mut(space.clone(), meta);
+                }
+                self.memtable.insert(key, None, resolved);
+            }
+            Command::CreateSpace { space } => {
+                let meta = self.new_space(SpaceConfig::default());
+                self.spaces.insert_mut(space.clone(), meta);
+            }
+            Command::CreateSpaceWithConfig { space, config } => {
+                let meta = self.new_space(config.clone());
+                self.spaces.insert_mut(space.clone(), meta);
+            }
+            Command::AlterSpace { space, config } => {
+                let mut meta = self.spaces[space].clone();
+                meta.config = config.clone();
+                self.spaces.insert_mut(space.clone(), meta);
+            }
+            Command::DeleteSpace { space } => {
+                self.spaces.remove_mut(space);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Allocates the id of a new space. The caller adds it to `spaces`.
+    fn new_space(&mut self, config: SpaceConfig) -> SpaceMeta {
+        let id = self.next_space_id;
+        self.next_space_id += 1;
+        SpaceMeta {
+            id,
+            config,
+            usage: SpaceUsage::default(),
+        }
+    }
+}
+
+/// Writes the entries of `memtable` in the `spaces` that still exist to a new level 0
+/// table. Returns the usage corrections for its unresolved keys, which are looked up in
+/// the tables that were there before.
+fn flush_memtable(
+    dir: &Path,
+    block_size: usize,
+    memtable: &Memtable,
+    spaces: &HashTrieMapSync<String, SpaceMeta>,
+    levels: &mut Levels,
+    next_table_id: &mut u64,
+) -> io::Result<HashMap<u64, SpaceUsage>> {
+    let live_spaces: HashSet<u64> = spaces.values().map(|meta| meta.id).collect();
+
+    let mut corrections: HashMap<u64, SpaceUsage> = HashMap::new();
+    for key in memtable.unresolved.iter() {
+        if let Some(Some(previous)) = lookup_tables(levels, key)? {
+            let correction = corrections.entry(key.0).or_default();
+            correction.keys += 1;
+            correction.bytes += (key.1.len() + previous.len()) as u64;
+        }
+    }
+
+    if !memtable.entries.is_empty() {
+        let mut builder = TableBuilder::create(dir, *next_table_id, block_size)?;
+        *next_table_id += 1;
+
+        for (key, value) in memtable.entries.iter() {
+            if live_spaces.contains(&key.0) {
+                builder.add(key.clone(), value)?;
+            }
+        }
+
+        if let Some(table) = builder.finish()? {
+            debug!("Flushed memtable to table {}", table.id);
+            if levels.is_empty() {
+                levels.push(Vec::new());
+            }
+            levels[0].push(Arc::new(table));
+        }
+    }
+
+    Ok(corrections)
+}
+
+/// `Ok(None)` if no table has an entry for `key`, `Ok(Some(None))` for a tombstone.
+fn lookup_tables(levels: &Levels, key: &InternalKey) -> io::Result<Option<Value>> {
+    if let Some(l0) = levels.first() {
+        for table in l0.iter().rev() {
+            if let Some(value) = table.get(key)? {
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    for level in levels.iter().skip(1) {
+        let position = level.partition_point(|table| table.last_key() < key);
+        if let Some(table) = level.get(position)
+            && let Some(value) = table.get(key)?
+        {
+            return Ok(Some(value));
+        }
+    }
+
+    Ok(None)
+}
+
+/// `spaces` with `corrections` taken off the usage of the spaces with those ids.
+fn correct_usage(
+    spaces: &HashTrieMapSync<String, SpaceMeta>,
+    corrections: &HashMap<u64, SpaceUsage>,
+) -> HashTrieMapSync<String, SpaceMeta> {
+    let mut corrected = spaces.clone();
+    for (space, meta) in spaces.iter() {
+        if let Some(correction) = corrections.get(&meta.id) {
+            let mut meta = meta.clone();
+            meta.usage.keys = meta.usage.keys.saturating_sub(correction.keys);
+            meta.usage.bytes = meta.usage.bytes.saturating_sub(correction.bytes);
+            corrected.insert_mut(space.clone(), meta);
+        }
+    }
+    corrected
+}
+
+/// Replays log `wal_id` into `version`. The log is streamed, and whenever the memtable grows past
+/// `LsmOptions::memtable_size` it is written to a level 0 table, so logs larger than memory
+/// can be replayed. These tables only enter the manifest with the flush of the replayed
+/// writes; until then a crash leaves them stale and the logs are replayed again. Returns
+/// whether the log held any records.
+async fn replay_wal(
+    dir: &Path,
+    wal_id: u64,
+    options: &LsmOptions,
+    version: &mut Version,
+    next_table_id: &mut u64,
+) -> Result<bool, ServerError> {
+    let path = wal_path(dir, wal_id);
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(storage_error(e)),
+    };
+    let len = file.metadata().await.map_err(storage_error)?.len();
+
+    let mut reader = AofReader::new(tokio::io::BufReader::new(file));
+    let mut records = 0;
+    while let Some(record) = reader.next_record().await.map_err(storage_error)? {
+        match record {
+            AofRecord::Command { command, .. } => {
+                // Commands that failed when they were issued were never logged, but a
+                // failure here must not stop recovery.
+                if let Err(e) = version.apply(&command) {
+                    warn!("Skipping write-ahead log record: {}", e);
+                }
+                if version.memtable.size >= options.memtable_size {
+                    let corrections = flush_memtable(
+                        dir,
+                        options.block_size,
+                        &version.memtable,
+                        &version.spaces,
+                        Arc::make_mut(&mut version.levels),
+                        next_table_id,
+                    )
+                    .map_err(storage_error)?;
+                    version.spaces = correct_usage(&version.spaces, &corrections);
+                    version.memtable = Memtable::default();
+                }
+            }
+            AofRecord::Undecodable { offset, error, .. } => {
+                warn!("Skipping undecodable log record at {}: {}", offset, error);
+            }
+            record => {
+                warn!(
+                    "Write-ahead log ends with a partial record at {}",
+                    record.offset()
+                );
+            }
+        }
+        records += 1;
+    }
+
+    if records > 0 {
+        info!("Replayed {} records from {}", records, path.display());
+    }
+    Ok(len > 0)
+}
+
+fn write_manifest(dir: &Path, manifest: &Manifest) -> io::Result<()> {
+    let bytes = bincode::encode_to_vec(manifest, bincode::config::standard())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let tmp_path = dir.join(format!("{MANIFEST_FILE}.tmp"));
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, dir.join(MANIFEST_FILE))
+}
+
+/// Removes tables left behind by an interrupted flush or compaction and logs that are
+/// already in tables.
+fn remove_stale_files(dir: &Path, live_tables: &HashSet<u64>, wal_id: u64) {
+    let Ok(files) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for file in files.flatten() {
+        let path = file.path();
+        let id = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok());
+        let stale = match (id, path.extension().and_then(|ext| ext.to_str())) {
+            (Some(id), Some("sst")) => !live_tables.contains(&id),
+            (Some(id), Some("wal")) => id < wal_id,
+            _ => false,
+        };
+
+        if stale {
+            debug!("Removing stale file {}", path.display());
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn wal_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{id:06}.wal"))
+}
+
+fn table_ids(levels: &Levels) -> Vec<Vec<u64>> {
+    levels
+        .iter()
+        .map(|level| level.iter().map(|table| table.id).collect())
+        .collect()
+}
+
+fn level_size(tables: &[Arc<Table>]) -> u64 {
+    tables.iter().map(|table| table.size).sum()
+}
+
+fn storage_error(e: io::Error) -> ServerError {
+    error!("Storage error: {}", e);
+    ServerError::StorageFailed(e.to_string())
+}
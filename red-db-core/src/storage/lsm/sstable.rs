@@ -0,0 +1,411 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use bincode::{Decode, Encode};
+
+use super::{InternalKey, Value};
+
+/// Last bytes of every table: offset and length of the metadata block, then this magic.
+const TABLE_MAGIC: &[u8; 8] = b"RDBSST01";
+const FOOTER_SIZE: u64 = 8 + 8 + 8;
+const CHECKSUM_SIZE: usize = 4;
+const BLOOM_BITS_PER_KEY: usize = 10;
+const BLOOM_HASHES: u32 = 7;
+
+#[derive(Encode, Decode)]
+struct BlockHandle {
+    last_key: InternalKey,
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Encode, Decode)]
+struct TableMeta {
+    first_key: InternalKey,
+    index: Vec<BlockHandle>,
+    bloom: Bloom,
+}
+
+/// An immutable, sorted run of entries on disk. Entries are packed into checksummed
+/// blocks; the block index and a bloom filter are kept in memory while the table is open.
+pub(super) struct Table {
+    pub(super) id: u64,
+    pub(super) size: u64,
+    path: PathBuf,
+    file: Mutex<File>,
+    meta: TableMeta,
+    obsolete: AtomicBool,
+}
+
+impl Table {
+    pub(super) fn open(path: PathBuf, id: u64) -> io::Result<Self> {
+        let mut file = File::open(&path)?;
+        let size = file.metadata()?.len();
+        if size < FOOTER_SIZE {
+            return Err(invalid_data("table is too short"));
+        }
+
+        let mut footer = [0u8; FOOTER_SIZE as usize];
+        file.seek(SeekFrom::Start(size - FOOTER_SIZE))?;
+        file.read_exact(&mut footer)?;
+        if &footer[16..] != TABLE_MAGIC {
+            return Err(invalid_data("not a table"));
+        }
+
+        let meta_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let meta_len = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+        let meta_bytes = read_block(&mut file, meta_offset, meta_len)?;
+        let (meta, _) = bincode::decode_from_slice(&meta_bytes, bincode::config::standard())
+            .map_err(|e| invalid_data(&format!("invalid table metadata: {e}")))?;
+
+        Ok(Self {
+            id,
+            size,
+            path,
+            file: Mutex::new(file),
+            meta,
+            obsolete: AtomicBool::new(false),
+        })
+    }
+
+    pub(super) fn first_key(&self) -> &InternalKey {
+        &self.meta.first_key
+    }
+
+    pub(super) fn last_key(&self) -> &InternalKey {
+        &self
+            .meta
+            .index
+            .last()
+            .expect("tables are never empty")
+            .last_key
+    }
+
+    pub(super) fn overlaps(&self, first: &InternalKey, last: &InternalKey) -> bool {
+        self.first_key() <= last && self.last_key() >= first
+    }
+
+    /// Whether the table may hold an entry for `key`, decided without reading it.
+    pub(super) fn may_contain(&self, key: &InternalKey) -> bool {
+        key >= self.first_key() && key <= self.last_key() && self.meta.bloom.may_contain(key)
+    }
+
+    /// `Ok(None)` if the table has no entry for `key`, `Ok(Some(None))` for a tombstone.
+    pub(super) fn get(&self, key: &InternalKey) -> io::Result<Option<Value>> {
+        if !self.may_contain(key) {
+            return Ok(None);
+        }
+
+        let block = self
+            .meta
+            .index
+            .partition_point(|handle| handle.last_key < *key);
+        if block == self.meta.index.len() {
+            return Ok(None);
+        }
+
+        let entries = self.read_entries(block)?;
+        Ok(entries
+            .binary_search_by(|(entry_key, _)| entry_key.cmp(key))
+            .ok()
+            .map(|position| entries[position].1.clone()))
+    }
+
+    /// Entries from the first one at or after `start`, in key order.
+    pub(super) fn iter_from(self: &Arc<Self>, start: &InternalKey) -> TableIter {
+        TableIter {
+            table: self.clone(),
+            block: self
+                .meta
+                .index
+                .partition_point(|handle| handle.last_key < *start),
+            start: Some(start.clone()),
+            entries: Vec::new().into_iter(),
+            failed: false,
+        }
+    }
+
+    /// The file is removed once the last reader lets go of the table.
+    pub(super) fn mark_obsolete(&self) {
+        self.obsolete.store(true, Ordering::Release);
+    }
+
+    fn read_entries(&self, block: usize) -> io::Result<Vec<(InternalKey, Value)>> {
+        let handle = &self.meta.index[block];
+        let bytes = {
+            let mut file = self.file.lock().unwrap();
+            read_block(&mut file, handle.offset, handle.len)?
+        };
+        decode_entries(&bytes)
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        if self.obsolete.load(Ordering::Acquire) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+pub(super) struct TableIter {
+    table: Arc<Table>,
+    block: usize,
+    start: Option<InternalKey>,
+    entries: std::vec::IntoIter<(InternalKey, Value)>,
+    failed: bool,
+}
+
+impl Iterator for TableIter {
+    type Item = io::Result<(InternalKey, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            if self.failed || self.block >= self.table.meta.index.len() {
+                return None;
+            }
+
+            let mut entries = match self.table.read_entries(self.block) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            };
+            self.block += 1;
+
+            if let Some(start) = self.start.take() {
+                let skip = entries.partition_point(|(key, _)| *key < start);
+                entries.drain(..skip);
+            }
+            self.entries = entries.into_iter();
+        }
+    }
+}
+
+/// Writes a table from entries added in strictly increasing key order.
+pub(super) struct TableBuilder {
+    id: u64,
+    path: PathBuf,
+    file: BufWriter<File>,
+    block_size: usize,
+    block: Vec<u8>,
+    offset: u64,
+    first_key: Option<InternalKey>,
+    last_key: Option<InternalKey>,
+    index: Vec<BlockHandle>,
+    hashes: Vec<u64>,
+}
+
+impl TableBuilder {
+    pub(super) fn create(dir: &Path, id: u64, block_size: usize) -> io::Result<Self> {
+        let path = table_path(dir, id);
+        let file = File::create(&path)?;
+
+        Ok(Self {
+            id,
+            path,
+            file: BufWriter::new(file),
+            block_size,
+            block: Vec::with_capacity(block_size),
+            offset: 0,
+            first_key: None,
+            last_key: None,
+            index: Vec::new(),
+            hashes: Vec::new(),
+        })
+    }
+
+    pub(super) fn add(&mut self, key: InternalKey, value: &Value) -> io::Result<()> {
+        encode_entry(&mut self.block, &key, value);
+        self.hashes.push(hash_key(&key));
+        if self.first_key.is_none() {
+            self.first_key = Some(key.clone());
+        }
+        self.last_key = Some(key);
+
+        if self.block.len() >= self.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn estimated_size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    /// Writes the metadata and footer and opens the finished table. Returns `None` and
+    /// removes the file if no entries were added.
+    pub(super) fn finish(mut self) -> io::Result<Option<Table>> {
+        let Some(first_key) = self.first_key.take() else {
+            drop(self.file);
+            fs::remove_file(&self.path)?;
+            return Ok(None);
+        };
+
+        if !self.block.is_empty() {
+            self.finish_block()?;
+        }
+
+        let meta = TableMeta {
+            first_key,
+            index: self.index,
+            bloom: Bloom::new(&self.hashes),
+        };
+        let meta_bytes = bincode::encode_to_vec(&meta, bincode::config::standard())
+            .map_err(|e| invalid_data(&e.to_string()))?;
+        let meta_offset = self.offset;
+        let meta_len = write_block(&mut self.file, &meta_bytes)?;
+
+        self.file.write_all(&meta_offset.to_le_bytes())?;
+        self.file.write_all(&meta_len.to_le_bytes())?;
+        self.file.write_all(TABLE_MAGIC)?;
+        self.file.into_inner()?.sync_all()?;
+
+        Table::open(self.path, self.id).map(Some)
+    }
+
+    fn finish_block(&mut self) -> io::Result<()> {
+        let len = write_block(&mut self.file, &self.block)?;
+        self.index.push(BlockHandle {
+            last_key: self.last_key.clone().expect("blocks are never empty"),
+            offset: self.offset,
+            len,
+        });
+        self.offset += len;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+pub(super) fn table_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{id:06}.sst"))
+}
+
+#[derive(Encode, Decode)]
+struct Bloom {
+    bits: Vec<u8>,
+}
+
+impl Bloom {
+    fn new(hashes: &[u64]) -> Self {
+        let bit_count = (hashes.len() * BLOOM_BITS_PER_KEY).max(64);
+        let mut bits = vec![0u8; bit_count.div_ceil(8)];
+        let bit_count = bits.len() as u64 * 8;
+
+        for &hash in hashes {
+            for probe in probes(hash) {
+                let bit = probe % bit_count;
+                bits[(bit / 8) as usize] |= 1 << (bit % 8);
+            }
+        }
+
+        Self { bits }
+    }
+
+    fn may_contain(&self, key: &InternalKey) -> bool {
+        let bit_count = self.bits.len() as u64 * 8;
+        probes(hash_key(key)).all(|probe| {
+            let bit = probe % bit_count;
+            self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+        })
+    }
+}
+
+fn probes(hash: u64) -> impl Iterator<Item = u64> {
+    let delta = hash.rotate_left(32) | 1;
+    (0..BLOOM_HASHES as u64).map(move |i| hash.wrapping_add(i.wrapping_mul(delta)))
+}
+
+/// FNV-1a, which is stable across builds and platforms as bloom filters are persisted.
+fn hash_key((space_id, key): &InternalKey) -> u64 {
+    space_id
+        .to_le_bytes()
+        .iter()
+        .chain(key.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Entry layout: space id (`u64`), key length (`u32`), key, then a tombstone flag byte
+/// followed by the value length (`u32`) and value for live entries.
+fn encode_entry(buf: &mut Vec<u8>, (space_id, key): &InternalKey, value: &Value) {
+    buf.extend_from_slice(&space_id.to_le_bytes());
+    buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    buf.extend_from_slice(key.as_bytes());
+    match value {
+        Some(value) => {
+            buf.push(1);
+            buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            buf.extend_from_slice(value);
+        }
+        None => buf.push(0),
+    }
+}
+
+fn decode_entries(mut bytes: &[u8]) -> io::Result<Vec<(InternalKey, Value)>> {
+    fn take<'a>(bytes: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+        if bytes.len() < len {
+            return Err(invalid_data("truncated block entry"));
+        }
+        let (taken, rest) = bytes.split_at(len);
+        *bytes = rest;
+        Ok(taken)
+    }
+
+    let mut entries = Vec::new();
+    while !bytes.is_empty() {
+        let space_id = u64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap());
+        let key_len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap()) as usize;
+        let key = String::from_utf8(take(&mut bytes, key_len)?.to_vec())
+            .map_err(|_| invalid_data("key is not UTF-8"))?;
+        let value = match take(&mut bytes, 1)?[0] {
+            0 => None,
+            _ => {
+                let len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap()) as usize;
+                Some(take(&mut bytes, len)?.to_vec())
+            }
+        };
+        entries.push(((space_id, key), value));
+    }
+
+    Ok(entries)
+}
+
+/// Writes `payload` followed by its CRC32 and returns the number of bytes written.
+fn write_block(file: &mut impl Write, payload: &[u8]) -> io::Result<u64> {
+    file.write_all(payload)?;
+    file.write_all(&crc32fast::hash(payload).to_le_bytes())?;
+    Ok((payload.len() + CHECKSUM_SIZE) as u64)
+}
+
+fn read_block(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    if (len as usize) < CHECKSUM_SIZE {
+        return Err(invalid_data("block is too short"));
+    }
+
+    let mut block = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut block)?;
+
+    let checksum = block.split_off(block.len() - CHECKSUM_SIZE);
+    if crc32fast::hash(&block).to_le_bytes()[..] != checksum[..] {
+        return Err(invalid_data("block checksum mismatch"));
+    }
+    Ok(block)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,816 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use rpds::{HashTrieMapSync, RedBlackTreeSetSync};
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+    sync::{Mutex, mpsc, oneshot},
+};
+use tracing::{debug, error, warn};
+
+use crate::{
+    aof::{AofReader, AofRecord},
+    error::ServerError,
+    proto::{Command, Entry, SpaceConfig, SpaceUsage, frame::encode_frame, info::PersistenceInfo},
+    storage::{ScanIter, Snapshot, StorageEngine},
+    utils::HashedKey,
+};
+
+type SpaceData = HashTrieMapSync<HashedKey, Arc<StoredValue>>;
+
+/// Approximate bookkeeping cost of an entry on top of its key and value.
+const ENTRY_OVERHEAD: usize = 64;
+const SPACE_OVERHEAD: usize = 128;
+
+#[derive(Clone)]
+struct Store {
+    spaces: HashTrieMapSync<String, Space>,
+    /// Approximate size of every key, value and space in the store.
+    used_memory: usize,
+    /// Kept while a memory limit with an evicting policy is set.
+    eviction_index: Option<EvictionIndex>,
+}
+
+#[derive(Clone)]
+struct Space {
+    entries: SpaceData,
+    config: SpaceConfig,
+    /// Total size of the keys and values in `entries`.
+    bytes: u64,
+}
+
+/// A value with its access statistics. The statistics are shared by every version of the
+/// store that holds the value, so reads can update them without a write.
+struct StoredValue {
+    value: Vec<u8>,
+    /// Reading of the engine's logical clock at the last access.
+    last_access: AtomicU64,
+    hits: AtomicU32,
+}
+
+/// Where a key stands in the eviction order; lower ranks are evicted first.
+type Rank = (u64, u64);
+
+/// The keys of writable spaces, ordered by how soon `policy` evicts them. Reads raise the
+/// rank of a key without a write, so an entry can rank a key lower than it should but
+/// never higher; eviction re-ranks such entries when it reaches them.
+#[derive(Clone)]
+struct EvictionIndex {
+    policy: EvictionPolicy,
+    order: RedBlackTreeSetSync<(Rank, String, String)>,
+    ranks: HashTrieMapSync<(String, String), Rank>,
+}
+
+/// What to do when a write would take the store past `MemoryLimit::max_memory`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used keys.
+    AllKeysLru,
+    /// Evict the least frequently used keys.
+    AllKeysLfu,
+    /// Evict the keys closest to expiring. Keys have no time to live yet, so nothing is
+    /// evicted and writes are rejected as with `NoEviction`.
+    VolatileTtl,
+    /// Evict random keys.
+    Random,
+    /// Reject writes that need more memory with `ServerError::OutOfMemory`.
+    #[default]
+    NoEviction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimit {
+    /// Approximate size in bytes of keys, values and spaces.
+    pub max_memory: usize,
+    pub policy: EvictionPolicy,
+}
+
+/// Kept up to date by the AOF writer for `Command::Info`.
+#[derive(Default)]
+struct AofStatus {
+    size: AtomicU64,
+    last_error: ArcSwapOption<String>,
+}
+
+impl AofStatus {
+    fn fail(&self, message: String) {
+        error!("{}", message);
+        self.last_error.store(Some(Arc::new(message)));
+    }
+}
+
+enum AofMessage {
+    Commands(Vec<Command>),
+    Shutdown(oneshot::Sender<Result<(), ServerError>>),
+}
+
+/// Keeps every space in a persistent hash map that is swapped atomically on each write.
+/// Writes are logged to an append-only file and replayed from it on startup, unless the
+/// engine is ephemeral.
+pub struct MemoryEngine {
+    data: ArcSwap<Store>,
+    /// Held while a write is applied and logged, so writes reach the AOF in the order
+    /// they were applied.
+    write_lock: Mutex<()>,
+    /// `None` for an ephemeral engine, which keeps nothing on disk.
+    aof_sender: Option<mpsc::Sender<AofMessage>>,
+    shut_down: AtomicBool,
+    limit: ArcSwapOption<MemoryLimit>,
+    /// Logical clock that orders accesses for LRU eviction.
+    clock: Arc<AtomicU64>,
+    aof_status: Arc<AofStatus>,
+}
+
+pub struct MemorySnapshot {
+    store: Arc<Store>,
+    clock: Arc<AtomicU64>,
+}
+
+impl MemoryEngine {
+    pub async fn open(aof_path: PathBuf) -> Self {
+        let (aof_sender, aof_receiver) = mpsc::channel(1024);
+
+        let initial_store = restore_from_aof(&aof_path).await.unwrap_or_else(|e| {
+            error!("Failed to restore from AOF: {}, starting fresh", e);
+            Store::new()
+        });
+
+        let aof_status = Arc::new(AofStatus::default());
+        if let Ok(metadata) = fs::metadata(&aof_path).await {
+            aof_status.size.store(metadata.len(), Ordering::Relaxed);
+        }
+        tokio::spawn(aof_writer_task(aof_receiver, aof_path, aof_status.clone()));
+
+        Self::with_store(initial_store, Some(aof_sender), aof_status)
+    }
+
+    /// An engine without an AOF. Its data is lost when it is dropped.
+    pub fn ephemeral() -> Self {
+        Self::with_store(Store::new(), None, Arc::default())
+    }
+
+    fn with_store(
+        store: Store,
+        aof_sender: Option<mpsc::Sender<AofMessage>>,
+        aof_status: Arc<AofStatus>,
+    ) -> Self {
+        Self {
+            data: ArcSwap::from_pointee(store),
+            write_lock: Mutex::new(()),
+            aof_sender,
+            shut_down: AtomicBool::new(false),
+            limit: ArcSwapOption::empty(),
+            clock: Arc::default(),
+            aof_status,
+        }
+    }
+
+    /// Limits the approximate memory taken by the store. Takes effect with the next write;
+    /// a store already over the limit only shrinks as keys are evicted or deleted.
+    pub fn set_memory_limit(&self, limit: Option<MemoryLimit>) {
+        self.limit.store(limit.map(Arc::new));
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.data.load().used_memory
+    }
+
+    /// Applies `commands` to the current store, logs them and publishes the result as one
+    /// step, one write at a time. Commands that fail are neither logged nor applied. Keys
+    /// evicted to make room are logged as deletes ahead of them.
+    async fn write(&self, commands: Vec<Command>) -> Result<(), ServerError> {
+        let _guard = self.write_lock.lock().await;
+
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+        let limit = self.limit.load_full();
+        let mut current_data = Store::clone(&self.data.load());
+        current_data.index_for(limit.as_deref().map(|limit| limit.policy));
+        let mut new_data = apply_all(&current_data, &commands, tick)?;
+
+        let evictions = match limit.as_deref() {
+            Some(limit) => evictions(&mut current_data, new_data.used_memory, &commands, limit)?,
+            None => Vec::new(),
+        };
+        if !evictions.is_empty() {
+            // Evicted keys are never written by `commands`, so they still apply.
+            let evicted = apply_all(&current_data, &evictions, tick)?;
+            new_data = apply_all(&evicted, &commands, tick)?;
+            debug!("Evicted {} keys", evictions.len());
+        }
+
+        let accepted = match &self.aof_sender {
+            Some(aof_sender) => {
+                let logged = evictions.into_iter().chain(commands).collect();
+                aof_sender.send(AofMessage::Commands(logged)).await.is_ok()
+            }
+            // Ephemeral engines reject writes after shutdown just like persistent ones.
+            None => !self.shut_down.load(Ordering::Acquire),
+        };
+        if !accepted {
+            return Err(ServerError::AofWriteFailed);
+        }
+
+        self.data.store(Arc::new(new_data));
+
+        Ok(())
+    }
+}
+
+impl StorageEngine for MemoryEngine {
+    type Snapshot = MemorySnapshot;
+
+    fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot {
+            store: self.data.load_full(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    async fn put(&self, space: &str, key: &str, value: Vec<u8>) -> Result<(), ServerError> {
+        self.write(vec![Command::Set {
+            space: space.to_string(),
+            key: key.to_string(),
+            value,
+        }])
+        .await
+    }
+
+    async fn delete(&self, space: &str, key: &str) -> Result<(), ServerError> {
+        self.write(vec![Command::Delete {
+            space: space.to_string(),
+            key: key.to_string(),
+        }])
+        .await
+    }
+
+    async fn create_space(&self, space: &str, config: SpaceConfig) -> Result<(), ServerError> {
+        self.write(vec![Command::CreateSpaceWithConfig {
+            space: space.to_string(),
+            config,
+        }])
+        .await
+    }
+
+    async fn alter_space(&self, space: &str, config: SpaceConfig) -> Result<(), ServerError> {
+        self.write(vec![Command::AlterSpace {
+            space: space.to_string(),
+            config,
+        }])
+        .await
+    }
+
+    async fn delete_space(&self, space: &str) -> Result<(), ServerError> {
+        self.write(vec![Command::DeleteSpace {
+            space: space.to_string(),
+        }])
+        .await
+    }
+
+    async fn put_batch(&self, entries: Vec<Entry>) -> Result<(), ServerError> {
+        let commands = entries
+            .into_iter()
+            .map(|Entry { space, key, value }| Command::Set { space, key, value })
+            .collect();
+
+        self.write(commands).await
+    }
+
+    async fn shutdown(&self) -> Result<(), ServerError> {
+        self.shut_down.store(true, Ordering::Release);
+        let Some(aof_sender) = &self.aof_sender else {
+            return Ok(());
+        };
+
+        let (ack_sender, ack_receiver) = oneshot::channel();
+
+        if aof_sender
+            .send(AofMessage::Shutdown(ack_sender))
+            .await
+            .is_err()
+        {
+            // The writer is already gone, so there is nothing left to flush.
+            return Ok(());
+        }
+
+        ack_receiver
+            .await
+            .unwrap_or(Err(ServerError::AofWriteFailed))
+    }
+
+    fn persistence_info(&self) -> PersistenceInfo {
+        PersistenceInfo {
+            engine: "memory".to_string(),
+            aof_size: self.aof_status.size.load(Ordering::Relaxed),
+            last_write_error: self.aof_status.last_error.load_full().as_deref().cloned(),
+            used_memory: Some(self.used_memory() as u64),
+        }
+    }
+}
+
+impl Snapshot for MemorySnapshot {
+    fn spaces(&self) -> Vec<String> {
+        self.store.spaces.keys().cloned().collect()
+    }
+
+    fn has_space(&self, space: &str) -> bool {
+        self.store.spaces.contains_key(space)
+    }
+
+    fn space_config(&self, space: &str) -> Result<SpaceConfig, ServerError> {
+        Ok(self.space(space)?.config.clone())
+    }
+
+    fn space_usage(&self, space: &str) -> Result<SpaceUsage, ServerError> {
+        Ok(self.space(space)?.usage())
+    }
+
+    fn get(&self, space: &str, key: &str) -> Result<Option<Vec<u8>>, ServerError> {
+        let space_data = &self.space(space)?.entries;
+        let Some(stored) = space_data.get(&HashedKey::new(key.to_string())) else {
+            return Ok(None);
+        };
+
+        stored.last_access.store(
+            self.clock.fetch_add(1, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        let _ = stored
+            .hits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |hits| {
+                hits.checked_add(1)
+            });
+
+        Ok(Some(stored.value.clone()))
+    }
+
+    fn scan(&self, space: &str) -> Result<ScanIter<'_>, ServerError> {
+        let space_data = &self.space(space)?.entries;
+        Ok(Box::new(space_data.iter().map(|(key, stored)| {
+            Ok((key.key.clone(), stored.value.clone()))
+        })))
+    }
+
+    fn keys(&self, space: &str) -> Result<Vec<String>, ServerError> {
+        Ok(self
+            .space(space)?
+            .entries
+            .keys()
+            .map(|k| k.key.clone())
+            .collect())
+    }
+}
+
+impl MemorySnapshot {
+    fn space(&self, space: &str) -> Result<&Space, ServerError> {
+        self.store
+            .spaces
+            .get(space)
+            .ok_or_else(|| ServerError::SpaceNotFound(space.to_string()))
+    }
+}
+
+impl Store {
+    fn new() -> Self {
+        Self {
+            spaces: HashTrieMapSync::new_sync(),
+            used_memory: 0,
+            eviction_index: None,
+        }
+    }
+
+    /// Builds the eviction index for `policy`, or drops it if `policy` never evicts.
+    fn index_for(&mut self, policy: Option<EvictionPolicy>) {
+        let policy = policy.filter(|policy| {
+            matches!(
+                policy,
+                EvictionPolicy::AllKeysLru | EvictionPolicy::AllKeysLfu | EvictionPolicy::Random
+            )
+        });
+
+        if self.eviction_index.as_ref().map(|index| index.policy) != policy {
+            self.eviction_index = policy.map(|policy| EvictionIndex::build(&self.spaces, policy));
+        }
+    }
+}
+
+impl EvictionIndex {
+    fn build(spaces: &HashTrieMapSync<String, Space>, policy: EvictionPolicy) -> Self {
+        let mut index = Self {
+            policy,
+            order: RedBlackTreeSetSync::new_sync(),
+            ranks: HashTrieMapSync::new_sync(),
+        };
+        for (space, space_data) in spaces.iter() {
+            index.insert_space(space, space_data);
+        }
+
+        index
+    }
+
+    fn rank(&self, key: &HashedKey, stored: &StoredValue) -> Rank {
+        let last_access = stored.last_access.load(Ordering::Relaxed);
+        match self.policy {
+            EvictionPolicy::AllKeysLfu => (stored.hits.load(Ordering::Relaxed) as u64, last_access),
+            // Drawn once, when the key is written.
+            EvictionPolicy::Random => (mix(key.hash_value() ^ last_access), 0),
+            _ => (last_access, 0),
+        }
+    }
+
+    fn insert(&mut self, space: &str, key: &HashedKey, stored: &StoredValue) {
+        let rank = self.rank(key, stored);
+        self.set_rank(space, &key.key, rank);
+    }
+
+    fn set_rank(&mut self, space: &str, key: &str, rank: Rank) {
+        self.remove(space, key);
+        self.order
+            .insert_mut((rank, space.to_string(), key.to_string()));
+        self.ranks
+            .insert_mut((space.to_string(), key.to_string()), rank);
+    }
+
+    fn remove(&mut self, space: &str, key: &str) {
+        let id = (space.to_string(), key.to_string());
+        if let Some(&rank) = self.ranks.get(&id) {
+            self.ranks.remove_mut(&id);
+            self.order.remove_mut(&(rank, id.0, id.1));
+        }
+    }
+
+    /// Keys of read-only spaces are left out, since they can't be evicted.
+    fn insert_space(&mut self, space: &str, space_data: &Space) {
+        if space_data.config.read_only {
+            return;
+        }
+        for (key, stored) in space_data.entries.iter() {
+            self.insert(space, key, stored);
+        }
+    }
+
+    fn remove_space(&mut self, space: &str, space_data: &Space) {
+        for key in space_data.entries.keys() {
+            self.remove(space, &key.key);
+        }
+    }
+
+    /// Returns the lowest ranked keys whose sizes add up to at least `to_free`, or every
+    /// key if they don't, and the bytes they take. Keys in `protected` are kept. Entries
+    /// found to rank too low are moved to their current rank.
+    fn pick_victims(
+        &mut self,
+        spaces: &HashTrieMapSync<String, Space>,
+        to_free: usize,
+        protected: &HashSet<(&str, &str)>,
+    ) -> (Vec<(String, String)>, usize) {
+        let mut remaining = self.order.clone();
+        let mut victims = Vec::new();
+        let mut freed = 0;
+
+        while freed < to_free {
+            let Some(entry) = remaining.first().cloned() else {
+                break;
+            };
+            remaining.remove_mut(&entry);
+
+            let (rank, space, key) = entry;
+            if protected.contains(&(space.as_str(), key.as_str())) {
+                continue;
+            }
+
+            let key = HashedKey::new(key);
+            let Some(stored) = spaces
+                .get(&space)
+                .and_then(|space_data| space_data.entries.get(&key))
+            else {
+                continue;
+            };
+
+            if self.policy != EvictionPolicy::Random {
+                let current = self.rank(&key, stored);
+                if current > rank {
+                    self.set_rank(&space, &key.key, current);
+                    remaining.insert_mut((current, space, key.key));
+                    continue;
+                }
+            }
+
+            freed += entry_size(&key.key, &stored.value);
+            victims.push((space, key.key));
+        }
+
+        (victims, freed)
+    }
+}
+
+impl Space {
+    fn new(config: SpaceConfig) -> Self {
+        Self {
+            entries: SpaceData::new_sync(),
+            config,
+            bytes: 0,
+        }
+    }
+
+    fn usage(&self) -> SpaceUsage {
+        SpaceUsage {
+            keys: self.entries.size() as u64,
+            bytes: self.bytes,
+        }
+    }
+}
+
+fn entry_size(key: &str, value: &[u8]) -> usize {
+    key.len() + value.len() + ENTRY_OVERHEAD
+}
+
+fn entry_bytes(key: &str, value: &[u8]) -> u64 {
+    (key.len() + value.len()) as u64
+}
+
+fn space_size(space: &str) -> usize {
+    space.len() + SPACE_OVERHEAD
+}
+
+/// Picks the keys to evict so that `commands`, which take the store to `needed` bytes, fit
+/// within `limit`. Writes that don't grow the store are always let through so that
+/// deletes can bring it back under the limit. Evicting frees a little more than needed,
+/// so eviction doesn't run on every write.
+fn evictions(
+    store: &mut Store,
+    needed: usize,
+    commands: &[Command],
+    limit: &MemoryLimit,
+) -> Result<Vec<Command>, ServerError> {
+    if needed <= limit.max_memory || needed <= store.used_memory {
+        return Ok(Vec::new());
+    }
+    let Some(index) = &mut store.eviction_index else {
+        return Err(ServerError::OutOfMemory);
+    };
+
+    let written: HashSet<(&str, &str)> = commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::Set { space, key, .. } => Some((space.as_str(), key.as_str())),
+            _ => None,
+        })
+        .collect();
+
+    let target = limit.max_memory - limit.max_memory / 20;
+    let (victims, freed) = index.pick_victims(&store.spaces, needed - target, &written);
+    if needed - freed > limit.max_memory {
+        return Err(ServerError::OutOfMemory);
+    }
+
+    Ok(victims
+        .into_iter()
+        .map(|(space, key)| Command::Delete { space, key })
+        .collect())
+}
+
+/// SplitMix64, to rank keys in a random order for `EvictionPolicy::Random`.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn apply_all(store: &Store, commands: &[Command], tick: u64) -> Result<Store, ServerError> {
+    commands
+        .iter()
+        .try_fold(store.clone(), |store, command| apply(&store, command, tick))
+}
+
+fn check(store: &Store, command: &Command) -> Result<(), ServerError> {
+    match command {
+        Command::Delete { space, .. }
+        | Command::DeleteSpace { space }
+        | Command::AlterSpace { space, .. }
+            if !store.spaces.contains_key(space) =>
+        {
+            Err(ServerError::SpaceNotFound(space.clone()))
+        }
+        Command::CreateSpace { space } | Command::CreateSpaceWithConfig { space, .. }
+            if store.spaces.contains_key(space) =>
+        {
+            Err(ServerError::SpaceAlreadyExists(space.clone()))
+        }
+        Command::Delete { space, .. } if store.spaces[space].config.read_only => {
+            Err(ServerError::SpaceReadOnly(space.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Applies a write command to `store`, enforcing the config of its space. Used for live
+/// writes and for AOF replay. `tick` is recorded as the last access of written values.
+fn apply(store: &Store, command: &Command, tick: u64) -> Result<Store, ServerError> {
+    check(store, command)?;
+
+    let mut used_memory = store.used_memory;
+    let mut eviction_index = store.eviction_index.clone();
+    let spaces = match command {
+        Command::Set { space, key, value } => {
+            let mut target = match store.spaces.get(space) {
+                Some(target) => target.clone(),
+                None => {
+                    used_memory += space_size(space);
+                    Space::new(SpaceConfig::default())
+                }
+            };
+            target.config.check_entry(space, key, value)?;
+            let before = target.usage();
+
+            let key = HashedKey::new(key.clone());
+            if let Some(previous) = target.entries.get(&key) {
+                target.bytes -= entry_bytes(&key.key, &previous.value);
+                used_memory -= entry_size(&key.key, &previous.value);
+            }
+            target.bytes += entry_bytes(&key.key, value);
+            used_memory += entry_size(&key.key, value);
+
+            let stored = StoredValue {
+                value: value.clone(),
+                last_access: AtomicU64::new(tick),
+                hits: AtomicU32::new(0),
+            };
+            if let Some(index) = &mut eviction_index {
+                index.insert(space, &key, &stored);
+            }
+            target.entries = target.entries.insert(key, Arc::new(stored));
+            target.config.check_usage(space, before, target.usage())?;
+            store.spaces.insert(space.clone(), target)
+        }
+        Command::Delete { space, key } => {
+            let mut target = store.spaces[space].clone();
+            let key = HashedKey::new(key.clone());
+            if let Some(previous) = target.entries.get(&key) {
+                target.bytes -= entry_bytes(&key.key, &previous.value);
+                used_memory -= entry_size(&key.key, &previous.value);
+            }
+            target.entries = target.entries.remove(&key);
+            if let Some(index) = &mut eviction_index {
+                index.remove(space, &key.key);
+            }
+            store.spaces.insert(space.clone(), target)
+        }
+        Command::CreateSpace { space } => {
+            used_memory += space_size(space);
+            store
+                .spaces
+                .insert(space.clone(), Space::new(SpaceConfig::default()))
+        }
+        Command::CreateSpaceWithConfig { space, config } => {
+            used_memory += space_size(space);
+            store
+                .spaces
+                .insert(space.clone(), Space::new(config.clone()))
+        }
+        Command::AlterSpace { space, config } => {
+            let mut target = store.spaces[space].clone();
+            let was_read_only = target.config.read_only;
+            target.config = config.clone();
+            if let Some(index) = &mut eviction_index
+                && config.read_only != was_read_only
+            {
+                index.remove_space(space, &target);
+                index.insert_space(space, &target);
+            }
+            store.spaces.insert(space.clone(), target)
+        }
+        Command::DeleteSpace { space } => {
+            if let Some(index) = &mut eviction_index {
+                index.remove_space(space, &store.spaces[space]);
+            }
+            used_memory -= space_size(space);
+            for (key, stored) in store.spaces[space].entries.iter() {
+                used_memory -= entry_size(&key.key, &stored.value);
+            }
+            store.spaces.remove(space)
+        }
+        _ => store.spaces.clone(),
+    };
+
+    Ok(Store {
+        spaces,
+        used_memory,
+        eviction_index,
+    })
+}
+
+async fn restore_from_aof(aof_path: &PathBuf) -> Result<Store, ServerError> {
+    if !aof_path.exists() {
+        return Ok(Store::new());
+    }
+
+    let file = fs::File::open(aof_path).await.map_err(|e| {
+        error!("Failed to open AOF file: {}", e);
+        ServerError::AofReadFailed
+    })?;
+
+    let mut reader = AofReader::new(io::BufReader::new(file));
+    let mut store = Store::new();
+
+    loop {
+        let record = reader.next_record().await.map_err(|e| {
+            error!("Failed to read command from AOF: {}", e);
+            ServerError::AofReadFailed
+        })?;
+
+        match record {
+            Some(AofRecord::Command { command, .. }) => {
+                // Commands that failed when they were issued don't change the store.
+                if let Ok(updated) = apply(&store, &command, 0) {
+                    store = updated;
+                }
+            }
+            Some(AofRecord::Undecodable { offset, error, .. }) => {
+                warn!("Skipping undecodable AOF record at {}: {}", offset, error);
+            }
+            // A crash while writing the length leaves a partial header; nothing was lost.
+            Some(AofRecord::TruncatedHeader { .. }) | None => break,
+            Some(AofRecord::TruncatedPayload { offset, .. }) => {
+                error!("AOF record at {} is truncated", offset);
+                return Err(ServerError::AofReadFailed);
+            }
+        }
+    }
+
+    Ok(store)
+}
+
+async fn aof_writer_task(
+    mut receiver: mpsc::Receiver<AofMessage>,
+    aof_path: PathBuf,
+    status: Arc<AofStatus>,
+) {
+    let mut file = match fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(aof_path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) => {
+            status.fail(format!("Failed to open AOF file: {e}"));
+            return;
+        }
+    };
+
+    while let Some(message) = receiver.recv().await {
+        match message {
+            AofMessage::Commands(commands) => write_commands(&mut file, &commands, &status).await,
+            AofMessage::Shutdown(ack) => {
+                receiver.close();
+
+                let mut acks = vec![ack];
+                while let Some(message) = receiver.recv().await {
+                    match message {
+                        AofMessage::Commands(commands) => {
+                            write_commands(&mut file, &commands, &status).await
+                        }
+                        AofMessage::Shutdown(ack) => acks.push(ack),
+                    }
+                }
+
+                let result = file.sync_all().await.map_err(|e| {
+                    status.fail(format!("Failed to sync AOF file: {e}"));
+                    ServerError::AofWriteFailed
+                });
+
+                debug!("AOF writer stopped");
+                for ack in acks {
+                    let _ = ack.send(result.clone());
+                }
+                return;
+            }
+        }
+    }
+}
+
+async fn write_commands(file: &mut fs::File, commands: &[Command], status: &AofStatus) {
+    for command in commands {
+        if let Ok(frame) = encode_frame(command) {
+            match file.write_all(&frame).await {
+                Ok(()) => {
+                    status.size.fetch_add(frame.len() as u64, Ordering::Relaxed);
+                }
+                Err(e) => status.fail(format!("Failed to write command to AOF: {e}")),
+            }
+        }
+    }
+
+    if let Err(e) = file.flush().await {
+        status.fail(format!("Failed to flush AOF: {e}"));
+    }
+}
@@ -0,0 +1,263 @@
+pub mod lsm;
+pub mod memory;
+
+use std::future::Future;
+
+use crate::{
+    error::ServerError,
+    proto::{Entry, SpaceConfig, SpaceUsage, info::PersistenceInfo},
+    storage::{
+        lsm::{LsmEngine, LsmSnapshot},
+        memory::{MemoryEngine, MemorySnapshot},
+    },
+};
+
+/// Which storage engine a `Db` keeps its data in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EngineKind {
+    /// The whole store in memory, persisted to an append-only file.
+    #[default]
+    Memory,
+    /// A log-structured merge tree on disk, for data sets larger than memory.
+    Lsm,
+}
+
+/// The entries of one space as `(key, value)` pairs. Engines that read from disk yield an
+/// error and stop if a read fails.
+pub type ScanIter<'a> = Box<dyn Iterator<Item = Result<(String, Vec<u8>), ServerError>> + 'a>;
+
+/// A read-only, point-in-time view of an engine. Writes made after it was taken are not
+/// visible through it.
+pub trait Snapshot: Send + Sync + 'static {
+    fn spaces(&self) -> Vec<String>;
+
+    fn has_space(&self, space: &str) -> bool;
+
+    fn space_config(&self, space: &str) -> Result<SpaceConfig, ServerError>;
+
+    fn space_usage(&self, space: &str) -> Result<SpaceUsage, ServerError>;
+
+    /// `Ok(None)` for a missing key, `ServerError::SpaceNotFound` for a missing space.
+    fn get(&self, space: &str, key: &str) -> Result<Option<Vec<u8>>, ServerError>;
+
+    /// Every entry of `space`, in no particular order.
+    fn scan(&self, space: &str) -> Result<ScanIter<'_>, ServerError>;
+
+    /// The entries of `space` with keys after `after`, in key order. Sorts a full scan
+    /// unless the engine keeps its keys in order.
+    fn scan_after(&self, space: &str, after: Option<&str>) -> Result<ScanIter<'_>, ServerError> {
+        let mut entries = Vec::new();
+        for entry in self.scan(space)? {
+            let (key, value) = entry?;
+            if after.is_none_or(|after| key.as_str() > after) {
+                entries.push((key, value));
+            }
+        }
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Box::new(entries.into_iter().map(Ok)))
+    }
+
+    fn keys(&self, space: &str) -> Result<Vec<String>, ServerError> {
+        self.scan(space)?
+            .map(|entry| entry.map(|(key, _)| key))
+            .collect()
+    }
+}
+
+/// Where a `Db` stores its spaces. Reads go through `snapshot`; commands are validated by
+/// `Db` before they reach the engine, which enforces the config of each space. Any
+/// implementation can be used with `Db::with_engine`.
+pub trait StorageEngine: Send + Sync + 'static {
+    type Snapshot: Snapshot;
+
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Whether reading from a snapshot may block on disk. `Db` runs such reads on the
+    /// blocking thread pool.
+    fn reads_block(&self) -> bool {
+        false
+    }
+
+    /// Creates `space` if it doesn't exist.
+    fn put(
+        &self,
+        space: &str,
+        key: &str,
+        value: Vec<u8>,
+    ) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    fn delete(
+        &self,
+        space: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    fn create_space(
+        &self,
+        space: &str,
+        config: SpaceConfig,
+    ) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    fn alter_space(
+        &self,
+        space: &str,
+        config: SpaceConfig,
+    ) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    fn delete_space(&self, space: &str) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    /// Writes every entry, creating missing spaces. Readers see all of them or none.
+    fn put_batch(
+        &self,
+        entries: Vec<Entry>,
+    ) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    /// Makes every accepted write durable. Writes issued afterwards fail.
+    fn shutdown(&self) -> impl Future<Output = Result<(), ServerError>> + Send;
+
+    fn persistence_info(&self) -> PersistenceInfo;
+}
+
+/// One of the built-in engines, picked at runtime by `EngineKind`.
+pub enum Engine {
+    Memory(MemoryEngine),
+    Lsm(LsmEngine),
+}
+
+pub enum EngineSnapshot {
+    Memory(MemorySnapshot),
+    Lsm(LsmSnapshot),
+}
+
+impl Snapshot for EngineSnapshot {
+    fn spaces(&self) -> Vec<String> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.spaces(),
+            EngineSnapshot::Lsm(snapshot) => snapshot.spaces(),
+        }
+    }
+
+    fn has_space(&self, space: &str) -> bool {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.has_space(space),
+            EngineSnapshot::Lsm(snapshot) => snapshot.has_space(space),
+        }
+    }
+
+    fn space_config(&self, space: &str) -> Result<SpaceConfig, ServerError> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.space_config(space),
+            EngineSnapshot::Lsm(snapshot) => snapshot.space_config(space),
+        }
+    }
+
+    fn space_usage(&self, space: &str) -> Result<SpaceUsage, ServerError> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.space_usage(space),
+            EngineSnapshot::Lsm(snapshot) => snapshot.space_usage(space),
+        }
+    }
+
+    fn get(&self, space: &str, key: &str) -> Result<Option<Vec<u8>>, ServerError> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.get(space, key),
+            EngineSnapshot::Lsm(snapshot) => snapshot.get(space, key),
+        }
+    }
+
+    fn scan(&self, space: &str) -> Result<ScanIter<'_>, ServerError> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.scan(space),
+            EngineSnapshot::Lsm(snapshot) => snapshot.scan(space),
+        }
+    }
+
+    fn scan_after(&self, space: &str, after: Option<&str>) -> Result<ScanIter<'_>, ServerError> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.scan_after(space, after),
+            EngineSnapshot::Lsm(snapshot) => snapshot.scan_after(space, after),
+        }
+    }
+
+    fn keys(&self, space: &str) -> Result<Vec<String>, ServerError> {
+        match self {
+            EngineSnapshot::Memory(snapshot) => snapshot.keys(space),
+            EngineSnapshot::Lsm(snapshot) => snapshot.keys(space),
+        }
+    }
+}
+
+impl StorageEngine for Engine {
+    type Snapshot = EngineSnapshot;
+
+    fn snapshot(&self) -> EngineSnapshot {
+        match self {
+            Engine::Memory(engine) => EngineSnapshot::Memory(engine.snapshot()),
+            Engine::Lsm(engine) => EngineSnapshot::Lsm(engine.snapshot()),
+        }
+    }
+
+    fn reads_block(&self) -> bool {
+        match self {
+            Engine::Memory(engine) => engine.reads_block(),
+            Engine::Lsm(engine) => engine.reads_block(),
+        }
+    }
+
+    async fn put(&self, space: &str, key: &str, value: Vec<u8>) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.put(space, key, value).await,
+            Engine::Lsm(engine) => engine.put(space, key, value).await,
+        }
+    }
+
+    async fn delete(&self, space: &str, key: &str) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.delete(space, key).await,
+            Engine::Lsm(engine) => engine.delete(space, key).await,
+        }
+    }
+
+    async fn create_space(&self, space: &str, config: SpaceConfig) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.create_space(space, config).await,
+            Engine::Lsm(engine) => engine.create_space(space, config).await,
+        }
+    }
+
+    async fn alter_space(&self, space: &str, config: SpaceConfig) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.alter_space(space, config).await,
+            Engine::Lsm(engine) => engine.alter_space(space, config).await,
+        }
+    }
+
+    async fn delete_space(&self, space: &str) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.delete_space(space).await,
+            Engine::Lsm(engine) => engine.delete_space(space).await,
+        }
+    }
+
+    async fn put_batch(&self, entries: Vec<Entry>) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.put_batch(entries).await,
+            Engine::Lsm(engine) => engine.put_batch(entries).await,
+        }
+    }
+
+    async fn shutdown(&self) -> Result<(), ServerError> {
+        match self {
+            Engine::Memory(engine) => engine.shutdown().await,
+            Engine::Lsm(engine) => engine.shutdown().await,
+        }
+    }
+
+    fn persistence_info(&self) -> PersistenceInfo {
+        match self {
+            Engine::Memory(engine) => engine.persistence_info(),
+            Engine::Lsm(engine) => engine.persistence_info(),
+        }
+    }
+}
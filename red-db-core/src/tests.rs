@@ -1,68 +1,1238 @@
 use tempfile::tempdir;
 
 use crate::{
+    backup::{self, BackupError},
     db::Db,
-    proto::{Command, Response},
+    error::ServerError,
+    proto::{
+        Command, DEFAULT_MAX_VALUE_SIZE, Entry, ExportPage, ImportMode, ImportOptions, Request,
+        Response, SpaceConfig, SpaceUsage,
+        frame::{FrameError, MAX_REQUEST_SIZE, encode_frame, read_frame, write_frame},
+        info::{InfoSection, SpaceInfo},
+    },
+    storage::{
+        EngineKind, Snapshot, StorageEngine,
+        lsm::{LsmEngine, LsmOptions},
+        memory::{EvictionPolicy, MemoryEngine, MemoryLimit},
+    },
 };
 
+const ENGINES: [EngineKind; 2] = [EngineKind::Memory, EngineKind::Lsm];
+
 #[tokio::test]
 async fn test_basic_operations() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let aof_path = temp_dir.path().join("test.aof");
+        let db = Db::open(kind, aof_path).await.unwrap();
+        let response = db
+            .execute(Command::CreateSpace {
+                space: "test".to_string(),
+            })
+            .await;
+        assert!(matches!(response, Response::Ok));
+
+        let response = db
+            .execute(Command::Set {
+                space: "test".to_string(),
+                key: "key1".to_string(),
+                value: b"value1".to_vec(),
+            })
+            .await;
+        assert!(matches!(response, Response::Ok));
+
+        let response = db
+            .execute(Command::Get {
+                space: "test".to_string(),
+                key: "key1".to_string(),
+            })
+            .await;
+        assert!(matches!(response, Response::Value(Some(v)) if v == b"value1"));
+    }
+}
+
+#[tokio::test]
+async fn test_aof_recovery() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let aof_path = temp_dir.path().join("recovery.aof");
+
+        {
+            let db = Db::open(kind, aof_path.clone()).await.unwrap();
+            db.execute(Command::CreateSpace {
+                space: "test".to_string(),
+            })
+            .await;
+            db.execute(Command::Set {
+                space: "test".to_string(),
+                key: "key1".to_string(),
+                value: b"persistent".to_vec(),
+            })
+            .await;
+
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        let db = Db::open(kind, aof_path).await.unwrap();
+        let response = db
+            .execute(Command::Get {
+                space: "test".to_string(),
+                key: "key1".to_string(),
+            })
+            .await;
+
+        assert!(matches!(response, Response::Value(Some(v)) if v == b"persistent"));
+    }
+}
+
+#[tokio::test]
+async fn test_shutdown_flushes_aof() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let aof_path = temp_dir.path().join("shutdown.aof");
+
+        {
+            let db = Db::open(kind, aof_path.clone()).await.unwrap();
+            db.execute(Command::CreateSpace {
+                space: "test".to_string(),
+            })
+            .await;
+
+            for i in 0..100 {
+                db.execute(Command::Set {
+                    space: "test".to_string(),
+                    key: format!("key{i}"),
+                    value: vec![i as u8],
+                })
+                .await;
+            }
+
+            db.shutdown().await.unwrap();
+
+            let response = db
+                .execute(Command::Set {
+                    space: "test".to_string(),
+                    key: "late".to_string(),
+                    value: b"late".to_vec(),
+                })
+                .await;
+            assert!(matches!(
+                response,
+                Response::Error(ServerError::AofWriteFailed)
+            ));
+        }
+
+        let db = Db::open(kind, aof_path).await.unwrap();
+        let response = db
+            .execute(Command::ListKeys {
+                space: "test".to_string(),
+            })
+            .await;
+
+        assert!(matches!(response, Response::Keys(keys) if keys.len() == 100));
+    }
+}
+
+#[tokio::test]
+async fn test_frame_round_trip() {
+    let request = Request {
+        id: 42,
+        command: Command::Get {
+            space: "test".to_string(),
+            key: "key1".to_string(),
+        },
+    };
+
+    let mut buffer = Vec::new();
+    write_frame(&mut buffer, &request).await.unwrap();
+
+    let mut reader = buffer.as_slice();
+    let decoded: Request = read_frame(&mut reader, MAX_REQUEST_SIZE)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded.id, 42);
+    assert!(matches!(decoded.command, Command::Get { key, .. } if key == "key1"));
+
+    let eof: Option<Request> = read_frame(&mut reader, MAX_REQUEST_SIZE).await.unwrap();
+    assert!(eof.is_none());
+
+    let mut reader = buffer.as_slice();
+    let result = read_frame::<Request, _>(&mut reader, 4).await;
+    assert!(matches!(result, Err(FrameError::TooLarge(_))));
+}
+
+fn entry(space: &str, key: &str, value: &[u8]) -> Entry {
+    Entry {
+        space: space.to_string(),
+        key: key.to_string(),
+        value: value.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn test_export_import() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let aof_path = temp_dir.path().join("import.aof");
+
+        {
+            let db = Db::open(kind, aof_path.clone()).await.unwrap();
+            db.execute(Command::Set {
+                space: "users".to_string(),
+                key: "user:1".to_string(),
+                value: b"Alice".to_vec(),
+            })
+            .await;
+
+            let entries = vec![
+                entry("users", "user:1", b"Alicia"),
+                entry("users", "user:2", b"Bob"),
+                entry("orders", "order:1", &[0xff, 0x00]),
+            ];
+
+            let summary = db
+                .import(
+                    entries.clone(),
+                    ImportOptions {
+                        mode: ImportMode::SkipExisting,
+                        dry_run: true,
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!((summary.written, summary.skipped), (2, 1));
+            assert_eq!(summary.spaces_created, vec!["orders".to_string()]);
+            assert!(matches!(
+                    db.execute(Command::Export {
+                space: None,
+                after: None,
+            }).await,
+                    Response::ExportPage(ExportPage { entries: exported, .. }) if exported.len() == 1
+                ));
+
+            let summary = db
+                .import(
+                    entries.clone(),
+                    ImportOptions {
+                        mode: ImportMode::SkipExisting,
+                        dry_run: false,
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!((summary.written, summary.skipped), (2, 1));
+
+            let invalid = db
+                .import(
+                    vec![entry("users", "user:3", b"Carol"), entry("users", "", b"")],
+                    ImportOptions::default(),
+                )
+                .await;
+            assert!(matches!(invalid, Err(ServerError::InvalidKey(_))));
+
+            db.shutdown().await.unwrap();
+        }
+
+        let db = Db::open(kind, aof_path).await.unwrap();
+        let response = db
+            .execute(Command::Export {
+                space: None,
+                after: None,
+            })
+            .await;
+        let Response::ExportPage(ExportPage {
+            entries: exported, ..
+        }) = response
+        else {
+            panic!("Expected entries, got {response:?}");
+        };
+        assert_eq!(
+            exported,
+            vec![
+                entry("orders", "order:1", &[0xff, 0x00]),
+                entry("users", "user:1", b"Alice"),
+                entry("users", "user:2", b"Bob"),
+            ]
+        );
+
+        let response = db
+            .execute(Command::Export {
+                space: Some("missing".to_string()),
+                after: None,
+            })
+            .await;
+        assert!(matches!(
+            response,
+            Response::Error(ServerError::SpaceNotFound(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_export_pages() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let db = Db::open(kind, temp_dir.path().join("data")).await.unwrap();
+        let value = vec![0; 400 * 1024];
+        let entries = [
+            entry("a", "key1", &value),
+            entry("a", "key2", &value),
+            entry("a", "key3", &value),
+            entry("b", "key1", &value),
+            entry("c", "key1", b"small"),
+        ];
+        for entry in entries.iter().rev() {
+            db.import(vec![entry.clone()], ImportOptions::default())
+                .await
+                .unwrap();
+        }
+
+        let first = db.export(None, None, |_| true).await.unwrap();
+        assert_eq!(first.entries, entries[..3]);
+        let next = first.next.unwrap();
+        assert_eq!((next.space.as_str(), next.key.as_str()), ("a", "key3"));
+
+        // Later pages come from the snapshot of the first, whatever is written meanwhile.
+        db.import(
+            vec![entry("b", "key0", b"new"), entry("c", "key1", b"changed")],
+            ImportOptions::default(),
+        )
+        .await
+        .unwrap();
+        db.execute(Command::Delete {
+            space: "b".to_string(),
+            key: "key1".to_string(),
+        })
+        .await;
+
+        let second = db.export(None, Some(next.clone()), |_| true).await.unwrap();
+        assert_eq!(second.entries, entries[3..]);
+        assert_eq!(second.next, None);
+
+        // The snapshot is released after the last page.
+        assert!(matches!(
+            db.export(None, Some(next), |_| true).await,
+            Err(ServerError::ExportExpired(_))
+        ));
+
+        let unreadable = db.export(None, None, |space| space != "a").await.unwrap();
+        assert_eq!(
+            unreadable.entries,
+            [entry("b", "key0", b"new"), entry("c", "key1", b"changed")]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_backup_and_restore() {
     let temp_dir = tempdir().unwrap();
-    let aof_path = temp_dir.path().join("test.aof");
+    let archive_path = temp_dir.path().join("backup.rdbak");
+
+    let db = Db::new(temp_dir.path().join("source.aof")).await;
+    db.execute(Command::CreateSpace {
+        space: "empty".to_string(),
+    })
+    .await;
+    db.import(
+        vec![
+            entry("users", "user:1", b"Alice"),
+            entry("users", "user:2", b"Bob"),
+        ],
+        ImportOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let metadata = db.backup(archive_path.clone()).await.unwrap();
+    assert_eq!(
+        metadata.spaces,
+        vec!["empty".to_string(), "users".to_string()]
+    );
+    assert_eq!(metadata.keys, 2);
+    db.shutdown().await.unwrap();
+
+    let (read_metadata, _) = backup::read_archive(&archive_path).await.unwrap();
+    assert_eq!(read_metadata, metadata);
+
+    let damaged_path = temp_dir.path().join("damaged.rdbak");
+    let mut damaged = std::fs::read(&archive_path).unwrap();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0xff;
+    std::fs::write(&damaged_path, damaged).unwrap();
+    assert!(matches!(
+        backup::read_archive(&damaged_path).await,
+        Err(BackupError::ChecksumMismatch)
+    ));
+    std::fs::write(&damaged_path, b"not a backup at all").unwrap();
+    assert!(matches!(
+        backup::read_archive(&damaged_path).await,
+        Err(BackupError::NotABackup)
+    ));
+
+    let aof_path = temp_dir.path().join("restored.aof");
+    std::fs::write(&aof_path, b"old data").unwrap();
+    backup::restore(&archive_path, &aof_path, EngineKind::Memory)
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(temp_dir.path().join("restored.aof.pre-restore")).unwrap(),
+        b"old data"
+    );
+
     let db = Db::new(aof_path).await;
     let response = db
-        .execute(Command::CreateSpace {
-            space: "test".to_string(),
+        .execute(Command::Export {
+            space: None,
+            after: None,
         })
         .await;
-    assert!(matches!(response, Response::Ok));
+    assert!(matches!(
+        response,
+        Response::ExportPage(ExportPage { entries, .. }) if entries == vec![
+            entry("users", "user:1", b"Alice"),
+            entry("users", "user:2", b"Bob"),
+        ]
+    ));
+    assert!(matches!(
+        db.execute(Command::ListSpaces).await,
+        Response::Spaces(spaces) if spaces.len() == 2
+    ));
+}
 
-    let response = db
-        .execute(Command::Set {
-            space: "test".to_string(),
-            key: "key1".to_string(),
-            value: b"value1".to_vec(),
+#[tokio::test]
+async fn test_failed_writes_are_not_replayed() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let aof_path = temp_dir.path().join("failed.aof");
+
+        {
+            let db = Db::open(kind, aof_path.clone()).await.unwrap();
+            db.execute(Command::Set {
+                space: "test".to_string(),
+                key: "key1".to_string(),
+                value: b"value1".to_vec(),
+            })
+            .await;
+
+            let response = db
+                .execute(Command::CreateSpace {
+                    space: "test".to_string(),
+                })
+                .await;
+            assert!(matches!(
+                response,
+                Response::Error(ServerError::SpaceAlreadyExists(_))
+            ));
+
+            let response = db
+                .execute(Command::Delete {
+                    space: "missing".to_string(),
+                    key: "key1".to_string(),
+                })
+                .await;
+            assert!(matches!(
+                response,
+                Response::Error(ServerError::SpaceNotFound(_))
+            ));
+
+            db.shutdown().await.unwrap();
+        }
+
+        // The rejected `CreateSpace` must not empty the space on replay.
+        let db = Db::open(kind, aof_path).await.unwrap();
+        let response = db
+            .execute(Command::Get {
+                space: "test".to_string(),
+                key: "key1".to_string(),
+            })
+            .await;
+        assert!(matches!(response, Response::Value(Some(v)) if v == b"value1"));
+    }
+}
+
+/// Concurrent writes that race on a space and its limits must replay from the AOF to the
+/// state they produced live.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_writes_replay_in_order() {
+    let temp_dir = tempdir().unwrap();
+    let aof_path = temp_dir.path().join("concurrent.aof");
+    let config = SpaceConfig {
+        max_keys: Some(8),
+        ..SpaceConfig::default()
+    };
+
+    let db = Db::with_engine(MemoryEngine::open(aof_path.clone()).await);
+    let mut tasks = Vec::new();
+    for i in 0..200 {
+        let db = db.clone();
+        let config = config.clone();
+
+        tasks.push(tokio::spawn(async move {
+            let space = "racy".to_string();
+            if i % 20 == 0 {
+                db.execute(Command::DeleteSpace {
+                    space: space.clone(),
+                })
+                .await;
+                db.execute(Command::CreateSpaceWithConfig { space, config })
+                    .await;
+            } else {
+                db.execute(Command::Set {
+                    space,
+                    key: format!("key{}", i % 16),
+                    value: vec![i as u8],
+                })
+                .await;
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let live = db
+        .execute(Command::Export {
+            space: None,
+            after: None,
         })
         .await;
-    assert!(matches!(response, Response::Ok));
+    db.shutdown().await.unwrap();
 
-    let response = db
-        .execute(Command::Get {
-            space: "test".to_string(),
-            key: "key1".to_string(),
+    let replayed = Db::new(aof_path)
+        .await
+        .execute(Command::Export {
+            space: None,
+            after: None,
         })
         .await;
-    assert!(matches!(response, Response::Value(Some(v)) if v == b"value1"));
+    assert_eq!(format!("{live:?}"), format!("{replayed:?}"));
 }
 
 #[tokio::test]
-async fn test_aof_recovery() {
+async fn test_lsm_flush_and_compaction() {
     let temp_dir = tempdir().unwrap();
-    let aof_path = temp_dir.path().join("recovery.aof");
+    let dir = temp_dir.path().join("lsm");
+    let options = LsmOptions {
+        memtable_size: 4 * 1024,
+        block_size: 256,
+        table_size: 8 * 1024,
+        l0_compaction_trigger: 2,
+        level_base_size: 16 * 1024,
+    };
 
     {
-        let db = Db::new(aof_path.clone()).await;
+        let engine = LsmEngine::open(dir.clone(), options.clone()).await.unwrap();
+        for round in 0..4u8 {
+            for i in 0..500 {
+                engine
+                    .put("data", &format!("key{i:04}"), vec![round; 16])
+                    .await
+                    .unwrap();
+            }
+        }
+        for i in (0..500).step_by(2) {
+            engine.delete("data", &format!("key{i:04}")).await.unwrap();
+        }
+
+        let before = engine.snapshot();
+        engine.put("gone", "key", b"value".to_vec()).await.unwrap();
+        engine.delete_space("gone").await.unwrap();
+        engine
+            .create_space("gone", SpaceConfig::default())
+            .await
+            .unwrap();
+        engine
+            .put("data", "key0001", b"new".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(before.get("data", "key0001").unwrap(), Some(vec![3; 16]));
+        assert!(!before.has_space("gone"));
+        assert_eq!(
+            engine.snapshot().keys("gone").unwrap(),
+            Vec::<String>::new()
+        );
+        engine.shutdown().await.unwrap();
+    }
+
+    let tables = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|file| file.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+        .count();
+    assert!(tables > 1, "expected flushed tables, found {tables}");
+
+    let engine = LsmEngine::open(dir, options).await.unwrap();
+    let snapshot = engine.snapshot();
+    assert_eq!(snapshot.get("data", "key0000").unwrap(), None);
+    assert_eq!(
+        snapshot.get("data", "key0001").unwrap(),
+        Some(b"new".to_vec())
+    );
+    assert_eq!(snapshot.get("data", "key0499").unwrap(), Some(vec![3; 16]));
+
+    let mut keys = snapshot.keys("data").unwrap();
+    keys.sort();
+    assert_eq!(keys.len(), 250);
+    assert_eq!(keys[0], "key0001");
+    assert!(snapshot.has_space("gone"));
+    assert!(matches!(
+        snapshot.get("missing", "key"),
+        Err(ServerError::SpaceNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_lsm_usage_of_overwritten_keys() {
+    let temp_dir = tempdir().unwrap();
+    let dir = temp_dir.path().join("lsm");
+    let limited = SpaceConfig {
+        max_keys: Some(100),
+        ..SpaceConfig::default()
+    };
+
+    {
+        let engine = LsmEngine::open(dir.clone(), LsmOptions::default())
+            .await
+            .unwrap();
+        engine.create_space("limited", limited).await.unwrap();
+        for i in 0..100 {
+            engine
+                .put("open", &format!("key{i:03}"), vec![0; 10])
+                .await
+                .unwrap();
+            engine
+                .put("limited", &format!("key{i:03}"), vec![0; 10])
+                .await
+                .unwrap();
+        }
+        engine.shutdown().await.unwrap();
+    }
+
+    // Reopening flushes the replayed log, so the old values are only in tables.
+    let engine = LsmEngine::open(dir.clone(), LsmOptions::default())
+        .await
+        .unwrap();
+    for i in 0..100 {
+        engine
+            .put("open", &format!("key{i:03}"), vec![0; 20])
+            .await
+            .unwrap();
+        engine
+            .put("limited", &format!("key{i:03}"), vec![0; 20])
+            .await
+            .unwrap();
+    }
+    engine.delete("open", "key000").await.unwrap();
+
+    let exact = SpaceUsage {
+        keys: 99,
+        bytes: 99 * 26,
+    };
+    assert_eq!(
+        engine.snapshot().space_usage("limited").unwrap(),
+        SpaceUsage {
+            keys: 100,
+            bytes: 100 * 26,
+        }
+    );
+    engine.shutdown().await.unwrap();
+
+    let engine = LsmEngine::open(dir, LsmOptions::default()).await.unwrap();
+    for _ in 0..500 {
+        if engine.snapshot().space_usage("open").unwrap() == exact {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!(
+        "usage was not corrected: {:?}",
+        engine.snapshot().space_usage("open").unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_restore_into_lsm() {
+    let temp_dir = tempdir().unwrap();
+    let archive_path = temp_dir.path().join("backup.rdbak");
+
+    let db = Db::new(temp_dir.path().join("source.aof")).await;
+    db.import(
+        vec![
+            entry("users", "user:1", b"Alice"),
+            entry("users", "user:2", b"Bob"),
+        ],
+        ImportOptions::default(),
+    )
+    .await
+    .unwrap();
+    db.backup(archive_path.clone()).await.unwrap();
+
+    let dir = temp_dir.path().join("lsm");
+    backup::restore(&archive_path, &dir, EngineKind::Lsm)
+        .await
+        .unwrap();
+
+    let db = Db::open(EngineKind::Lsm, dir).await.unwrap();
+    assert!(matches!(
+        db.execute(Command::Export {
+            space: None,
+            after: None,
+        }).await,
+        Response::ExportPage(ExportPage { entries, .. }) if entries == vec![
+            entry("users", "user:1", b"Alice"),
+            entry("users", "user:2", b"Bob"),
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn test_restore_larger_than_memtable() {
+    let temp_dir = tempdir().unwrap();
+    let archive_path = temp_dir.path().join("backup.rdbak");
+
+    // Six times the size of a default memtable.
+    let db = Db::new(temp_dir.path().join("source.aof")).await;
+    for batch in 0..6 {
+        let entries = (0..256)
+            .map(|i| {
+                let key = format!("key{:04}", batch * 256 + i);
+                entry("data", &key, &[batch as u8; 16 * 1024])
+            })
+            .collect();
+        db.import(entries, ImportOptions::default()).await.unwrap();
+    }
+    db.backup(archive_path.clone()).await.unwrap();
+    db.shutdown().await.unwrap();
+
+    let dir = temp_dir.path().join("lsm");
+    backup::restore(&archive_path, &dir, EngineKind::Lsm)
+        .await
+        .unwrap();
+
+    let tables = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|file| file.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+        .count();
+    assert!(tables > 0, "expected the restore to write tables");
+
+    let engine = LsmEngine::open(dir, LsmOptions::default()).await.unwrap();
+    let snapshot = engine.snapshot();
+    assert_eq!(snapshot.keys("data").unwrap().len(), 6 * 256);
+    assert_eq!(
+        snapshot.get("data", "key0000").unwrap(),
+        Some(vec![0; 16 * 1024])
+    );
+    assert_eq!(
+        snapshot.get("data", "key1535").unwrap(),
+        Some(vec![5; 16 * 1024])
+    );
+}
+
+#[tokio::test]
+async fn test_lsm_replay_flushes_full_memtables() {
+    let temp_dir = tempdir().unwrap();
+    let dir = temp_dir.path().join("lsm");
+
+    {
+        let engine = LsmEngine::open(dir.clone(), LsmOptions::default())
+            .await
+            .unwrap();
+        for i in 0..1000 {
+            engine
+                .put("data", &format!("key{i:04}"), vec![1; 1024])
+                .await
+                .unwrap();
+        }
+        engine.shutdown().await.unwrap();
+    }
+
+    // The log holds about 1 MiB, which replay must spread over many small memtables.
+    let options = LsmOptions {
+        memtable_size: 64 * 1024,
+        l0_compaction_trigger: usize::MAX,
+        ..LsmOptions::default()
+    };
+    let engine = LsmEngine::open(dir.clone(), options.clone()).await.unwrap();
+    engine.shutdown().await.unwrap();
+
+    let tables = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|file| file.as_ref().unwrap().path().extension() == Some("sst".as_ref()))
+        .count();
+    assert!(
+        tables > 10,
+        "expected tables flushed during replay, found {tables}"
+    );
+
+    let engine = LsmEngine::open(dir, options).await.unwrap();
+    let snapshot = engine.snapshot();
+    assert_eq!(
+        snapshot.space_usage("data").unwrap(),
+        SpaceUsage {
+            keys: 1000,
+            bytes: 1000 * (7 + 1024),
+        }
+    );
+    assert_eq!(
+        snapshot.get("data", "key0999").unwrap(),
+        Some(vec![1; 1024])
+    );
+}
+
+#[tokio::test]
+async fn test_in_memory_save_to() {
+    let temp_dir = tempdir().unwrap();
+    let aof_path = temp_dir.path().join("saved.aof");
+
+    let db = Db::in_memory();
+    db.execute(Command::CreateSpace {
+        space: "empty".to_string(),
+    })
+    .await;
+    db.execute(Command::Set {
+        space: "test".to_string(),
+        key: "key1".to_string(),
+        value: b"value1".to_vec(),
+    })
+    .await;
+    assert!(matches!(
         db.execute(Command::CreateSpace {
             space: "test".to_string(),
         })
-        .await;
-        db.execute(Command::Set {
+        .await,
+        Response::Error(ServerError::SpaceAlreadyExists(_))
+    ));
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+    db.save_to(aof_path.clone()).await.unwrap();
+    db.shutdown().await.unwrap();
+    assert!(matches!(
+        db.execute(Command::Delete {
             space: "test".to_string(),
             key: "key1".to_string(),
-            value: b"persistent".to_vec(),
         })
-        .await;
-
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-    }
+        .await,
+        Response::Error(ServerError::AofWriteFailed)
+    ));
 
     let db = Db::new(aof_path).await;
-    let response = db
-        .execute(Command::Get {
+    assert!(matches!(
+        db.execute(Command::Get {
             space: "test".to_string(),
             key: "key1".to_string(),
         })
+        .await,
+        Response::Value(Some(v)) if v == b"value1"
+    ));
+    assert!(matches!(
+        db.execute(Command::ListSpaces).await,
+        Response::Spaces(spaces) if spaces.len() == 2
+    ));
+}
+
+async fn set(db: &Db, key: &str) -> Response {
+    db.execute(Command::Set {
+        space: "cache".to_string(),
+        key: key.to_string(),
+        value: vec![0; 100],
+    })
+    .await
+}
+
+async fn get(db: &Db, key: &str) -> Option<Vec<u8>> {
+    match db
+        .execute(Command::Get {
+            space: "cache".to_string(),
+            key: key.to_string(),
+        })
+        .await
+    {
+        Response::Value(value) => value,
+        response => panic!("Expected a value, got {response:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_eviction_policies() {
+    let temp_dir = tempdir().unwrap();
+    let aof_path = temp_dir.path().join("evictions.aof");
+
+    {
+        let db = Db::new(aof_path.clone()).await;
+        db.set_memory_limit(Some(MemoryLimit {
+            max_memory: 10_000,
+            policy: EvictionPolicy::AllKeysLru,
+        }))
+        .unwrap();
+
+        for i in 0..40 {
+            assert!(matches!(set(&db, &format!("key{i}")).await, Response::Ok));
+        }
+        assert!(get(&db, "key0").await.is_some());
+        for i in 40..80 {
+            assert!(matches!(set(&db, &format!("key{i}")).await, Response::Ok));
+        }
+
+        assert!(get(&db, "key0").await.is_some());
+        assert!(get(&db, "key1").await.is_none());
+        assert!(get(&db, "key79").await.is_some());
+        db.shutdown().await.unwrap();
+    }
+
+    // Evictions are replayed from the AOF as deletes.
+    let db = Db::new(aof_path).await;
+    assert!(get(&db, "key0").await.is_some());
+    assert!(get(&db, "key1").await.is_none());
+
+    let db = Db::in_memory();
+    db.set_memory_limit(Some(MemoryLimit {
+        max_memory: 10_000,
+        policy: EvictionPolicy::AllKeysLfu,
+    }))
+    .unwrap();
+    for i in 0..40 {
+        set(&db, &format!("key{i}")).await;
+        if i % 2 == 0 {
+            get(&db, &format!("key{i}")).await;
+        }
+    }
+    for i in 40..60 {
+        set(&db, &format!("key{i}")).await;
+    }
+    for i in (0..40).step_by(2) {
+        assert!(get(&db, &format!("key{i}")).await.is_some(), "key{i}");
+    }
+
+    for policy in [EvictionPolicy::NoEviction, EvictionPolicy::VolatileTtl] {
+        let db = Db::in_memory();
+        db.set_memory_limit(Some(MemoryLimit {
+            max_memory: 1_500,
+            policy,
+        }))
+        .unwrap();
+        for i in 0..10 {
+            set(&db, &format!("key{i}")).await;
+        }
+        assert!(matches!(
+            set(&db, "one_more").await,
+            Response::Error(ServerError::OutOfMemory)
+        ));
+        assert!(get(&db, "key0").await.is_some());
+
+        let response = db
+            .execute(Command::Delete {
+                space: "cache".to_string(),
+                key: "key0".to_string(),
+            })
+            .await;
+        assert!(matches!(response, Response::Ok));
+    }
+
+    let db = Db::in_memory();
+    db.set_memory_limit(Some(MemoryLimit {
+        max_memory: 5_000,
+        policy: EvictionPolicy::Random,
+    }))
+    .unwrap();
+    for i in 0..100 {
+        assert!(matches!(set(&db, &format!("key{i}")).await, Response::Ok));
+    }
+    assert!(matches!(
+        db.execute(Command::ListKeys { space: "cache".to_string() }).await,
+        Response::Keys(keys) if keys.len() < 30 && keys.contains(&"key99".to_string())
+    ));
+
+    // Keys of read-only spaces are never evicted.
+    let db = Db::in_memory();
+    db.set_memory_limit(Some(MemoryLimit {
+        max_memory: 10_000,
+        policy: EvictionPolicy::AllKeysLru,
+    }))
+    .unwrap();
+    for i in 0..20 {
+        set(&db, &format!("key{i}")).await;
+    }
+    db.execute(Command::AlterSpace {
+        space: "cache".to_string(),
+        config: SpaceConfig {
+            read_only: true,
+            ..SpaceConfig::default()
+        },
+    })
+    .await;
+    for i in 0..100 {
+        let response = db
+            .execute(Command::Set {
+                space: "other".to_string(),
+                key: format!("key{i}"),
+                value: vec![0; 100],
+            })
+            .await;
+        assert!(matches!(response, Response::Ok));
+    }
+    for i in 0..20 {
+        assert!(get(&db, &format!("key{i}")).await.is_some(), "key{i}");
+    }
+}
+
+async fn set_limited(db: &Db, key: &str, value: &[u8]) -> Response {
+    db.execute(Command::Set {
+        space: "limited".to_string(),
+        key: key.to_string(),
+        value: value.to_vec(),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_space_config() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("spaces");
+        let saved_path = temp_dir.path().join("saved.aof");
+
+        {
+            let db = Db::open(kind, path.clone()).await.unwrap();
+            let config = SpaceConfig {
+                max_value_size: Some(8),
+                max_key_length: Some(4),
+                max_keys: Some(2),
+                max_bytes: Some(15),
+                ..SpaceConfig::default()
+            };
+            db.execute(Command::CreateSpaceWithConfig {
+                space: "limited".to_string(),
+                config: config.clone(),
+            })
+            .await;
+
+            assert!(matches!(
+                set_limited(&db, "too-long", b"v").await,
+                Response::Error(ServerError::KeyTooLong(4))
+            ));
+            assert!(matches!(
+                set_limited(&db, "k1", &[0; 9]).await,
+                Response::Error(ServerError::ValueTooLarge)
+            ));
+            assert!(matches!(
+                set_limited(&db, "k1", &[0; 8]).await,
+                Response::Ok
+            ));
+            assert!(matches!(set_limited(&db, "k2", b"v").await, Response::Ok));
+            assert!(matches!(
+                set_limited(&db, "k3", b"v").await,
+                Response::Error(ServerError::KeyLimitReached(_))
+            ));
+            assert!(matches!(
+                set_limited(&db, "k2", &[0; 8]).await,
+                Response::Error(ServerError::SpaceQuotaExceeded(_))
+            ));
+            assert!(matches!(
+                db.import(
+                    vec![entry("limited", "k4", &[0; 9])],
+                    ImportOptions {
+                        dry_run: true,
+                        ..ImportOptions::default()
+                    },
+                )
+                .await,
+                Err(ServerError::ValueTooLarge)
+            ));
+
+            // A limit lowered below what the space holds only stops it from growing.
+            db.execute(Command::AlterSpace {
+                space: "limited".to_string(),
+                config: SpaceConfig {
+                    max_keys: Some(1),
+                    ..config
+                },
+            })
+            .await;
+            assert!(matches!(set_limited(&db, "k2", b"w").await, Response::Ok));
+
+            db.execute(Command::AlterSpace {
+                space: "limited".to_string(),
+                config: SpaceConfig {
+                    read_only: true,
+                    ..SpaceConfig::default()
+                },
+            })
+            .await;
+            assert!(matches!(
+                set_limited(&db, "k1", b"v").await,
+                Response::Error(ServerError::SpaceReadOnly(_))
+            ));
+            assert!(matches!(
+                db.execute(Command::Delete {
+                    space: "limited".to_string(),
+                    key: "k1".to_string(),
+                })
+                .await,
+                Response::Error(ServerError::SpaceReadOnly(_))
+            ));
+            assert!(matches!(
+                db.execute(Command::AlterSpace {
+                    space: "missing".to_string(),
+                    config: SpaceConfig::default(),
+                })
+                .await,
+                Response::Error(ServerError::SpaceNotFound(_))
+            ));
+            let too_large = SpaceConfig {
+                max_value_size: Some(DEFAULT_MAX_VALUE_SIZE + 1),
+                ..SpaceConfig::default()
+            };
+            assert!(matches!(
+                db.execute(Command::CreateSpaceWithConfig {
+                    space: "oversized".to_string(),
+                    config: too_large.clone(),
+                })
+                .await,
+                Response::Error(ServerError::InvalidSpaceConfig(_))
+            ));
+            assert!(matches!(
+                db.execute(Command::AlterSpace {
+                    space: "limited".to_string(),
+                    config: too_large,
+                })
+                .await,
+                Response::Error(ServerError::InvalidSpaceConfig(_))
+            ));
+
+            db.save_to(saved_path.clone()).await.unwrap();
+            db.shutdown().await.unwrap();
+        }
+
+        for db in [
+            Db::open(kind, path).await.unwrap(),
+            Db::new(saved_path).await,
+        ] {
+            assert!(matches!(
+                db.execute(Command::Get {
+                    space: "limited".to_string(),
+                    key: "k2".to_string(),
+                })
+                .await,
+                Response::Value(Some(v)) if v == b"w"
+            ));
+            assert!(matches!(
+                set_limited(&db, "k1", b"v").await,
+                Response::Error(ServerError::SpaceReadOnly(_))
+            ));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_legacy_create_space_record() {
+    let temp_dir = tempdir().unwrap();
+    let aof_path = temp_dir.path().join("legacy.aof");
+
+    // A `CreateSpace` record from before space configs existed.
+    let record = encode_frame(&(6u32, "legacy".to_string())).unwrap();
+    std::fs::write(&aof_path, record).unwrap();
+
+    let db = Db::new(aof_path).await;
+    assert!(matches!(
+        db.execute(Command::ListSpaces).await,
+        Response::Spaces(spaces) if spaces == vec!["legacy".to_string()]
+    ));
+    assert!(matches!(
+        db.execute(Command::Set {
+            space: "legacy".to_string(),
+            key: "key".to_string(),
+            value: vec![0; 1024 * 1024 + 1],
+        })
+        .await,
+        Response::Error(ServerError::ValueTooLarge)
+    ));
+}
+
+#[tokio::test]
+async fn test_info() {
+    for kind in ENGINES {
+        let temp_dir = tempdir().unwrap();
+        let db = Db::open(kind, temp_dir.path().join("info")).await.unwrap();
+
+        for (key, value) in [("a", "1"), ("b", "22"), ("a", "333")] {
+            db.execute(Command::Set {
+                space: "users".to_string(),
+                key: key.to_string(),
+                value: value.as_bytes().to_vec(),
+            })
+            .await;
+        }
+        db.execute(Command::Delete {
+            space: "users".to_string(),
+            key: "b".to_string(),
+        })
         .await;
 
-    assert!(matches!(response, Response::Value(Some(v)) if v == b"persistent"));
+        let client = db.track_client();
+        let info = db.info(None);
+        assert_eq!(info.clients.unwrap().connected, 1);
+        drop(client);
+
+        let stats = info.stats.unwrap();
+        assert_eq!(stats.total_commands, 4);
+        assert_eq!(
+            stats.commands,
+            vec![("set".to_string(), 3), ("delete".to_string(), 1)]
+        );
+        assert_eq!(
+            info.spaces.unwrap(),
+            vec![SpaceInfo {
+                name: "users".to_string(),
+                usage: SpaceUsage { keys: 1, bytes: 4 },
+                config: SpaceConfig::default(),
+            }]
+        );
+
+        db.shutdown().await.unwrap();
+        let persistence = db.info(Some(InfoSection::Persistence)).persistence.unwrap();
+        assert!(persistence.aof_size > 0);
+        assert!(persistence.last_write_error.is_none());
+        if kind == EngineKind::Memory {
+            let aof_size = std::fs::metadata(temp_dir.path().join("info"))
+                .unwrap()
+                .len();
+            assert_eq!(persistence.aof_size, aof_size);
+        }
+
+        let info = db.info(Some(InfoSection::Clients));
+        assert_eq!(info.clients.unwrap().connected, 0);
+        assert!(info.server.is_none() && info.spaces.is_none());
+        assert!(matches!(
+            db.execute(Command::SpaceInfo {
+                space: "missing".to_string(),
+            })
+            .await,
+            Response::Error(ServerError::SpaceNotFound(_))
+        ));
+    }
+}
+
+#[test]
+fn test_errors_for_older_protocols() {
+    let error = ServerError::StorageFailed("disk full".to_string());
+    assert!(matches!(
+        error.clone().for_protocol(5),
+        ServerError::StorageFailed(_)
+    ));
+    assert!(matches!(error.for_protocol(4), ServerError::AofWriteFailed));
+    assert!(matches!(
+        ServerError::OutOfMemory.for_protocol(5),
+        ServerError::AofWriteFailed
+    ));
+    assert!(matches!(
+        ServerError::SpaceReadOnly("logs".to_string()).for_protocol(6),
+        ServerError::PermissionDenied(reason) if reason == "Space 'logs' is read-only"
+    ));
+
+    let response = Response::Error(ServerError::BackupFailed("gone".to_string()));
+    assert!(matches!(
+        response.for_protocol(3),
+        Response::Error(ServerError::AofWriteFailed)
+    ));
 }
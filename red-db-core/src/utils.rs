@@ -31,4 +31,8 @@ impl HashedKey {
             hash: hasher.finish(),
         }
     }
+
+    pub fn hash_value(&self) -> u64 {
+        self.hash
+    }
 }
@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{
+        SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
+use blake2::{Blake2s256, Digest};
+use red_db_core::{error::ServerError, proto::Command};
+
+use crate::{
+    error::AuthError,
+    settings::{AclRule, AuthSettings, Permission},
+};
+
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::Hash(e.to_string()))
+}
+
+#[derive(Debug)]
+pub struct Authenticator {
+    users: HashMap<String, User>,
+    /// Verified against when the user is unknown, so that a missing user takes as long
+    /// to reject as a wrong password.
+    dummy_hash: String,
+    verified: CredentialCache,
+}
+
+/// How long credentials checked by `authenticate_cached` are trusted without running
+/// argon2 again.
+const CREDENTIAL_TTL: Duration = Duration::from_secs(60);
+const MAX_CACHED_CREDENTIALS: usize = 1024;
+
+/// Recently verified credentials, keyed by a digest of the username and password.
+#[derive(Debug)]
+struct CredentialCache {
+    /// Keys the digests, so the cache holds nothing that can be checked offline.
+    secret: [u8; 32],
+    entries: Mutex<HashMap<[u8; 32], (User, Instant)>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub username: String,
+    password_hash: String,
+    rules: Vec<AclRule>,
+}
+
+impl Authenticator {
+    pub fn from_settings(settings: &AuthSettings) -> Result<Self, AuthError> {
+        let mut users = HashMap::new();
+
+        for user in &settings.users {
+            PasswordHash::new(&user.password_hash)
+                .map_err(|e| AuthError::InvalidHash(user.username.clone(), e.to_string()))?;
+
+            let previous = users.insert(
+                user.username.clone(),
+                User {
+                    username: user.username.clone(),
+                    password_hash: user.password_hash.clone(),
+                    rules: user.rules.clone(),
+                },
+            );
+
+            if previous.is_some() {
+                return Err(AuthError::DuplicateUser(user.username.clone()));
+            }
+        }
+
+        Ok(Self {
+            users,
+            dummy_hash: hash_password("")?,
+            verified: CredentialCache::new(),
+        })
+    }
+
+    /// Like `authenticate`, but skips argon2 for credentials that were verified less
+    /// than `CREDENTIAL_TTL` ago. Meant for protocols that send the password with every
+    /// request, like HTTP Basic auth.
+    pub async fn authenticate_cached(&self, username: &str, password: String) -> Option<User> {
+        let digest = self.verified.digest(username, &password);
+        if let Some(user) = self.verified.get(&digest) {
+            return Some(user);
+        }
+
+        let user = self.authenticate(username, password).await?;
+        self.verified.insert(digest, user.clone());
+
+        Some(user)
+    }
+
+    pub async fn authenticate(&self, username: &str, password: String) -> Option<User> {
+        let user = self.users.get(username).cloned();
+        let password_hash = match &user {
+            Some(user) => user.password_hash.clone(),
+            None => self.dummy_hash.clone(),
+        };
+
+        // Argon2 is deliberately slow, keep it off the async workers.
+        tokio::task::spawn_blocking(move || {
+            let hash = PasswordHash::new(&password_hash).ok()?;
+            let verified = Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok();
+
+            user.filter(|_| verified)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+}
+
+impl CredentialCache {
+    fn new() -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+
+        Self {
+            secret,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn digest(&self, username: &str, password: &str) -> [u8; 32] {
+        Blake2s256::new()
+            .chain_update(self.secret)
+            .chain_update((username.len() as u64).to_le_bytes())
+            .chain_update(username)
+            .chain_update(password)
+            .finalize()
+            .into()
+    }
+
+    fn get(&self, digest: &[u8; 32]) -> Option<User> {
+        let entries = self.entries.lock().unwrap();
+        let (user, verified_at) = entries.get(digest)?;
+
+        (verified_at.elapsed() < CREDENTIAL_TTL).then(|| user.clone())
+    }
+
+    fn insert(&self, digest: [u8; 32], user: User) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_CREDENTIALS {
+            entries.retain(|_, (_, verified_at)| verified_at.elapsed() < CREDENTIAL_TTL);
+        }
+        if entries.len() >= MAX_CACHED_CREDENTIALS {
+            entries.clear();
+        }
+
+        entries.insert(digest, (user, Instant::now()));
+    }
+}
+
+impl User {
+    pub fn check(&self, command: &Command) -> Result<(), ServerError> {
+        let (permission, space) = match command {
+            Command::Get { space, .. }
+            | Command::ListKeys { space }
+            | Command::IsSpaceExists { space }
+            | Command::SpaceInfo { space } => (Permission::Read, Some(space)),
+            Command::Set { space, .. } | Command::Delete { space, .. } => {
+                (Permission::Write, Some(space))
+            }
+            Command::CreateSpace { space }
+            | Command::CreateSpaceWithConfig { space, .. }
+            | Command::AlterSpace { space, .. }
+            | Command::DeleteSpace { space } => (Permission::Admin, Some(space)),
+            Command::Export { space, .. } => (Permission::Read, space.as_ref()),
+            Command::ListSpaces | Command::Info { .. } => (Permission::Read, None),
+            // The entries name the spaces written to; an empty batch still needs a user who
+            // may write somewhere.
+            Command::Import { entries, .. } if entries.is_empty() => (Permission::Write, None),
+            Command::Import { entries, .. } => {
+                let spaces: BTreeSet<_> = entries.iter().map(|entry| &entry.space).collect();
+                return spaces
+                    .into_iter()
+                    .try_for_each(|space| self.require(Permission::Write, Some(space)));
+            }
+            // Archives hold every space and are written to the server's filesystem.
+            Command::Backup { .. } => {
+                return self.require(Permission::Admin, Some(&"*".to_string()));
+            }
+            Command::Auth { .. } | Command::Ping { .. } => return Ok(()),
+        };
+
+        self.require(permission, space)
+    }
+
+    fn require(&self, permission: Permission, space: Option<&String>) -> Result<(), ServerError> {
+        let allowed = match space {
+            Some(space) => self.is_allowed(permission, space),
+            None => self
+                .rules
+                .iter()
+                .any(|rule| rule.permissions.contains(&permission)),
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(ServerError::PermissionDenied(format!(
+                "user '{}' has no {:?} access{}",
+                self.username,
+                permission,
+                space
+                    .map(|s| format!(" to space '{s}'"))
+                    .unwrap_or_default()
+            )))
+        }
+    }
+
+    pub fn readable_spaces(&self, spaces: Vec<String>) -> Vec<String> {
+        spaces
+            .into_iter()
+            .filter(|space| self.is_allowed(Permission::Read, space))
+            .collect()
+    }
+
+    pub fn is_allowed(&self, permission: Permission, space: &str) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.permissions.contains(&permission)
+                && rule
+                    .spaces
+                    .iter()
+                    .any(|pattern| match pattern.strip_suffix('*') {
+                        Some(prefix) => space.starts_with(prefix),
+                        None => pattern == space,
+                    })
+        })
+    }
+}
@@ -1,3 +1,4 @@
+use red_db_core::proto::frame::FrameError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -8,4 +9,36 @@ pub enum ConnectionError {
     Protocol(String),
     #[error("Command too large")]
     CommandTooLarge,
+    #[error("Handshake failed: {0}")]
+    Handshake(String),
+}
+
+impl From<FrameError> for ConnectionError {
+    fn from(err: FrameError) -> Self {
+        match err {
+            FrameError::Io(e) => ConnectionError::Io(e),
+            FrameError::TooLarge(_) => ConnectionError::CommandTooLarge,
+            e => ConnectionError::Protocol(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to load certificates from '{0}': {1}")]
+    Certificate(String, String),
+    #[error("Failed to load private key from '{0}': {1}")]
+    PrivateKey(String, String),
+    #[error("Invalid TLS configuration: {0}")]
+    Config(String),
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Invalid password hash for user '{0}': {1}")]
+    InvalidHash(String, String),
+    #[error("User '{0}' is defined more than once")]
+    DuplicateUser(String),
+    #[error("Failed to hash password: {0}")]
+    Hash(String),
 }
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response as HttpResponse},
+    routing::{get, post},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use red_db_core::{
+    error::ServerError,
+    proto::{Command, Response},
+};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, info};
+
+use crate::{ServerState, authorize, confine_backup, error::ConnectionError, execute};
+
+pub(crate) async fn handle_connection<S>(
+    state: Arc<ServerState>,
+    stream: S,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = TowerToHyperService::new(router(state.clone()));
+    let connection =
+        hyper::server::conn::http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = state.shutdown.cancelled() => {
+            debug!("Closing HTTP connection for shutdown");
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    result.map_err(|e| ConnectionError::Protocol(format!("HTTP error: {e}")))
+}
+
+fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/spaces", get(list_spaces))
+        .route("/spaces/{space}", post(create_space).delete(delete_space))
+        .route("/spaces/{space}/keys", get(list_keys))
+        .route(
+            "/spaces/{space}/keys/{key}",
+            get(get_key).put(set_key).delete(delete_key),
+        )
+        .with_state(state)
+}
+
+async fn list_spaces(State(state): State<Arc<ServerState>>, headers: HeaderMap) -> HttpResponse {
+    match run(&state, &headers, Command::ListSpaces).await {
+        Response::Spaces(spaces) => Json(json!({ "spaces": spaces })).into_response(),
+        response => unexpected(response),
+    }
+}
+
+async fn create_space(
+    State(state): State<Arc<ServerState>>,
+    Path(space): Path<String>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    let command = Command::CreateSpace { space };
+    match run(&state, &headers, command).await {
+        Response::Ok => StatusCode::CREATED.into_response(),
+        response => unexpected(response),
+    }
+}
+
+async fn delete_space(
+    State(state): State<Arc<ServerState>>,
+    Path(space): Path<String>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    match run(&state, &headers, Command::DeleteSpace { space }).await {
+        Response::Ok => StatusCode::NO_CONTENT.into_response(),
+        response => unexpected(response),
+    }
+}
+
+async fn list_keys(
+    State(state): State<Arc<ServerState>>,
+    Path(space): Path<String>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    match run(&state, &headers, Command::ListKeys { space }).await {
+        Response::Keys(keys) => Json(json!({ "keys": keys })).into_response(),
+        response => unexpected(response),
+    }
+}
+
+async fn get_key(
+    State(state): State<Arc<ServerState>>,
+    Path((space, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    let command = Command::Get {
+        space: space.clone(),
+        key: key.clone(),
+    };
+
+    match run(&state, &headers, command).await {
+        Response::Value(Some(value)) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], value).into_response()
+        }
+        Response::Value(None) => error_response(&ServerError::KeyNotFound(key, space)),
+        response => unexpected(response),
+    }
+}
+
+async fn set_key(
+    State(state): State<Arc<ServerState>>,
+    Path((space, key)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> HttpResponse {
+    let command = Command::Set {
+        space,
+        key,
+        value: body.to_vec(),
+    };
+
+    match run(&state, &headers, command).await {
+        Response::Ok => StatusCode::NO_CONTENT.into_response(),
+        response => unexpected(response),
+    }
+}
+
+async fn delete_key(
+    State(state): State<Arc<ServerState>>,
+    Path((space, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> HttpResponse {
+    match run(&state, &headers, Command::Delete { space, key }).await {
+        Response::Ok => StatusCode::NO_CONTENT.into_response(),
+        response => unexpected(response),
+    }
+}
+
+/// Authenticates the request with HTTP Basic credentials when auth is enabled,
+/// then runs the command through the same path as native connections.
+async fn run(state: &ServerState, headers: &HeaderMap, command: Command) -> Response {
+    let user = match (&state.auth, basic_credentials(headers)) {
+        (None, _) => None,
+        (Some(_), None) => return Response::Error(ServerError::AuthenticationFailed),
+        (Some(auth), Some((username, password))) => {
+            match auth.authenticate_cached(&username, password).await {
+                Some(user) => {
+                    info!("HTTP request authenticated as '{}'", username);
+                    Some(user)
+                }
+                None => return Response::Error(ServerError::AuthenticationFailed),
+            }
+        }
+    };
+
+    match authorize(state, user.as_ref(), &command).and_then(|()| confine_backup(state, command)) {
+        Ok(command) => execute(&state.db, user.as_ref(), command).await,
+        Err(e) => Response::Error(e),
+    }
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some((username.to_string(), password.to_string()))
+}
+
+fn unexpected(response: Response) -> HttpResponse {
+    match response {
+        Response::Error(e) => error_response(&e),
+        response => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "error": {
+                    "code": "unexpected_response",
+                    "message": format!("Unexpected response {response:?}"),
+                }
+            })),
+        )
+            .into_response(),
+    }
+}
+
+fn error_response(error: &ServerError) -> HttpResponse {
+    let (status, code) = match error {
+        ServerError::SpaceNotFound(_) => (StatusCode::NOT_FOUND, "space_not_found"),
+        ServerError::KeyNotFound(_, _) => (StatusCode::NOT_FOUND, "key_not_found"),
+        ServerError::SpaceAlreadyExists(_) => (StatusCode::CONFLICT, "space_already_exists"),
+        ServerError::AofWriteFailed => (StatusCode::INTERNAL_SERVER_ERROR, "aof_write_failed"),
+        ServerError::AofReadFailed => (StatusCode::INTERNAL_SERVER_ERROR, "aof_read_failed"),
+        ServerError::InvalidKey(_) => (StatusCode::BAD_REQUEST, "invalid_key"),
+        ServerError::InvalidSpaceName => (StatusCode::BAD_REQUEST, "invalid_space_name"),
+        ServerError::ValueTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "value_too_large"),
+        ServerError::PermissionDenied(_) => (StatusCode::FORBIDDEN, "permission_denied"),
+        ServerError::AuthenticationFailed => (StatusCode::UNAUTHORIZED, "authentication_failed"),
+        ServerError::BackupFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "backup_failed"),
+        ServerError::StorageFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, "storage_failed"),
+        ServerError::OutOfMemory => (StatusCode::INSUFFICIENT_STORAGE, "out_of_memory"),
+        ServerError::SpaceReadOnly(_) => (StatusCode::CONFLICT, "space_read_only"),
+        ServerError::KeyTooLong(_) => (StatusCode::BAD_REQUEST, "key_too_long"),
+        ServerError::KeyLimitReached(_) => (StatusCode::INSUFFICIENT_STORAGE, "key_limit_reached"),
+        ServerError::SpaceQuotaExceeded(_) => {
+            (StatusCode::INSUFFICIENT_STORAGE, "space_quota_exceeded")
+        }
+        ServerError::InvalidSpaceConfig(_) => (StatusCode::BAD_REQUEST, "invalid_space_config"),
+        ServerError::InvalidBackupPath(_) => (StatusCode::BAD_REQUEST, "invalid_backup_path"),
+        ServerError::ExportExpired(_) => (StatusCode::GONE, "export_expired"),
+    };
+
+    let mut response = (
+        status,
+        Json(json!({
+            "error": {
+                "code": code,
+                "message": error.to_string(),
+            }
+        })),
+    )
+        .into_response();
+
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Basic realm=\"red-db\""),
+        );
+    }
+
+    response
+}
@@ -1,57 +1,277 @@
+pub mod auth;
 pub mod error;
+mod http;
+mod resp;
 pub mod settings;
+mod tls;
 
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{future::Future, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use red_db_core::{
+    backup,
     db::Db,
-    proto::{Command, Response},
+    error::ServerError,
+    proto::{
+        Command, Reply, Request, Response,
+        frame::{MAX_REQUEST_SIZE, encode_frame, read_frame, write_frame},
+        handshake::{
+            self, FEATURE_AUTH, FEATURE_PIPELINING, Hello, HelloReply, PROTOCOL_MAGIC,
+            PROTOCOL_VERSION,
+        },
+    },
+    storage::memory::MemoryLimit,
 };
 
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinSet,
 };
-use tracing::{debug, error, info, instrument};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{debug, error, info, instrument, warn};
 
+use auth::{Authenticator, User};
 use error::ConnectionError;
-use settings::Settings;
+use settings::{Permission, RespSettings, Settings};
+
+#[cfg(unix)]
+use settings::UnixSocketSettings;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct ServerState {
+    db: Arc<Db>,
+    tls: Option<TlsAcceptor>,
+    auth: Option<Authenticator>,
+    resp: Option<RespSettings>,
+    backup_dir: Option<PathBuf>,
+    shutdown: CancellationToken,
+    connections: TaskTracker,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Protocol {
+    Native,
+    Resp,
+    Http,
+}
 
 pub async fn run_server(settings: Settings) -> Result<(), Box<dyn std::error::Error>> {
+    run_server_until(settings, shutdown_signal()).await
+}
+
+/// Runs the server until `shutdown` completes, then stops accepting connections,
+/// lets in-flight requests finish and flushes the AOF.
+pub async fn run_server_until<F>(
+    settings: Settings,
+    shutdown: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Future<Output = ()>,
+{
     let bind_addr: SocketAddr = format!("{}:{}", settings.host, settings.port).parse()?;
 
     info!("Starting red-db server on {}", bind_addr);
 
-    let listener = TcpListener::bind(&bind_addr)
-        .await
-        .unwrap_or_else(|e| panic!("Failed to bind to {bind_addr}: {e}"));
+    let tls = match &settings.tls {
+        Some(tls_settings) => {
+            info!("TLS enabled");
+            Some(tls::build_acceptor(tls_settings)?)
+        }
+        None => None,
+    };
+
+    let auth = match &settings.auth {
+        Some(auth_settings) => {
+            info!(
+                "Authentication enabled for {} user(s)",
+                auth_settings.users.len()
+            );
+            Some(Authenticator::from_settings(auth_settings)?)
+        }
+        None => None,
+    };
 
-    let db = Arc::new(Db::new(PathBuf::from(settings.aof_path)).await);
+    let listener = bind(bind_addr).await;
 
-    info!("red-db server ready to accept connections");
+    let mut extra_listeners = Vec::new();
+
+    if let Some(resp_settings) = &settings.resp {
+        let resp_addr: SocketAddr = format!("{}:{}", settings.host, resp_settings.port).parse()?;
+        info!("Starting RESP listener on {}", resp_addr);
+        extra_listeners.push((bind(resp_addr).await, Protocol::Resp));
+    }
 
-    let shutdown_signal = tokio::signal::ctrl_c();
+    if let Some(http_settings) = &settings.http {
+        let http_addr: SocketAddr = format!("{}:{}", settings.host, http_settings.port).parse()?;
+        info!("Starting HTTP listener on {}", http_addr);
+        extra_listeners.push((bind(http_addr).await, Protocol::Http));
+    }
+
+    #[cfg(unix)]
+    let unix_listener = match &settings.unix_socket {
+        Some(unix_settings) => {
+            info!("Starting Unix socket listener on {}", unix_settings.path);
+            Some(bind_unix(unix_settings)?)
+        }
+        None => None,
+    };
+
+    let db = Arc::new(
+        Db::open(
+            settings.storage_engine.into(),
+            PathBuf::from(settings.aof_path),
+        )
+        .await?,
+    );
+
+    if let Some(max_memory) = settings.max_memory {
+        info!(
+            "Limiting memory to {} bytes with {:?} eviction",
+            max_memory, settings.eviction_policy
+        );
+        db.set_memory_limit(Some(MemoryLimit {
+            max_memory,
+            policy: settings.eviction_policy.into(),
+        }))?;
+    }
+
+    let state = Arc::new(ServerState {
+        db,
+        tls,
+        auth,
+        resp: settings.resp,
+        backup_dir: settings.backup_dir.map(PathBuf::from),
+        shutdown: CancellationToken::new(),
+        connections: TaskTracker::new(),
+    });
+
+    let mut accept_loops = JoinSet::new();
+    accept_loops.spawn(accept_connections(
+        listener,
+        state.clone(),
+        Protocol::Native,
+    ));
+    for (listener, protocol) in extra_listeners {
+        accept_loops.spawn(accept_connections(listener, state.clone(), protocol));
+    }
+    #[cfg(unix)]
+    if let Some(listener) = unix_listener {
+        accept_loops.spawn(accept_unix_connections(listener, state.clone()));
+    }
+
+    info!("red-db server ready to accept connections");
 
     tokio::select! {
-        _ = accept_connections(listener, db) => {
+        _ = accept_loops.join_next() => {
             info!("Accept loop ended");
         }
-        _ = shutdown_signal => {
+        _ = shutdown => {
             info!("Shutting down...");
         }
     }
 
+    // Dropping the accept loops closes the listeners.
+    accept_loops.abort_all();
+    while accept_loops.join_next().await.is_some() {}
+
+    #[cfg(unix)]
+    if let Some(unix_settings) = &settings.unix_socket {
+        let _ = std::fs::remove_file(&unix_settings.path);
+    }
+
+    state.shutdown.cancel();
+    state.connections.close();
+
+    let shutdown_timeout = Duration::from_secs(settings.shutdown_timeout_secs);
+    if tokio::time::timeout(shutdown_timeout, state.connections.wait())
+        .await
+        .is_err()
+    {
+        warn!(
+            "Timed out waiting for {} connection(s) to finish",
+            state.connections.len()
+        );
+    }
+
+    state.db.shutdown().await?;
+
+    info!("Shutdown complete");
+
     Ok(())
 }
 
-async fn accept_connections(listener: TcpListener, db: Arc<Db>) {
+async fn bind(addr: SocketAddr) -> TcpListener {
+    TcpListener::bind(&addr)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to bind to {addr}: {e}"))
+}
+
+#[cfg(unix)]
+fn bind_unix(settings: &UnixSocketSettings) -> std::io::Result<UnixListener> {
+    use std::{
+        fs,
+        os::unix::fs::{FileTypeExt, PermissionsExt},
+    };
+
+    // A socket left behind by a previous run would make the bind fail.
+    if let Ok(metadata) = fs::symlink_metadata(&settings.path)
+        && metadata.file_type().is_socket()
+    {
+        fs::remove_file(&settings.path)?;
+    }
+
+    let listener = UnixListener::bind(&settings.path)?;
+    fs::set_permissions(&settings.path, fs::Permissions::from_mode(settings.mode))?;
+
+    Ok(listener)
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+async fn accept_connections(listener: TcpListener, state: Arc<ServerState>, protocol: Protocol) {
     loop {
         match listener.accept().await {
             Ok(conn) => {
-                let db_clone = db.clone();
+                let state_clone = state.clone();
 
-                tokio::spawn(async move {
-                    if let Err(e) = handle_connection(db_clone, conn).await {
+                state.connections.spawn(async move {
+                    let _client = state_clone.db.track_client();
+                    if let Err(e) = serve_connection(state_clone, conn, protocol).await {
                         debug!("Connection error: {:?}", e);
                     }
                 });
@@ -63,82 +283,329 @@ async fn accept_connections(listener: TcpListener, db: Arc<Db>) {
     }
 }
 
+#[cfg(unix)]
+async fn accept_unix_connections(listener: UnixListener, state: Arc<ServerState>) {
+    use tracing::Instrument;
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let state_clone = state.clone();
+
+                // Access is controlled by the socket file's permissions, so TLS is not used here.
+                state.connections.spawn(
+                    async move {
+                        let _client = state_clone.db.track_client();
+                        if let Err(e) = handle_connection(&state_clone, stream).await {
+                            debug!("Connection error: {:?}", e);
+                        }
+                    }
+                    .instrument(tracing::info_span!("connection", client.addr = "unix")),
+                );
+            }
+            Err(e) => {
+                error!("Failed to accept connection: {}", e);
+            }
+        }
+    }
+}
+
 #[instrument(
     name = "connection",
-    skip(db, conn),
+    skip(state, conn),
     fields(
         client.addr = %conn.1,
+        protocol = ?protocol,
     )
 )]
-async fn handle_connection(
-    db: Arc<Db>,
+async fn serve_connection(
+    state: Arc<ServerState>,
     conn: (TcpStream, SocketAddr),
+    protocol: Protocol,
 ) -> Result<(), ConnectionError> {
-    let (mut stream, _) = conn;
+    let (stream, _) = conn;
     stream.set_nodelay(true).expect("Failed to set nodelay");
 
-    info!("New client connected");
+    match &state.tls {
+        Some(acceptor) => {
+            let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                .await
+                .map_err(|_| ConnectionError::Handshake("TLS handshake timed out".to_string()))?
+                .map_err(|e| {
+                    debug!("TLS handshake failed: {}", e);
+                    ConnectionError::Io(e)
+                })?;
+
+            speak(state, stream, protocol).await
+        }
+        None => speak(state, stream, protocol).await,
+    }
+}
+
+async fn speak<S>(
+    state: Arc<ServerState>,
+    stream: S,
+    protocol: Protocol,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    match protocol {
+        Protocol::Native => handle_connection(&state, stream).await,
+        Protocol::Resp => resp::handle_connection(&state, stream).await,
+        Protocol::Http => http::handle_connection(state, stream).await,
+    }
+}
+
+async fn handle_connection<S>(state: &ServerState, mut stream: S) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (hello, protocol_version) =
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(state, &mut stream))
+            .await
+            .map_err(|_| ConnectionError::Handshake("timed out".to_string()))??;
+
+    info!(
+        client.name = %hello.client_name,
+        client.protocol = protocol_version,
+        "New client connected"
+    );
+
+    let (mut reader, writer) = tokio::io::split(stream);
+    let (reply_sender, reply_receiver) = mpsc::channel(256);
+    let writer_task = tokio::spawn(write_replies(writer, reply_receiver, protocol_version));
+
+    let mut user: Option<Arc<User>> = None;
+    let mut in_flight = JoinSet::new();
 
     loop {
-        let command = read_command(&mut stream).await?;
+        let request = tokio::select! {
+            request = read_frame::<Request, _>(&mut reader, MAX_REQUEST_SIZE) => request?,
+            _ = state.shutdown.cancelled() => {
+                debug!("Closing connection for shutdown");
+                break;
+            }
+        };
+
+        let Some(Request { id, command }) = request else {
+            debug!("Client closed connection gracefully.");
+            break;
+        };
 
-        if let Some(cmd) = command {
-            let response = db.execute(cmd).await;
+        if command.protocol_version() > protocol_version {
+            return Err(ConnectionError::Protocol(format!(
+                "'{}' needs protocol version {}, the connection speaks {}",
+                command.name(),
+                command.protocol_version(),
+                protocol_version
+            )));
+        }
+
+        while in_flight.try_join_next().is_some() {}
+
+        // Reads run concurrently with each other, but anything else waits for the reads
+        // before it, so every request sees the effects of the requests sent before it.
+        if !command.is_read_only() {
+            while in_flight.join_next().await.is_some() {}
+        }
 
-            write_response(&mut stream, response).await?;
-        } else {
+        let response = match command {
+            Command::Auth { username, password } => {
+                authenticate(state, &mut user, username, password).await
+            }
+            command => match authorize(state, user.as_deref(), &command)
+                .and_then(|()| confine_backup(state, command))
+            {
+                Err(e) => Response::Error(e),
+                Ok(command) if command.is_read_only() => {
+                    // Replies to reads may overtake replies to earlier requests.
+                    let db = state.db.clone();
+                    let user = user.clone();
+                    let reply_sender = reply_sender.clone();
+
+                    in_flight.spawn(async move {
+                        let response = execute(&db, user.as_deref(), command).await;
+                        let _ = reply_sender.send(Reply { id, response }).await;
+                    });
+                    continue;
+                }
+                Ok(command) => execute(&state.db, user.as_deref(), command).await,
+            },
+        };
+
+        if reply_sender.send(Reply { id, response }).await.is_err() {
             break;
         }
     }
 
+    while in_flight.join_next().await.is_some() {}
+    drop(reply_sender);
+
+    writer_task
+        .await
+        .map_err(|e| ConnectionError::Protocol(format!("Writer task failed: {e}")))??;
+
     info!("Connection closed");
 
     Ok(())
 }
 
-async fn read_command(stream: &mut TcpStream) -> Result<Option<Command>, ConnectionError> {
-    let mut len_buf = [0u8; 4];
-    match stream.read_exact(&mut len_buf).await {
-        Ok(_) => {}
-        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-            debug!("Client closed connection gracefully.");
-            return Ok(None);
+/// Returns the client's hello and the protocol version both sides speak.
+async fn handshake<S>(state: &ServerState, stream: &mut S) -> Result<(Hello, u32), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut magic = [0u8; 4];
+    stream.read_exact(&mut magic).await?;
+
+    if magic != PROTOCOL_MAGIC {
+        return Err(ConnectionError::Handshake(
+            "peer does not speak the red-db protocol".to_string(),
+        ));
+    }
+
+    let hello: Hello = read_frame(stream, MAX_REQUEST_SIZE)
+        .await?
+        .ok_or_else(|| ConnectionError::Handshake("connection closed".to_string()))?;
+
+    let reply = match handshake::negotiate(hello.protocol_version) {
+        Ok(protocol_version) => {
+            let mut capabilities = vec![FEATURE_PIPELINING.to_string()];
+            if state.auth.is_some() {
+                capabilities.push(FEATURE_AUTH.to_string());
+            }
+
+            HelloReply::Accepted {
+                protocol_version,
+                server_version: env!("CARGO_PKG_VERSION").to_string(),
+                capabilities,
+            }
         }
-        Err(e) => {
-            debug!("Failed to read command length: {}", e);
+        Err(reason) => HelloReply::Rejected {
+            reason,
+            protocol_version: PROTOCOL_VERSION,
+        },
+    };
 
-            return Err(ConnectionError::Io(e));
+    write_frame(stream, &reply).await?;
+
+    match reply {
+        HelloReply::Accepted {
+            protocol_version, ..
+        } => Ok((hello, protocol_version)),
+        HelloReply::Rejected { reason, .. } => {
+            warn!("Rejected client '{}': {}", hello.client_name, reason);
+            Err(ConnectionError::Handshake(reason))
         }
     }
+}
 
-    let len = u32::from_le_bytes(len_buf) as usize;
+async fn write_replies<W>(
+    mut writer: W,
+    mut replies: mpsc::Receiver<Reply>,
+    protocol_version: u32,
+) -> Result<(), ConnectionError>
+where
+    W: AsyncWrite + Unpin,
+{
+    while let Some(Reply { id, response }) = replies.recv().await {
+        let reply = Reply {
+            id,
+            response: response.for_protocol(protocol_version),
+        };
+        writer.write_all(&encode_frame(&reply)?).await?;
 
-    if len > 1024 * 1024 {
-        return Err(ConnectionError::CommandTooLarge);
+        // Flush once the queue is drained so pipelined replies share a write.
+        if replies.is_empty() {
+            writer.flush().await?;
+        }
     }
 
-    let mut cmd_buf = vec![0u8; len];
-    stream
-        .read_exact(&mut cmd_buf)
-        .await
-        .map_err(ConnectionError::Io)?;
+    let _ = writer.shutdown().await;
+
+    Ok(())
+}
+
+async fn execute(db: &Db, user: Option<&User>, command: Command) -> Response {
+    // Exports leave out the spaces the user may not read before scanning them.
+    let reader = user
+        .filter(|_| matches!(command, Command::Export { .. }))
+        .cloned();
+    let readable = move |space: &str| {
+        reader
+            .as_ref()
+            .is_none_or(|user| user.is_allowed(Permission::Read, space))
+    };
+
+    match (db.execute_filtered(command, readable).await, user) {
+        (Response::Spaces(spaces), Some(user)) => Response::Spaces(user.readable_spaces(spaces)),
+        (Response::Info(mut info), Some(user)) => {
+            if let Some(spaces) = &mut info.spaces {
+                spaces.retain(|space| user.is_allowed(Permission::Read, &space.name));
+            }
+            Response::Info(info)
+        }
+        (response, _) => response,
+    }
+}
+
+async fn authenticate(
+    state: &ServerState,
+    user: &mut Option<Arc<User>>,
+    username: String,
+    password: String,
+) -> Response {
+    let Some(auth) = &state.auth else {
+        return Response::Ok;
+    };
 
-    bincode::decode_from_slice(&cmd_buf, bincode::config::standard())
-        .map(|(cmd, _)| Some(cmd))
-        .map_err(|e| ConnectionError::Protocol(format!("Decode error: {e}")))
+    match auth.authenticate(&username, password).await {
+        Some(authenticated) => {
+            info!("Authenticated as '{}'", username);
+            *user = Some(Arc::new(authenticated));
+            Response::Ok
+        }
+        None => {
+            warn!("Failed authentication attempt for '{}'", username);
+            *user = None;
+            Response::Error(ServerError::AuthenticationFailed)
+        }
+    }
 }
 
-async fn write_response(stream: &mut TcpStream, response: Response) -> Result<(), ConnectionError> {
-    let data = bincode::encode_to_vec(&response, bincode::config::standard())
-        .map_err(|e| ConnectionError::Protocol(format!("Encode error: {e}")))?;
+/// Points a `Backup` at its file in the configured backup directory, so clients can't
+/// have the server write anywhere else.
+fn confine_backup(state: &ServerState, command: Command) -> Result<Command, ServerError> {
+    let Command::Backup { dest_path } = command else {
+        return Ok(command);
+    };
+    let Some(backup_dir) = &state.backup_dir else {
+        return Err(ServerError::BackupFailed(
+            "backups are disabled, no backup_dir is configured".to_string(),
+        ));
+    };
 
-    let len_bytes = (data.len() as u32).to_le_bytes();
+    let dest_path = backup::resolve_path(backup_dir, &dest_path)?;
+    Ok(Command::Backup {
+        dest_path: dest_path.to_string_lossy().into_owned(),
+    })
+}
 
-    stream
-        .write_all(&len_bytes)
-        .await
-        .map_err(ConnectionError::Io)?;
-    stream.write_all(&data).await.map_err(ConnectionError::Io)?;
+fn authorize(
+    state: &ServerState,
+    user: Option<&User>,
+    command: &Command,
+) -> Result<(), ServerError> {
+    // Pings don't touch any data, so health checks work before authenticating.
+    if state.auth.is_none() || matches!(command, Command::Ping { .. }) {
+        return Ok(());
+    }
 
-    Ok(())
+    match user {
+        Some(user) => user.check(command),
+        None => Err(ServerError::PermissionDenied(
+            "authentication required".to_string(),
+        )),
+    }
 }
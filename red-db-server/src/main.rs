@@ -1,8 +1,44 @@
-use red_db_server::{run_server, settings::Settings};
+use std::path::Path;
+
+use red_db_core::backup;
+use red_db_server::{auth::hash_password, run_server, settings::Settings};
+use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if let [command, password] = args.as_slice()
+        && command == "hash-password"
+    {
+        println!("{}", hash_password(password)?);
+        return Ok(());
+    }
+
     tracing_subscriber::fmt::init();
 
-    run_server(Settings::read()).await
+    let settings = Settings::read();
+
+    // `--restore <archive>` replaces the AOF with the archive's contents before starting.
+    if let [flag, archive] = args.as_slice()
+        && flag == "--restore"
+    {
+        let metadata = backup::restore(
+            Path::new(archive),
+            Path::new(&settings.aof_path),
+            settings.storage_engine.into(),
+        )
+        .await
+        .map_err(|e| format!("Failed to restore {archive}: {e}"))?;
+        info!(
+            "Restored {} keys in {} spaces from {} (red-db {}, created at {} ms)",
+            metadata.keys,
+            metadata.spaces.len(),
+            archive,
+            metadata.server_version,
+            metadata.created_at
+        );
+    }
+
+    run_server(settings).await
 }
@@ -0,0 +1,527 @@
+mod value;
+
+use std::sync::Arc;
+
+use red_db_core::{
+    error::ServerError,
+    proto::{
+        Command, Response,
+        info::{Info, InfoSection},
+    },
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+
+use crate::{ServerState, auth::User, authorize, confine_backup, error::ConnectionError, execute};
+use value::{Value, read_command};
+
+/// Serves one Redis client. Commands are answered in order, which also makes
+/// RESP pipelining work without any extra bookkeeping.
+pub(crate) async fn handle_connection<S>(
+    state: &ServerState,
+    stream: S,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    info!("New RESP client connected");
+
+    let mut stream = BufReader::new(stream);
+    let mut session = Session::new(state);
+    let mut out = Vec::new();
+
+    loop {
+        let args = tokio::select! {
+            args = read_command(&mut stream) => args?,
+            _ = state.shutdown.cancelled() => {
+                debug!("Closing RESP connection for shutdown");
+                break;
+            }
+        };
+
+        let Some(args) = args else {
+            break;
+        };
+
+        let (reply, close) = session.dispatch(args).await;
+
+        out.clear();
+        reply.encode(session.resp3, &mut out);
+        stream.get_mut().write_all(&out).await?;
+
+        // Flush once the client has nothing else buffered, so pipelined
+        // commands share a write.
+        if stream.buffer().is_empty() || close {
+            stream.get_mut().flush().await?;
+        }
+
+        if close {
+            break;
+        }
+    }
+
+    let _ = stream.get_mut().shutdown().await;
+
+    info!("RESP connection closed");
+
+    Ok(())
+}
+
+struct Session<'a> {
+    state: &'a ServerState,
+    user: Option<Arc<User>>,
+    space: String,
+    resp3: bool,
+}
+
+impl<'a> Session<'a> {
+    fn new(state: &'a ServerState) -> Self {
+        let space = state
+            .resp
+            .as_ref()
+            .map(|resp| resp.default_space.clone())
+            .unwrap_or_default();
+
+        Self {
+            state,
+            user: None,
+            space,
+            resp3: false,
+        }
+    }
+
+    fn separator(&self) -> Option<&str> {
+        self.state
+            .resp
+            .as_ref()
+            .and_then(|resp| resp.key_separator.as_deref())
+    }
+
+    async fn dispatch(&mut self, args: Vec<Vec<u8>>) -> (Value, bool) {
+        let raw_name = String::from_utf8_lossy(&args[0]).into_owned();
+        let name = raw_name.to_ascii_uppercase();
+        let args = &args[1..];
+
+        let reply = match name.as_str() {
+            "PING" => match args {
+                [] => Value::SimpleString("PONG".to_string()),
+                [message] => Value::bulk(message.clone()),
+                _ => wrong_arity(&name),
+            },
+            "QUIT" => return (Value::ok(), true),
+            "HELLO" => self.hello(args).await,
+            "AUTH" => match args {
+                [password] => self.auth("default", password).await,
+                [username, password] => {
+                    self.auth(&String::from_utf8_lossy(username), password)
+                        .await
+                }
+                _ => wrong_arity(&name),
+            },
+            _ if self.state.auth.is_some() && self.user.is_none() => {
+                Value::error("NOAUTH Authentication required.")
+            }
+            "GET" => match args {
+                [key] => self.get(key).await,
+                _ => wrong_arity(&name),
+            },
+            "SET" => match args {
+                [key, value] => self.set(key, value.clone()).await,
+                [_, _, ..] => Value::error("ERR SET options are not supported"),
+                _ => wrong_arity(&name),
+            },
+            "DEL" if !args.is_empty() => self.del(args).await,
+            "KEYS" => match args {
+                [pattern] => self.keys(pattern).await,
+                _ => wrong_arity(&name),
+            },
+            "SELECT" => match args {
+                [space] => self.select(space).await,
+                _ => wrong_arity(&name),
+            },
+            "INFO" => match args {
+                [] => self.info(None).await,
+                [section] => {
+                    self.info(Some(&String::from_utf8_lossy(section).to_ascii_lowercase()))
+                        .await
+                }
+                _ => wrong_arity(&name),
+            },
+            "DEL" => wrong_arity(&name),
+            _ => Value::error(format!("ERR unknown command '{raw_name}'")),
+        };
+
+        (reply, false)
+    }
+
+    async fn hello(&mut self, args: &[Vec<u8>]) -> Value {
+        let mut args = args.iter();
+
+        if let Some(version) = args.next() {
+            match version.as_slice() {
+                b"2" => self.resp3 = false,
+                b"3" => self.resp3 = true,
+                _ => return Value::error("NOPROTO unsupported protocol version"),
+            }
+        }
+
+        while let Some(option) = args.next() {
+            match option.to_ascii_uppercase().as_slice() {
+                b"AUTH" => {
+                    let (Some(username), Some(password)) = (args.next(), args.next()) else {
+                        return Value::error("ERR syntax error");
+                    };
+                    if let err @ Value::Error(_) = self
+                        .auth(&String::from_utf8_lossy(username), password)
+                        .await
+                    {
+                        return err;
+                    }
+                }
+                b"SETNAME" => {
+                    args.next();
+                }
+                _ => return Value::error("ERR syntax error"),
+            }
+        }
+
+        if self.state.auth.is_some() && self.user.is_none() {
+            return Value::error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time",
+            );
+        }
+
+        let field = |name: &str, value: Value| (Value::bulk(name), value);
+
+        Value::Map(vec![
+            field("server", Value::bulk("red-db")),
+            field("version", Value::bulk(env!("CARGO_PKG_VERSION"))),
+            field("proto", Value::Integer(if self.resp3 { 3 } else { 2 })),
+            field("id", Value::Integer(0)),
+            field("mode", Value::bulk("standalone")),
+            field("role", Value::bulk("master")),
+            field("modules", Value::Array(Vec::new())),
+        ])
+    }
+
+    async fn auth(&mut self, username: &str, password: &[u8]) -> Value {
+        let Some(auth) = &self.state.auth else {
+            return Value::error(
+                "ERR AUTH called without any password configured for the default user",
+            );
+        };
+
+        match auth
+            .authenticate(username, String::from_utf8_lossy(password).into_owned())
+            .await
+        {
+            Some(user) => {
+                info!("RESP client authenticated as '{}'", username);
+                self.user = Some(Arc::new(user));
+                Value::ok()
+            }
+            None => {
+                warn!("Failed RESP authentication attempt for '{}'", username);
+                Value::error("WRONGPASS invalid username-password pair or user is disabled.")
+            }
+        }
+    }
+
+    async fn run(&self, command: Command) -> Response {
+        match authorize(self.state, self.user.as_deref(), &command)
+            .and_then(|()| confine_backup(self.state, command))
+        {
+            Ok(command) => execute(&self.state.db, self.user.as_deref(), command).await,
+            Err(e) => Response::Error(e),
+        }
+    }
+
+    /// Maps a Redis key onto a red-db space and key.
+    fn locate(&self, key: &[u8]) -> Result<(String, String), Value> {
+        let key =
+            std::str::from_utf8(key).map_err(|_| Value::error("ERR keys must be valid UTF-8"))?;
+
+        match self.separator().and_then(|sep| key.split_once(sep)) {
+            Some((space, key)) if !space.is_empty() => Ok((space.to_string(), key.to_string())),
+            _ => Ok((self.space.clone(), key.to_string())),
+        }
+    }
+
+    async fn get(&self, key: &[u8]) -> Value {
+        let (space, key) = match self.locate(key) {
+            Ok(location) => location,
+            Err(e) => return e,
+        };
+
+        match self.run(Command::Get { space, key }).await {
+            Response::Value(Some(value)) => Value::BulkString(value),
+            Response::Value(None) | Response::Error(ServerError::SpaceNotFound(_)) => Value::Null,
+            response => unexpected(response),
+        }
+    }
+
+    async fn set(&self, key: &[u8], value: Vec<u8>) -> Value {
+        let (space, key) = match self.locate(key) {
+            Ok(location) => location,
+            Err(e) => return e,
+        };
+
+        match self.run(Command::Set { space, key, value }).await {
+            Response::Ok => Value::ok(),
+            response => unexpected(response),
+        }
+    }
+
+    async fn del(&self, keys: &[Vec<u8>]) -> Value {
+        let mut deleted = 0;
+
+        for key in keys {
+            let (space, key) = match self.locate(key) {
+                Ok(location) => location,
+                Err(e) => return e,
+            };
+
+            let get = Command::Get {
+                space: space.clone(),
+                key: key.clone(),
+            };
+            match self.run(get).await {
+                Response::Value(Some(_)) => {}
+                Response::Value(None) | Response::Error(ServerError::SpaceNotFound(_)) => continue,
+                response => return unexpected(response),
+            }
+
+            match self.run(Command::Delete { space, key }).await {
+                Response::Ok => deleted += 1,
+                response => return unexpected(response),
+            }
+        }
+
+        Value::Integer(deleted)
+    }
+
+    async fn keys(&self, pattern: &[u8]) -> Value {
+        let spaces = match self.separator() {
+            None => vec![self.space.clone()],
+            Some(_) => match self.run(Command::ListSpaces).await {
+                Response::Spaces(spaces) => spaces,
+                response => return unexpected(response),
+            },
+        };
+
+        let mut matches = Vec::new();
+        for space in spaces {
+            let keys = match self
+                .run(Command::ListKeys {
+                    space: space.clone(),
+                })
+                .await
+            {
+                Response::Keys(keys) => keys,
+                Response::Error(ServerError::SpaceNotFound(_)) => continue,
+                response => return unexpected(response),
+            };
+
+            for key in keys {
+                let name = match self.separator() {
+                    Some(sep) if space != self.space => format!("{space}{sep}{key}"),
+                    _ => key,
+                };
+
+                if glob_match(pattern, name.as_bytes()) {
+                    matches.push(Value::bulk(name));
+                }
+            }
+        }
+
+        Value::Array(matches)
+    }
+
+    async fn select(&mut self, space: &[u8]) -> Value {
+        if self.separator().is_some() {
+            return Value::error(
+                "ERR SELECT is not supported when spaces are mapped to key prefixes",
+            );
+        }
+
+        let Ok(space) = String::from_utf8(space.to_vec()) else {
+            return Value::error("ERR space names must be valid UTF-8");
+        };
+
+        // Selecting an empty database is fine in redis, and spaces are created
+        // on the first SET, so only check that the user may read it.
+        match authorize(
+            self.state,
+            self.user.as_deref(),
+            &Command::IsSpaceExists {
+                space: space.clone(),
+            },
+        ) {
+            Ok(()) => {
+                self.space = space;
+                Value::ok()
+            }
+            Err(e) => error_value(&e),
+        }
+    }
+
+    async fn info(&self, section: Option<&str>) -> Value {
+        let wants = |name: &str| {
+            matches!(section, None | Some("all" | "everything" | "default"))
+                || section == Some(name)
+        };
+        let mut info = String::new();
+
+        if wants("server") {
+            let server = match self
+                .run(Command::Info {
+                    section: Some(InfoSection::Server),
+                })
+                .await
+            {
+                Response::Info(Info {
+                    server: Some(server),
+                    ..
+                }) => server,
+                response => return unexpected(response),
+            };
+            info.push_str("# Server\r\n");
+            info.push_str("redis_version:7.0.0\r\n");
+            info.push_str(&format!("red_db_version:{}\r\n", server.version));
+            info.push_str("redis_mode:standalone\r\n");
+            info.push_str(&format!("uptime_in_seconds:{}\r\n", server.uptime_secs));
+            info.push_str("\r\n");
+        }
+
+        if wants("clients") {
+            let clients = match self
+                .run(Command::Info {
+                    section: Some(InfoSection::Clients),
+                })
+                .await
+            {
+                Response::Info(Info {
+                    clients: Some(clients),
+                    ..
+                }) => clients,
+                response => return unexpected(response),
+            };
+            info.push_str("# Clients\r\n");
+            info.push_str(&format!("connected_clients:{}\r\n", clients.connected));
+            info.push_str("\r\n");
+        }
+
+        if wants("keyspace") {
+            let spaces = match self
+                .run(Command::Info {
+                    section: Some(InfoSection::Spaces),
+                })
+                .await
+            {
+                Response::Info(Info {
+                    spaces: Some(spaces),
+                    ..
+                }) => spaces,
+                response => return unexpected(response),
+            };
+            info.push_str("# Keyspace\r\n");
+            for space in spaces {
+                info.push_str(&format!(
+                    "{}:keys={},expires=0,avg_ttl=0\r\n",
+                    space.name, space.usage.keys
+                ));
+            }
+        }
+
+        Value::bulk(info)
+    }
+}
+
+fn wrong_arity(name: &str) -> Value {
+    Value::error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name.to_ascii_lowercase()
+    ))
+}
+
+fn unexpected(response: Response) -> Value {
+    match response {
+        Response::Error(e) => error_value(&e),
+        response => Value::error(format!("ERR unexpected response {response:?}")),
+    }
+}
+
+fn error_value(error: &ServerError) -> Value {
+    match error {
+        ServerError::PermissionDenied(_) => Value::error(format!("NOPERM {error}")),
+        ServerError::AuthenticationFailed => {
+            Value::error("WRONGPASS invalid username-password pair or user is disabled.")
+        }
+        ServerError::OutOfMemory => Value::error(format!("OOM {error}")),
+        ServerError::SpaceReadOnly(_) => Value::error(format!("READONLY {error}")),
+        e => Value::error(format!("ERR {e}")),
+    }
+}
+
+/// Redis-style glob matching supporting `*`, `?`, `[...]` classes and `\` escapes.
+///
+/// Every token but `*` matches exactly one byte, so when a token fails to match it is
+/// enough to let the most recent `*` swallow one more byte and retry from there.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The pattern position after the most recent `*` and the text position it has
+    // swallowed up to.
+    let mut star = None;
+
+    while t < text.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, t));
+        } else if let Some(next) = match_token(pattern, p, text[t]) {
+            p = next;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches the token starting at `pattern[p]` against `c` and returns where the next
+/// token starts.
+fn match_token(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let Some(end) = pattern[p + 1..].iter().position(|&b| b == b']') else {
+                return (c == b'[').then_some(p + 1);
+            };
+
+            let (negate, class) = match &pattern[p + 1..p + 1 + end] {
+                [b'^', class @ ..] => (true, class),
+                class => (false, class),
+            };
+
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    matched |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+
+            (matched != negate).then_some(p + end + 2)
+        }
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        &b => (b == c).then_some(p + 1),
+    }
+}
@@ -0,0 +1,181 @@
+use red_db_core::proto::frame::MAX_REQUEST_SIZE;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::error::ConnectionError;
+
+/// The most bytes all arguments of one command may add up to, the same as for a
+/// native request.
+const MAX_COMMAND_LEN: usize = MAX_REQUEST_SIZE;
+const MAX_ARRAY_LEN: usize = 1024 * 1024;
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    SimpleString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(Vec<u8>),
+    Null,
+    Array(Vec<Value>),
+    /// Encoded as a RESP3 map, or as a flat key/value array for RESP2 clients.
+    Map(Vec<(Value, Value)>),
+}
+
+impl Value {
+    pub fn ok() -> Self {
+        Value::SimpleString("OK".to_string())
+    }
+
+    pub fn error<T: Into<String>>(message: T) -> Self {
+        Value::Error(message.into())
+    }
+
+    pub fn bulk<T: Into<Vec<u8>>>(value: T) -> Self {
+        Value::BulkString(value.into())
+    }
+
+    pub fn encode(&self, resp3: bool, out: &mut Vec<u8>) {
+        match self {
+            Value::SimpleString(s) => write_line(out, b'+', s.as_bytes()),
+            Value::Error(e) => write_line(out, b'-', e.replace(['\r', '\n'], " ").as_bytes()),
+            Value::Integer(n) => write_line(out, b':', n.to_string().as_bytes()),
+            Value::BulkString(bytes) => {
+                write_line(out, b'$', bytes.len().to_string().as_bytes());
+                out.extend_from_slice(bytes);
+                out.extend_from_slice(b"\r\n");
+            }
+            Value::Null if resp3 => out.extend_from_slice(b"_\r\n"),
+            Value::Null => out.extend_from_slice(b"$-1\r\n"),
+            Value::Array(items) => {
+                write_line(out, b'*', items.len().to_string().as_bytes());
+                for item in items {
+                    item.encode(resp3, out);
+                }
+            }
+            Value::Map(entries) => {
+                if resp3 {
+                    write_line(out, b'%', entries.len().to_string().as_bytes());
+                } else {
+                    write_line(out, b'*', (entries.len() * 2).to_string().as_bytes());
+                }
+                for (key, value) in entries {
+                    key.encode(resp3, out);
+                    value.encode(resp3, out);
+                }
+            }
+        }
+    }
+}
+
+fn write_line(out: &mut Vec<u8>, prefix: u8, body: &[u8]) {
+    out.push(prefix);
+    out.extend_from_slice(body);
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Reads one client command: either an array of bulk strings, as sent by client
+/// libraries, or an inline command line as typed into telnet.
+/// Returns `Ok(None)` when the client closes the connection between commands.
+pub async fn read_command<R>(reader: &mut R) -> Result<Option<Vec<Vec<u8>>>, ConnectionError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    loop {
+        let Some(line) = read_line(reader, MAX_INLINE_LEN).await? else {
+            return Ok(None);
+        };
+
+        let Some(rest) = line.strip_prefix(b"*") else {
+            let args: Vec<Vec<u8>> = line
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+
+            // Blank lines are ignored, like redis does.
+            if args.is_empty() {
+                continue;
+            }
+
+            return Ok(Some(args));
+        };
+
+        let count = parse_len(rest, MAX_ARRAY_LEN)?;
+        // So are empty arrays.
+        if count == 0 {
+            continue;
+        }
+
+        let mut args = Vec::with_capacity(count.min(64));
+        let mut remaining = MAX_COMMAND_LEN;
+
+        for _ in 0..count {
+            let line = read_line(reader, MAX_INLINE_LEN)
+                .await?
+                .ok_or_else(unexpected_eof)?;
+            let len_bytes = line
+                .strip_prefix(b"$")
+                .ok_or_else(|| ConnectionError::Protocol("expected a bulk string".to_string()))?;
+            let len = parse_len(len_bytes, remaining)?;
+            remaining -= len;
+
+            let mut arg = vec![0u8; len + 2];
+            reader.read_exact(&mut arg).await?;
+            if !arg.ends_with(b"\r\n") {
+                return Err(ConnectionError::Protocol(
+                    "bulk string is not terminated by CRLF".to_string(),
+                ));
+            }
+            arg.truncate(len);
+            args.push(arg);
+        }
+
+        return Ok(Some(args));
+    }
+}
+
+async fn read_line<R>(reader: &mut R, max_len: usize) -> Result<Option<Vec<u8>>, ConnectionError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    let read = (&mut *reader)
+        .take(max_len as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .await?;
+
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(if line.len() > max_len {
+            ConnectionError::CommandTooLarge
+        } else {
+            unexpected_eof()
+        });
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+
+    Ok(Some(line))
+}
+
+fn parse_len(bytes: &[u8], max: usize) -> Result<usize, ConnectionError> {
+    let len = std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .ok_or_else(|| ConnectionError::Protocol("invalid length".to_string()))?;
+
+    if len > max {
+        return Err(ConnectionError::CommandTooLarge);
+    }
+
+    Ok(len)
+}
+
+fn unexpected_eof() -> ConnectionError {
+    ConnectionError::Io(std::io::ErrorKind::UnexpectedEof.into())
+}
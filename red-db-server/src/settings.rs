@@ -1,7 +1,8 @@
 use config::Config;
+use red_db_core::storage::{EngineKind, memory};
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug)]
 pub struct Settings {
     #[serde(default = "default_host")]
     pub host: String,
@@ -9,6 +10,176 @@ pub struct Settings {
     pub port: u16,
     #[serde(default = "default_aof_path")]
     pub aof_path: String,
+    #[serde(default)]
+    pub storage_engine: StorageEngine,
+    /// Approximate memory limit of the store in bytes. Unlimited if unset.
+    #[serde(default)]
+    pub max_memory: Option<usize>,
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Directory `Command::Backup` writes archives to; paths sent by clients are resolved
+    /// inside it. Backups are refused when it is unset.
+    #[serde(default)]
+    pub backup_dir: Option<String>,
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+    #[serde(default)]
+    pub auth: Option<AuthSettings>,
+    #[serde(default)]
+    pub resp: Option<RespSettings>,
+    #[serde(default)]
+    pub http: Option<HttpSettings>,
+    #[serde(default)]
+    pub unix_socket: Option<UnixSocketSettings>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            host: default_host(),
+            port: default_port(),
+            aof_path: default_aof_path(),
+            storage_engine: StorageEngine::default(),
+            max_memory: None,
+            eviction_policy: EvictionPolicy::default(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            backup_dir: None,
+            tls: None,
+            auth: None,
+            resp: None,
+            http: None,
+            unix_socket: None,
+        }
+    }
+}
+
+/// Where the data lives; `aof_path` is the file or directory the engine keeps it in.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageEngine {
+    /// Everything in memory, persisted to the append-only file at `aof_path`.
+    #[default]
+    Memory,
+    /// A log-structured merge tree on disk; `aof_path` is the directory it is kept in.
+    Lsm,
+}
+
+/// What to evict when a write would exceed `max_memory`.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    AllkeysLru,
+    AllkeysLfu,
+    VolatileTtl,
+    Random,
+    #[default]
+    Noeviction,
+}
+
+impl From<EvictionPolicy> for memory::EvictionPolicy {
+    fn from(policy: EvictionPolicy) -> Self {
+        match policy {
+            EvictionPolicy::AllkeysLru => memory::EvictionPolicy::AllKeysLru,
+            EvictionPolicy::AllkeysLfu => memory::EvictionPolicy::AllKeysLfu,
+            EvictionPolicy::VolatileTtl => memory::EvictionPolicy::VolatileTtl,
+            EvictionPolicy::Random => memory::EvictionPolicy::Random,
+            EvictionPolicy::Noeviction => memory::EvictionPolicy::NoEviction,
+        }
+    }
+}
+
+impl From<StorageEngine> for EngineKind {
+    fn from(engine: StorageEngine) -> Self {
+        match engine {
+            StorageEngine::Memory => EngineKind::Memory,
+            StorageEngine::Lsm => EngineKind::Lsm,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UnixSocketSettings {
+    pub path: String,
+    /// Permission bits of the socket file. Only local users who may write to it can connect.
+    #[serde(default = "default_unix_socket_mode")]
+    pub mode: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HttpSettings {
+    #[serde(default = "default_http_port")]
+    pub port: u16,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            port: default_http_port(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RespSettings {
+    #[serde(default = "default_resp_port")]
+    pub port: u16,
+    /// Space used before the client sends `SELECT`, and for keys without a prefix.
+    #[serde(default = "default_resp_space")]
+    pub default_space: String,
+    /// When set, `users:1` addresses key `1` in space `users` and `SELECT` is disabled.
+    #[serde(default)]
+    pub key_separator: Option<String>,
+}
+
+impl Default for RespSettings {
+    fn default() -> Self {
+        Self {
+            port: default_resp_port(),
+            default_space: default_resp_space(),
+            key_separator: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+    /// When set, clients must present a certificate signed by one of these CAs.
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct AuthSettings {
+    #[serde(default)]
+    pub users: Vec<UserSettings>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct UserSettings {
+    pub username: String,
+    /// Argon2 PHC string, as printed by `red-db-server hash-password <password>`.
+    pub password_hash: String,
+    #[serde(default)]
+    pub rules: Vec<AclRule>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct AclRule {
+    /// Space names; `*` matches any space and `prefix*` matches by prefix.
+    pub spaces: Vec<String>,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+    Admin,
 }
 
 fn default_host() -> String {
@@ -23,6 +194,26 @@ fn default_aof_path() -> String {
     "aof.rdb".to_string()
 }
 
+fn default_http_port() -> u16 {
+    8080
+}
+
+fn default_resp_port() -> u16 {
+    6379
+}
+
+fn default_resp_space() -> String {
+    "0".to_string()
+}
+
+fn default_unix_socket_mode() -> u32 {
+    0o660
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
 impl Settings {
     pub fn read() -> Self {
         let settings = Config::builder()
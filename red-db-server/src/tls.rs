@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        RootCertStore, ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::WebPkiClientVerifier,
+    },
+};
+
+use crate::{error::TlsError, settings::TlsSettings};
+
+pub fn build_acceptor(settings: &TlsSettings) -> Result<TlsAcceptor, TlsError> {
+    let provider = Arc::new(ring::default_provider());
+
+    let certs = load_certs(&settings.cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(&settings.key_path)
+        .map_err(|e| TlsError::PrivateKey(settings.key_path.clone(), e.to_string()))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| TlsError::Config(e.to_string()))?;
+
+    let builder = match &settings.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| TlsError::Certificate(ca_path.clone(), e.to_string()))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| TlsError::Config(e.to_string()))?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| TlsError::Config(e.to_string()))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| TlsError::Certificate(path.to_string(), e.to_string()))?;
+
+    if certs.is_empty() {
+        return Err(TlsError::Certificate(
+            path.to_string(),
+            "no certificates found".to_string(),
+        ));
+    }
+
+    Ok(certs)
+}
@@ -1,13 +1,40 @@
 use std::{
     net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     time::Duration,
 };
 
-use tempfile::tempdir;
-use tokio::time::sleep;
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use tempfile::{TempDir, tempdir};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::oneshot,
+    time::sleep,
+};
 
-use red_db_client::ClientBuilder;
-use red_db_server::settings::Settings;
+use red_db_client::{ClientBuilder, RetryPolicy, TlsConfig, error::ClientError};
+use red_db_core::{
+    db::Db,
+    error::ServerError,
+    proto::{
+        Command, ImportOptions, Reply, Request, Response, SpaceConfig,
+        frame::{MAX_REQUEST_SIZE, MAX_RESPONSE_SIZE, read_frame, write_frame},
+        handshake::{Hello, HelloReply, PROTOCOL_MAGIC, PROTOCOL_VERSION},
+        info::InfoSection,
+    },
+};
+use red_db_server::{
+    auth::hash_password,
+    settings::{
+        AclRule, AuthSettings, EvictionPolicy, HttpSettings, Permission, RespSettings, Settings,
+        TlsSettings, UnixSocketSettings, UserSettings,
+    },
+};
 
 fn find_free_port() -> u16 {
     TcpListener::bind("127.0.0.1:0")
@@ -32,9 +59,12 @@ pub async fn wait_for_port(port: u16, timeout_ms: u64) -> bool {
 }
 
 async fn start_server() -> u16 {
+    start_server_with(Settings::default()).await
+}
+
+async fn start_server_with(mut settings: Settings) -> u16 {
     let _ = tracing_subscriber::fmt::try_init();
 
-    let mut settings = Settings::default();
     let port = find_free_port();
     settings.port = port;
     settings.host = "127.0.0.1".to_string();
@@ -93,3 +123,1410 @@ async fn test_basic_connection() {
         .expect("Failed to get key");
     assert_eq!(result, Some("test_value".to_string()));
 }
+
+struct TestCerts {
+    dir: TempDir,
+}
+
+impl TestCerts {
+    fn generate() -> Self {
+        let dir = tempdir().expect("Failed to create temp dir");
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "red-db test CA");
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+        let issue = |name: &str, usage: ExtendedKeyUsagePurpose| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            params.extended_key_usages = vec![usage];
+            let cert = params.signed_by(&key, &ca_cert, &ca_key).unwrap();
+            (cert.pem(), key.serialize_pem())
+        };
+
+        let (server_cert, server_key) = issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+        let (client_cert, client_key) = issue("client", ExtendedKeyUsagePurpose::ClientAuth);
+
+        let files = [
+            ("ca.pem", ca_cert.pem()),
+            ("server.pem", server_cert),
+            ("server.key", server_key),
+            ("client.pem", client_cert),
+            ("client.key", client_key),
+        ];
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        }
+
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn server_settings(&self, require_client_cert: bool) -> Settings {
+        let path = |name: &str| self.path(name).to_string_lossy().to_string();
+
+        Settings {
+            tls: Some(TlsSettings {
+                cert_path: path("server.pem"),
+                key_path: path("server.key"),
+                client_ca_path: require_client_cert.then(|| path("ca.pem")),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+async fn round_trip(builder: ClientBuilder) -> Result<(), ClientError> {
+    let client = builder.build().await?;
+
+    client.create_space("tls_space".to_string()).await?;
+    let space = client.space("tls_space".to_string()).await?;
+    space.set_string("key", "secret").await?;
+
+    assert_eq!(space.get_string("key").await?, Some("secret".to_string()));
+
+    Ok(())
+}
+
+fn tls_client(port: u16, ca_path: &Path) -> ClientBuilder {
+    ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_tls(TlsConfig::new("localhost", ca_path.to_path_buf()))
+}
+
+#[tokio::test]
+async fn test_tls_connection() {
+    let certs = TestCerts::generate();
+    let port = start_server_with(certs.server_settings(false)).await;
+
+    round_trip(tls_client(port, &certs.path("ca.pem")))
+        .await
+        .expect("TLS round trip failed");
+}
+
+#[tokio::test]
+async fn test_tls_rejects_plaintext_client() {
+    let certs = TestCerts::generate();
+    let port = start_server_with(certs.server_settings(false)).await;
+
+    let result =
+        round_trip(ClientBuilder::new().with_server_addr(SocketAddr::from(([127, 0, 0, 1], port))))
+            .await;
+
+    assert!(
+        result.is_err(),
+        "Plaintext client must not talk to a TLS server"
+    );
+}
+
+#[tokio::test]
+async fn test_tls_rejects_untrusted_server() {
+    let certs = TestCerts::generate();
+    let other_certs = TestCerts::generate();
+    let port = start_server_with(certs.server_settings(false)).await;
+
+    let result = round_trip(tls_client(port, &other_certs.path("ca.pem"))).await;
+
+    assert!(result.is_err(), "Client must reject an unknown server CA");
+}
+
+#[tokio::test]
+async fn test_mutual_tls() {
+    let certs = TestCerts::generate();
+    let port = start_server_with(certs.server_settings(true)).await;
+
+    let result = round_trip(tls_client(port, &certs.path("ca.pem"))).await;
+    assert!(result.is_err(), "Server must require a client certificate");
+
+    let tls = TlsConfig::new("localhost", certs.path("ca.pem"))
+        .with_client_cert(certs.path("client.pem"), certs.path("client.key"));
+    round_trip(
+        ClientBuilder::new()
+            .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+            .with_tls(tls),
+    )
+    .await
+    .expect("Mutual TLS round trip failed");
+}
+
+fn auth_settings() -> Settings {
+    let user = |username: &str, password: &str, rules: Vec<AclRule>| UserSettings {
+        username: username.to_string(),
+        password_hash: hash_password(password).unwrap(),
+        rules,
+    };
+
+    Settings {
+        auth: Some(AuthSettings {
+            users: vec![
+                user(
+                    "admin",
+                    "admin-pass",
+                    vec![AclRule {
+                        spaces: vec!["*".to_string()],
+                        permissions: vec![Permission::Read, Permission::Write, Permission::Admin],
+                    }],
+                ),
+                user(
+                    "reader",
+                    "reader-pass",
+                    vec![AclRule {
+                        spaces: vec!["public_*".to_string()],
+                        permissions: vec![Permission::Read],
+                    }],
+                ),
+            ],
+        }),
+        ..Default::default()
+    }
+}
+
+fn is_server_error<T>(result: Result<T, ClientError>, check: fn(&ServerError) -> bool) -> bool {
+    matches!(result, Err(ClientError::Server(ref e)) if check(e))
+}
+
+#[tokio::test]
+async fn test_auth_required() {
+    let port = start_server_with(auth_settings()).await;
+
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .await
+        .unwrap();
+
+    let result = client.create_space("public_data".to_string()).await;
+    assert!(
+        is_server_error(result, |e| matches!(e, ServerError::PermissionDenied(_))),
+        "Unauthenticated commands must be denied"
+    );
+
+    client
+        .ping()
+        .await
+        .expect("Ping must work without authenticating");
+
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_credentials("admin", "wrong-pass")
+        .build()
+        .await
+        .unwrap();
+
+    let result = client.create_space("public_data".to_string()).await;
+    assert!(
+        is_server_error(result, |e| matches!(e, ServerError::AuthenticationFailed)),
+        "Wrong password must fail authentication"
+    );
+}
+
+#[tokio::test]
+async fn test_acl_rules() {
+    let port = start_server_with(auth_settings()).await;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let admin = ClientBuilder::new()
+        .with_server_addr(addr)
+        .with_credentials("admin", "admin-pass")
+        .build()
+        .await
+        .unwrap();
+
+    admin.create_space("public_data".to_string()).await.unwrap();
+    admin.create_space("private".to_string()).await.unwrap();
+    admin
+        .space("public_data".to_string())
+        .await
+        .unwrap()
+        .set_string("key", "value")
+        .await
+        .unwrap();
+
+    let reader = ClientBuilder::new()
+        .with_server_addr(addr)
+        .with_credentials("reader", "reader-pass")
+        .build()
+        .await
+        .unwrap();
+
+    let public = reader.space("public_data".to_string()).await.unwrap();
+    assert_eq!(
+        public.get_string("key").await.unwrap(),
+        Some("value".to_string())
+    );
+
+    let denied = |e: &ServerError| matches!(e, ServerError::PermissionDenied(_));
+    assert!(is_server_error(
+        public.set_string("key", "other").await,
+        denied
+    ));
+    assert!(is_server_error(
+        reader.delete_space("public_data".to_string()).await,
+        denied
+    ));
+    assert!(is_server_error(
+        reader.space("private".to_string()).await,
+        denied
+    ));
+
+    // A full export only contains the spaces the user may read.
+    let exported = reader.export(None).await.unwrap();
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0].space, "public_data");
+    assert!(is_server_error(
+        reader.export(Some("private")).await,
+        denied
+    ));
+    assert!(is_server_error(
+        reader.import(exported, ImportOptions::default()).await,
+        denied
+    ));
+    let empty_import = Command::Import {
+        entries: Vec::new(),
+        options: ImportOptions {
+            dry_run: true,
+            ..ImportOptions::default()
+        },
+    };
+    assert!(matches!(
+        reader.execute(empty_import).await,
+        Ok(Response::Error(ServerError::PermissionDenied(_)))
+    ));
+}
+
+#[test]
+fn test_default_settings_match_config_defaults() {
+    let settings = Settings::default();
+
+    assert_eq!(settings.port, 25500);
+    assert_eq!(settings.shutdown_timeout_secs, 30);
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown_flushes_aof() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let aof_path = temp_dir.path().join("shutdown.rdb");
+    let port = find_free_port();
+
+    let settings = Settings {
+        host: "127.0.0.1".to_string(),
+        port,
+        aof_path: aof_path.to_string_lossy().to_string(),
+        shutdown_timeout_secs: 5,
+        ..Default::default()
+    };
+
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        red_db_server::run_server_until(settings, async {
+            let _ = shutdown_receiver.await;
+        })
+        .await
+        .map_err(|e| e.to_string())
+    });
+
+    assert!(
+        wait_for_port(port, 5 * 1000).await,
+        "Server failed to start"
+    );
+
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_max_pool_size(4)
+        .build()
+        .await
+        .unwrap();
+
+    client.create_space("durable".to_string()).await.unwrap();
+    let space = client.space("durable".to_string()).await.unwrap();
+    for i in 0..50 {
+        space.set(&format!("key{i}"), vec![i]).await.unwrap();
+    }
+
+    shutdown_sender.send(()).unwrap();
+    server.await.unwrap().expect("Server failed to shut down");
+
+    assert!(
+        client.create_space("too_late".to_string()).await.is_err(),
+        "Server must stop serving after shutdown"
+    );
+
+    let db = Db::new(aof_path).await;
+    let response = db
+        .execute(Command::ListKeys {
+            space: "durable".to_string(),
+        })
+        .await;
+
+    assert!(matches!(response, Response::Keys(keys) if keys.len() == 50));
+}
+
+async fn raw_connect(port: u16, protocol_version: u32) -> (TcpStream, Option<HelloReply>) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    stream.write_all(&PROTOCOL_MAGIC).await.unwrap();
+    write_frame(
+        &mut stream,
+        &Hello {
+            protocol_version,
+            client_name: "raw-test".to_string(),
+            features: Vec::new(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let reply = read_frame(&mut stream, MAX_RESPONSE_SIZE).await.unwrap();
+
+    (stream, reply)
+}
+
+#[tokio::test]
+async fn test_handshake_rejects_incompatible_version() {
+    let port = start_server().await;
+
+    let (mut stream, reply) = raw_connect(port, 0).await;
+    assert!(
+        matches!(reply, Some(HelloReply::Rejected { protocol_version, .. }) if protocol_version == PROTOCOL_VERSION)
+    );
+
+    let mut buf = [0u8; 1];
+    assert_eq!(
+        stream.read(&mut buf).await.unwrap_or(0),
+        0,
+        "Server must close the connection after rejecting"
+    );
+}
+
+#[tokio::test]
+async fn test_handshake_older_version() {
+    let port = start_server().await;
+
+    let (mut stream, reply) = raw_connect(port, 1).await;
+    assert!(matches!(
+        reply,
+        Some(HelloReply::Accepted {
+            protocol_version: 1,
+            ..
+        })
+    ));
+
+    let request = Request {
+        id: 1,
+        command: Command::ListSpaces,
+    };
+    write_frame(&mut stream, &request).await.unwrap();
+    let reply: Reply = read_frame(&mut stream, MAX_RESPONSE_SIZE)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(reply.response, Response::Spaces(_)));
+
+    // Version 1 has no `Ping`, so a peer that sends one breaks the protocol.
+    let request = Request {
+        id: 2,
+        command: Command::Ping {
+            payload: Vec::new(),
+        },
+    };
+    write_frame(&mut stream, &request).await.unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(
+        stream.read(&mut buf).await.unwrap_or(0),
+        0,
+        "Server must close connections that send commands newer than their version"
+    );
+}
+
+#[tokio::test]
+async fn test_errors_for_older_clients() {
+    let port = start_server().await;
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .await
+        .unwrap();
+    client
+        .create_space_with_config(
+            "frozen".to_string(),
+            SpaceConfig {
+                read_only: true,
+                ..SpaceConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    // `SpaceReadOnly` came with version 7, so a version 6 client gets the closest
+    // error it knows.
+    let (mut stream, _) = raw_connect(port, 6).await;
+    let request = Request {
+        id: 1,
+        command: Command::Set {
+            space: "frozen".to_string(),
+            key: "key".to_string(),
+            value: b"value".to_vec(),
+        },
+    };
+    write_frame(&mut stream, &request).await.unwrap();
+    let reply: Reply = read_frame(&mut stream, MAX_RESPONSE_SIZE)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        reply.response,
+        Response::Error(ServerError::PermissionDenied(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_handshake_rejects_unknown_peer() {
+    let port = start_server().await;
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    // A pre-handshake client starts straight away with a length-prefixed command.
+    write_frame(&mut stream, &Command::ListSpaces)
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; 1];
+    assert_eq!(
+        stream.read(&mut buf).await.unwrap_or(0),
+        0,
+        "Server must close connections that skip the handshake"
+    );
+}
+
+#[tokio::test]
+async fn test_pipelined_requests() {
+    let port = start_server().await;
+    let (mut stream, reply) = raw_connect(port, PROTOCOL_VERSION).await;
+    assert!(matches!(reply, Some(HelloReply::Accepted { .. })));
+
+    let requests = [
+        Command::CreateSpace {
+            space: "pipeline".to_string(),
+        },
+        Command::Set {
+            space: "pipeline".to_string(),
+            key: "key".to_string(),
+            value: b"value".to_vec(),
+        },
+        Command::Get {
+            space: "pipeline".to_string(),
+            key: "key".to_string(),
+        },
+        Command::ListSpaces,
+    ];
+
+    for (id, command) in requests.into_iter().enumerate() {
+        write_frame(
+            &mut stream,
+            &Request {
+                id: id as u64 + 100,
+                command,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let mut replies = Vec::new();
+    for _ in 0..4 {
+        let reply: Reply = read_frame(&mut stream, MAX_RESPONSE_SIZE)
+            .await
+            .unwrap()
+            .unwrap();
+        replies.push(reply);
+    }
+    replies.sort_by_key(|reply| reply.id);
+
+    let ids: Vec<u64> = replies.iter().map(|reply| reply.id).collect();
+    assert_eq!(ids, vec![100, 101, 102, 103]);
+    assert!(matches!(replies[0].response, Response::Ok));
+    assert!(matches!(replies[1].response, Response::Ok));
+    assert!(matches!(&replies[2].response, Response::Value(Some(v)) if v == b"value"));
+    assert!(matches!(&replies[3].response, Response::Spaces(s) if s == &["pipeline".to_string()]));
+}
+
+#[tokio::test]
+async fn test_pipelined_requests_keep_order() {
+    let port = start_server().await;
+    let (mut stream, _) = raw_connect(port, PROTOCOL_VERSION).await;
+
+    let get = || Command::Get {
+        space: "ordered".to_string(),
+        key: "key".to_string(),
+    };
+    let set = |value: u64| Command::Set {
+        space: "ordered".to_string(),
+        key: "key".to_string(),
+        value: value.to_be_bytes().to_vec(),
+    };
+
+    let mut requests = vec![
+        Command::CreateSpace {
+            space: "ordered".to_string(),
+        },
+        set(0),
+    ];
+    for i in 1..=100 {
+        requests.extend([get(), set(i)]);
+    }
+    requests.push(get());
+
+    let count = requests.len();
+    for (id, command) in requests.into_iter().enumerate() {
+        write_frame(
+            &mut stream,
+            &Request {
+                id: id as u64,
+                command,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // Request 2n is the read issued after writing n.
+    for _ in 0..count {
+        let reply: Reply = read_frame(&mut stream, MAX_RESPONSE_SIZE)
+            .await
+            .unwrap()
+            .unwrap();
+
+        if reply.id >= 2 && reply.id.is_multiple_of(2) {
+            let expected = (reply.id / 2 - 1).to_be_bytes().to_vec();
+            assert!(
+                matches!(&reply.response, Response::Value(Some(v)) if *v == expected),
+                "request {} got {:?}",
+                reply.id,
+                reply.response
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_multiplexed_connection() {
+    let port = start_server().await;
+
+    let connection = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build_multiplexed()
+        .await
+        .expect("Failed to open multiplexed connection");
+
+    let response = connection
+        .execute(Command::CreateSpace {
+            space: "shared".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(response, Response::Ok));
+
+    let mut tasks = Vec::new();
+    for i in 0..32u8 {
+        let connection = connection.clone();
+
+        tasks.push(tokio::spawn(async move {
+            let key = format!("key{i}");
+            connection
+                .execute(Command::Set {
+                    space: "shared".to_string(),
+                    key: key.clone(),
+                    value: vec![i],
+                })
+                .await
+                .unwrap();
+
+            connection
+                .execute(Command::Get {
+                    space: "shared".to_string(),
+                    key,
+                })
+                .await
+                .unwrap()
+        }));
+    }
+
+    for (i, task) in tasks.into_iter().enumerate() {
+        let response = task.await.unwrap();
+        assert!(matches!(response, Response::Value(Some(v)) if v == vec![i as u8]));
+    }
+}
+
+async fn start_resp_server(mut settings: Settings) -> (u16, BufReader<TcpStream>) {
+    let resp_port = find_free_port();
+    settings.resp = Some(RespSettings {
+        port: resp_port,
+        ..settings.resp.unwrap_or_default()
+    });
+
+    let port = start_server_with(settings).await;
+    assert!(
+        wait_for_port(resp_port, 5 * 1000).await,
+        "RESP listener failed to start"
+    );
+
+    let stream = TcpStream::connect(("127.0.0.1", resp_port)).await.unwrap();
+
+    (port, BufReader::new(stream))
+}
+
+async fn resp(conn: &mut BufReader<TcpStream>, args: &[&str]) -> String {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+    }
+    conn.get_mut().write_all(request.as_bytes()).await.unwrap();
+
+    read_resp_reply(conn).await
+}
+
+/// Renders a reply compactly: `+OK`, `-ERR ..`, `:1`, `"bulk"`, `nil`, `[a, b]`.
+fn read_resp_reply(
+    conn: &mut BufReader<TcpStream>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = String> + '_>> {
+    Box::pin(async move {
+        let mut line = String::new();
+        conn.read_line(&mut line).await.unwrap();
+        let line = line.trim_end();
+        let (kind, rest) = line.split_at(1);
+
+        match kind {
+            "+" | "-" | ":" => line.to_string(),
+            "_" => "nil".to_string(),
+            "$" if rest == "-1" => "nil".to_string(),
+            "$" => {
+                let mut data = vec![0u8; rest.parse::<usize>().unwrap() + 2];
+                conn.read_exact(&mut data).await.unwrap();
+                data.truncate(data.len() - 2);
+                format!("{:?}", String::from_utf8(data).unwrap())
+            }
+            "*" | "%" => {
+                let mut count = rest.parse::<usize>().unwrap();
+                if kind == "%" {
+                    count *= 2;
+                }
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    items.push(read_resp_reply(conn).await);
+                }
+                format!("[{}]", items.join(", "))
+            }
+            _ => panic!("Unexpected RESP reply: {line}"),
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_resp_commands() {
+    let (_, mut conn) = start_resp_server(Settings::default()).await;
+
+    assert_eq!(resp(&mut conn, &["PING"]).await, "+PONG");
+    assert_eq!(resp(&mut conn, &["ping", "hi"]).await, "\"hi\"");
+    assert_eq!(resp(&mut conn, &["GET", "foo"]).await, "nil");
+    assert_eq!(resp(&mut conn, &["SET", "foo", "bar"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["SET", "fizz", "buzz"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["GET", "foo"]).await, "\"bar\"");
+    assert_eq!(resp(&mut conn, &["KEYS", "fo?"]).await, "[\"foo\"]");
+    assert_eq!(resp(&mut conn, &["DEL", "foo", "missing"]).await, ":1");
+    assert_eq!(resp(&mut conn, &["GET", "foo"]).await, "nil");
+
+    assert_eq!(resp(&mut conn, &["SELECT", "1"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["GET", "fizz"]).await, "nil");
+    assert_eq!(resp(&mut conn, &["SELECT", "0"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["GET", "fizz"]).await, "\"buzz\"");
+
+    let info = resp(&mut conn, &["INFO", "keyspace"]).await;
+    assert!(info.contains("0:keys=1"), "Unexpected INFO: {info}");
+
+    let hello = resp(&mut conn, &["HELLO", "3"]).await;
+    assert!(hello.contains("\"proto\", :3"), "Unexpected HELLO: {hello}");
+    assert_eq!(resp(&mut conn, &["GET", "foo"]).await, "nil");
+
+    assert!(
+        resp(&mut conn, &["FLUSHALL"])
+            .await
+            .starts_with("-ERR unknown command")
+    );
+
+    conn.get_mut().write_all(b"PING\r\n").await.unwrap();
+    assert_eq!(read_resp_reply(&mut conn).await, "+PONG");
+}
+
+#[tokio::test]
+async fn test_resp_glob_patterns() {
+    let (_, mut conn) = start_resp_server(Settings::default()).await;
+
+    let long_key = "a".repeat(64);
+    assert_eq!(resp(&mut conn, &["SET", &long_key, "1"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["SET", "h*llo", "2"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["SET", "hallo", "3"]).await, "+OK");
+
+    // Would take exponential time with a backtracking matcher.
+    assert_eq!(
+        resp(&mut conn, &["KEYS", "a*a*a*a*a*a*a*a*a*a*b"]).await,
+        "[]"
+    );
+    assert_eq!(resp(&mut conn, &["KEYS", "h\\*llo"]).await, "[\"h*llo\"]");
+    assert_eq!(resp(&mut conn, &["KEYS", "h[^*]ll?"]).await, "[\"hallo\"]");
+    assert_eq!(
+        resp(&mut conn, &["KEYS", "*[a-b]"]).await,
+        format!("[{long_key:?}]")
+    );
+}
+
+#[tokio::test]
+async fn test_resp_rejects_malformed_commands() {
+    let (_, mut conn) = start_resp_server(Settings::default()).await;
+    let addr = conn.get_ref().peer_addr().unwrap();
+
+    // Empty arrays used to panic the connection handler, redis skips them.
+    conn.get_mut().write_all(b"*0\r\n*0\r\n").await.unwrap();
+    assert_eq!(resp(&mut conn, &["PING"]).await, "+PONG");
+
+    // Arguments may not add up to more than a native request.
+    let header = format!("*3\r\n$3\r\nSET\r\n$1\r\nk\r\n${MAX_REQUEST_SIZE}\r\n");
+    conn.get_mut().write_all(header.as_bytes()).await.unwrap();
+    let mut rest = Vec::new();
+    conn.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+
+    let mut conn = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    assert_eq!(resp(&mut conn, &["PING"]).await, "+PONG");
+}
+
+#[tokio::test]
+async fn test_resp_key_prefixes() {
+    let settings = Settings {
+        resp: Some(RespSettings {
+            key_separator: Some(":".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let (port, mut conn) = start_resp_server(settings).await;
+
+    assert_eq!(resp(&mut conn, &["SET", "users:1", "alice"]).await, "+OK");
+    assert_eq!(resp(&mut conn, &["KEYS", "users:*"]).await, "[\"users:1\"]");
+    assert!(resp(&mut conn, &["SELECT", "1"]).await.starts_with("-ERR"));
+
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .await
+        .unwrap();
+    let users = client.space("users".to_string()).await.unwrap();
+
+    assert_eq!(
+        users.get_string("1").await.unwrap(),
+        Some("alice".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_resp_auth() {
+    let (_, mut conn) = start_resp_server(auth_settings()).await;
+
+    assert!(
+        resp(&mut conn, &["GET", "foo"])
+            .await
+            .starts_with("-NOAUTH")
+    );
+    assert!(
+        resp(&mut conn, &["AUTH", "admin", "nope"])
+            .await
+            .starts_with("-WRONGPASS")
+    );
+    assert_eq!(
+        resp(&mut conn, &["AUTH", "reader", "reader-pass"]).await,
+        "+OK"
+    );
+    assert!(
+        resp(&mut conn, &["SET", "foo", "bar"])
+            .await
+            .starts_with("-NOPERM")
+    );
+    assert_eq!(
+        resp(&mut conn, &["AUTH", "admin", "admin-pass"]).await,
+        "+OK"
+    );
+    assert_eq!(resp(&mut conn, &["SET", "foo", "bar"]).await, "+OK");
+}
+
+async fn start_http_server(mut settings: Settings) -> u16 {
+    let http_port = find_free_port();
+    settings.http = Some(HttpSettings { port: http_port });
+
+    start_server_with(settings).await;
+    assert!(
+        wait_for_port(http_port, 5 * 1000).await,
+        "HTTP listener failed to start"
+    );
+
+    http_port
+}
+
+/// Sends one HTTP/1.1 request and returns the status code and body.
+async fn http(
+    port: u16,
+    method: &str,
+    path: &str,
+    body: &[u8],
+    credentials: Option<&str>,
+) -> (u16, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    let mut request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+        body.len()
+    );
+    if let Some(credentials) = credentials {
+        // Pre-encoded "user:password" pairs keep the test free of a base64 dependency.
+        request.push_str(&format!("Authorization: Basic {credentials}\r\n"));
+    }
+    request.push_str("\r\n");
+
+    stream.write_all(request.as_bytes()).await.unwrap();
+    stream.write_all(body).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+
+    let status = response[9..12].parse().unwrap();
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .unwrap_or_default();
+
+    (status, body)
+}
+
+#[tokio::test]
+async fn test_http_gateway() {
+    let port = start_http_server(Settings::default()).await;
+
+    assert_eq!(http(port, "POST", "/spaces/docs", b"", None).await.0, 201);
+
+    let (status, body) = http(port, "POST", "/spaces/docs", b"", None).await;
+    assert_eq!(status, 409);
+    assert!(body.contains("\"code\":\"space_already_exists\""), "{body}");
+
+    let (status, body) = http(port, "GET", "/spaces", b"", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "{\"spaces\":[\"docs\"]}");
+
+    assert_eq!(
+        http(port, "PUT", "/spaces/docs/keys/readme", b"hello", None)
+            .await
+            .0,
+        204
+    );
+    assert_eq!(
+        http(port, "GET", "/spaces/docs/keys/readme", b"", None).await,
+        (200, "hello".to_string())
+    );
+    assert_eq!(
+        http(port, "GET", "/spaces/docs/keys", b"", None).await,
+        (200, "{\"keys\":[\"readme\"]}".to_string())
+    );
+
+    assert_eq!(
+        http(port, "DELETE", "/spaces/docs/keys/readme", b"", None)
+            .await
+            .0,
+        204
+    );
+    let (status, body) = http(port, "GET", "/spaces/docs/keys/readme", b"", None).await;
+    assert_eq!(status, 404);
+    assert!(body.contains("\"code\":\"key_not_found\""), "{body}");
+
+    let (status, body) = http(port, "GET", "/spaces/missing/keys/readme", b"", None).await;
+    assert_eq!(status, 404);
+    assert!(body.contains("\"code\":\"space_not_found\""), "{body}");
+
+    assert_eq!(http(port, "DELETE", "/spaces/docs", b"", None).await.0, 204);
+}
+
+#[tokio::test]
+async fn test_http_gateway_auth() {
+    let port = start_http_server(auth_settings()).await;
+
+    // "reader:reader-pass" and "admin:admin-pass"
+    let reader = Some("cmVhZGVyOnJlYWRlci1wYXNz");
+    let admin = Some("YWRtaW46YWRtaW4tcGFzcw==");
+
+    assert_eq!(http(port, "GET", "/spaces", b"", None).await.0, 401);
+    assert_eq!(
+        http(port, "POST", "/spaces/public_x", b"", reader).await.0,
+        403
+    );
+    assert_eq!(
+        http(port, "POST", "/spaces/public_x", b"", admin).await.0,
+        201
+    );
+    assert_eq!(
+        http(port, "GET", "/spaces", b"", reader).await,
+        (200, "{\"spaces\":[\"public_x\"]}".to_string())
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket() {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let socket_dir = tempdir().expect("Failed to create temp dir");
+    let socket_path = socket_dir.path().join("red-db.sock");
+
+    start_server_with(Settings {
+        unix_socket: Some(UnixSocketSettings {
+            path: socket_path.to_string_lossy().to_string(),
+            mode: 0o600,
+        }),
+        ..auth_settings()
+    })
+    .await;
+
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    let metadata = std::fs::metadata(&socket_path).expect("Socket file was not created");
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+    round_trip(
+        ClientBuilder::new()
+            .with_unix_socket(&socket_path)
+            .with_credentials("admin", "admin-pass"),
+    )
+    .await
+    .expect("Round trip over the Unix socket failed");
+
+    let connection = ClientBuilder::new()
+        .with_unix_socket(&socket_path)
+        .with_credentials("admin", "admin-pass")
+        .build_multiplexed()
+        .await
+        .expect("Failed to open multiplexed connection");
+
+    let response = connection
+        .execute(Command::Get {
+            space: "tls_space".to_string(),
+            key: "key".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(response, Response::Value(Some(v)) if v == b"secret"));
+
+    // Authentication still applies on top of the socket permissions.
+    let client = ClientBuilder::new()
+        .with_unix_socket(&socket_path)
+        .build()
+        .await
+        .unwrap();
+    let result = client.create_space("public_data".to_string()).await;
+    assert!(is_server_error(result, |e| matches!(
+        e,
+        ServerError::PermissionDenied(_)
+    )));
+}
+
+#[tokio::test]
+async fn test_client_reconnects_after_server_restart() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let port = find_free_port();
+
+    let settings = || Settings {
+        host: "127.0.0.1".to_string(),
+        port,
+        aof_path: temp_dir
+            .path()
+            .join("restart.rdb")
+            .to_string_lossy()
+            .to_string(),
+        shutdown_timeout_secs: 5,
+        ..Default::default()
+    };
+
+    let start = |settings: Settings| {
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            red_db_server::run_server_until(settings, async {
+                let _ = shutdown_receiver.await;
+            })
+            .await
+            .map_err(|e| e.to_string())
+        });
+        (shutdown_sender, server)
+    };
+
+    let (shutdown_sender, server) = start(settings());
+    assert!(
+        wait_for_port(port, 5 * 1000).await,
+        "Server failed to start"
+    );
+
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_request_timeout(Duration::from_secs(1))
+        .build()
+        .await
+        .unwrap();
+
+    client.create_space("restart".to_string()).await.unwrap();
+    let space = client.space("restart".to_string()).await.unwrap();
+    space.set_string("key", "value").await.unwrap();
+
+    let checked_client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_health_check_interval(Duration::ZERO)
+        .with_retry_policy(RetryPolicy::none())
+        .build()
+        .await
+        .unwrap();
+    checked_client.ping().await.unwrap();
+
+    shutdown_sender.send(()).unwrap();
+    server.await.unwrap().expect("Server failed to shut down");
+
+    let (shutdown_sender, server) = start(settings());
+    assert!(
+        wait_for_port(port, 5 * 1000).await,
+        "Server failed to restart"
+    );
+
+    // The pooled connection is dead; the read is retried on a fresh one.
+    assert_eq!(
+        space.get_string("key").await.unwrap(),
+        Some("value".to_string())
+    );
+
+    // Without retries, the health check replaces the dead connection before it is used.
+    assert!(
+        checked_client
+            .is_space_exists("restart".to_string())
+            .await
+            .unwrap()
+    );
+
+    shutdown_sender.send(()).unwrap();
+    server.await.unwrap().expect("Server failed to shut down");
+}
+
+#[tokio::test]
+async fn test_typed_space() {
+    let port = start_server().await;
+
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .await
+        .unwrap();
+
+    client.create_space("scores".to_string()).await.unwrap();
+    let scores = client
+        .typed_space::<String, Vec<u32>>("scores")
+        .await
+        .unwrap();
+
+    scores.set(&"alice".to_string(), &vec![3, 5]).await.unwrap();
+    assert_eq!(
+        scores.get(&"alice".to_string()).await.unwrap(),
+        Some(vec![3, 5])
+    );
+    assert_eq!(
+        scores.scan().await.unwrap(),
+        vec![("alice".to_string(), vec![3, 5])]
+    );
+}
+
+#[tokio::test]
+async fn test_blocking_client() {
+    let port = start_server().await;
+
+    tokio::task::spawn_blocking(move || {
+        let client = ClientBuilder::new()
+            .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+            .build_blocking()
+            .unwrap();
+
+        client.create_space("blocking".to_string()).unwrap();
+        let space = client.space("blocking".to_string()).unwrap();
+        space.set_string("key", "value").unwrap();
+
+        assert_eq!(space.get_string("key").unwrap(), Some("value".to_string()));
+        assert_eq!(space.list_keys().unwrap(), vec!["key".to_string()]);
+    })
+    .await
+    .unwrap();
+}
+
+struct ChildGuard(std::process::Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[tokio::test]
+async fn test_backup_restore_under_concurrent_writes() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let port = start_server_with(Settings {
+        backup_dir: Some(dir.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .await;
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .await
+        .unwrap();
+    client.create_space("counters".to_string()).await.unwrap();
+
+    // Keys are written one after another, so any consistent snapshot holds a prefix of them.
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = tokio::spawn({
+        let space = client.space("counters".to_string()).await.unwrap();
+        let stop = stop.clone();
+        async move {
+            let mut written = 0;
+            while !stop.load(Ordering::Relaxed) {
+                space
+                    .set_string(&format!("key:{written:06}"), "value")
+                    .await
+                    .unwrap();
+                written += 1;
+            }
+            written
+        }
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let archive = dir.path().join("nightly.rdbak");
+    let metadata = client.backup("nightly.rdbak").await.expect("Backup failed");
+
+    sleep(Duration::from_millis(50)).await;
+    stop.store(true, Ordering::Relaxed);
+    let written = writer.await.unwrap();
+
+    assert_eq!(metadata.spaces, vec!["counters".to_string()]);
+    assert!(metadata.keys > 0 && metadata.keys <= written);
+
+    let restored_port = find_free_port();
+    std::fs::write(
+        dir.path().join("config.toml"),
+        format!(
+            "host = \"127.0.0.1\"\nport = {restored_port}\naof_path = {:?}\n",
+            dir.path().join("restored.rdb").to_string_lossy()
+        ),
+    )
+    .unwrap();
+
+    let _server = ChildGuard(
+        std::process::Command::new(env!("CARGO_BIN_EXE_red-db-server"))
+            .current_dir(dir.path())
+            .arg("--restore")
+            .arg(&archive)
+            .spawn()
+            .expect("Failed to start red-db-server"),
+    );
+    assert!(
+        wait_for_port(restored_port, 10 * 1000).await,
+        "Restored server failed to start"
+    );
+
+    let restored = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], restored_port)))
+        .build()
+        .await
+        .unwrap();
+
+    let keys: Vec<String> = restored
+        .export(Some("counters"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.key)
+        .collect();
+    let expected: Vec<String> = (0..metadata.keys).map(|i| format!("key:{i:06}")).collect();
+    assert_eq!(keys, expected);
+
+    // A damaged archive is rejected before the server starts.
+    let mut damaged = std::fs::read(&archive).unwrap();
+    let last = damaged.len() - 1;
+    damaged[last] ^= 0xff;
+    std::fs::write(&archive, damaged).unwrap();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_red-db-server"))
+        .current_dir(dir.path())
+        .arg("--restore")
+        .arg(&archive)
+        .output()
+        .unwrap();
+    assert!(!status.status.success());
+    assert!(String::from_utf8_lossy(&status.stderr).contains("Checksum mismatch"));
+}
+
+#[tokio::test]
+async fn test_backup_paths_are_confined() {
+    // Without a backup directory, clients can't have the server write files.
+    let disabled = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], start_server().await)))
+        .build()
+        .await
+        .unwrap();
+    assert!(is_server_error(
+        disabled.backup("nightly.rdbak").await,
+        |e| matches!(e, ServerError::BackupFailed(_))
+    ));
+
+    let dir = tempdir().expect("Failed to create temp dir");
+    let backup_dir = dir.path().join("backups");
+    std::fs::create_dir(&backup_dir).unwrap();
+    let port = start_server_with(Settings {
+        backup_dir: Some(backup_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+    .await;
+    let client = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .await
+        .unwrap();
+
+    let outside = dir.path().join("outside.rdbak");
+    let mut rejected = vec![
+        "../outside.rdbak".to_string(),
+        outside.to_string_lossy().into_owned(),
+        "missing/nightly.rdbak".to_string(),
+        String::new(),
+    ];
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.path(), backup_dir.join("escape")).unwrap();
+        rejected.push("escape/outside.rdbak".to_string());
+    }
+    for dest_path in rejected {
+        assert!(
+            is_server_error(client.backup(dest_path.clone()).await, |e| matches!(
+                e,
+                ServerError::InvalidBackupPath(_)
+            )),
+            "{dest_path} should be rejected"
+        );
+    }
+    assert!(!outside.exists());
+
+    std::fs::create_dir(backup_dir.join("daily")).unwrap();
+    client.backup("daily/monday.rdbak").await.unwrap();
+    assert!(backup_dir.join("daily/monday.rdbak").exists());
+}
+
+#[tokio::test]
+async fn test_max_memory() {
+    for (eviction_policy, evicts) in [
+        (EvictionPolicy::AllkeysLru, true),
+        (EvictionPolicy::Noeviction, false),
+    ] {
+        let port = start_server_with(Settings {
+            max_memory: Some(4_000),
+            eviction_policy,
+            ..Default::default()
+        })
+        .await;
+
+        let client = ClientBuilder::new()
+            .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+            .build()
+            .await
+            .unwrap();
+        client.create_space("cache".to_string()).await.unwrap();
+        let space = client.space("cache".to_string()).await.unwrap();
+
+        let mut rejected = 0;
+        for i in 0..100 {
+            match space.set(&format!("key{i}"), vec![0; 100]).await {
+                Ok(()) => {}
+                Err(ClientError::Server(ServerError::OutOfMemory)) => rejected += 1,
+                Err(e) => panic!("Unexpected error: {e}"),
+            }
+        }
+
+        assert_eq!(rejected == 0, evicts);
+        assert!(space.list_keys().await.unwrap().len() < 30);
+        assert_eq!(space.get("key99").await.unwrap().is_some(), evicts);
+    }
+}
+
+#[tokio::test]
+async fn test_info() {
+    let port = start_server_with(auth_settings()).await;
+
+    let admin = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_credentials("admin", "admin-pass")
+        .build()
+        .await
+        .unwrap();
+    for space in ["public_data", "private_data"] {
+        admin.create_space(space.to_string()).await.unwrap();
+        let space = admin.space(space.to_string()).await.unwrap();
+        space.set_string("key", "value").await.unwrap();
+    }
+
+    let info = admin.info(None).await.unwrap();
+    assert_eq!(info.server.unwrap().version, env!("CARGO_PKG_VERSION"));
+    assert!(info.clients.unwrap().connected >= 1);
+    let stats = info.stats.unwrap();
+    assert!(stats.commands.contains(&("set".to_string(), 2)));
+    assert!(info.persistence.unwrap().last_write_error.is_none());
+    assert_eq!(info.spaces.unwrap().len(), 2);
+
+    let reader = ClientBuilder::new()
+        .with_server_addr(SocketAddr::from(([127, 0, 0, 1], port)))
+        .with_credentials("reader", "reader-pass")
+        .build()
+        .await
+        .unwrap();
+    let spaces = reader
+        .info(Some(InfoSection::Spaces))
+        .await
+        .unwrap()
+        .spaces
+        .unwrap();
+    assert_eq!(spaces.len(), 1);
+    assert_eq!(spaces[0].name, "public_data");
+    assert_eq!(spaces[0].usage.keys, 1);
+
+    assert_eq!(
+        reader
+            .space_info("public_data".to_string())
+            .await
+            .unwrap()
+            .usage
+            .bytes,
+        8
+    );
+    assert!(is_server_error(
+        reader.space_info("private_data".to_string()).await,
+        |e| matches!(e, ServerError::PermissionDenied(_))
+    ));
+}